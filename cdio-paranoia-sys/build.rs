@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
 fn main() {
+    println!("cargo:rustc-link-lib=cdio");
     println!("cargo:rustc-link-lib=cdio_cdda");
     println!("cargo:rustc-link-lib=cdio_paranoia");
     println!("cargo:rerun-if-changed=wrapper.h");
@@ -1,2 +1,3 @@
 #include <cdio/paranoia/cdda.h>
-#include <cdio/paranoia/paranoia.h>
\ No newline at end of file
+#include <cdio/paranoia/paranoia.h>
+#include <cdio/mmc.h>
\ No newline at end of file
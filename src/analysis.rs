@@ -0,0 +1,687 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Drive caching and timing analysis, like `cdparanoia -A`.
+//!
+//! Paranoia verifies data by reading it more than once. This only works if
+//! the second read actually comes from the disc and not from the cache of the
+//! drive. [`analyze()`] finds out how a drive behaves by timing raw reads:
+//! a read that is served from the cache is much faster than one that has to
+//! seek and read the disc. The result is a [`Report`].
+//!
+//! [`SimulatedDrive`] is a [`CdBackend`] with a configurable [`CacheModel`]
+//! and a [`SimulatedClock`], so the analysis can be tested without hardware.
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::analysis::{self, CacheDefeat, CacheModel, SimulatedDrive};
+//!
+//! let mut drive = SimulatedDrive::new(75 * 60 * 60).with_cache(CacheModel {
+//!     sectors: 1000,
+//!     read_ahead: 100,
+//! });
+//! let clock = drive.clock();
+//! let report = analysis::analyze_with_clock(&mut drive, &clock)?;
+//!
+//! assert!(report.cached);
+//! // Read-ahead sectors take up some of the space in the cache.
+//! let cache_sectors = report.cache_sectors.unwrap();
+//! assert!((900..1000).contains(&cache_sectors));
+//! assert_eq!(
+//!     report.cache_defeat,
+//!     CacheDefeat::ReadElsewhere { sectors: cache_sectors + 1 }
+//! );
+//! assert!(report.accurate_stream);
+//!
+//! let mut drive = SimulatedDrive::new(75 * 60 * 60).with_jitter(3, 42);
+//! let clock = drive.clock();
+//! let report = analysis::analyze_with_clock(&mut drive, &clock)?;
+//!
+//! assert!(!report.cached);
+//! assert_eq!(report.cache_defeat, CacheDefeat::NotNeeded);
+//! assert!(!report.accurate_stream);
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::{
+    fmt::{self, Display},
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{fault::Rng, CdBackend, Error, Result, SECTOR_BYTES, SECTOR_WORDS};
+
+/// Number of sectors per second of audio at single speed.
+const SECTORS_PER_SECOND: u32 = 75;
+/// Number of sectors per raw read, like cdparanoia.
+const READ_SECTORS: usize = 26;
+/// Number of long seeks that are averaged.
+const SEEK_PROBES: u32 = 6;
+/// Number of sectors that are read sequentially to measure the read time.
+const SEQUENTIAL_SECTORS: u32 = 150;
+/// Distance of the backward seek that measures an uncached read.
+const BACKSTEP_SECTORS: u32 = 64;
+/// The largest cache that is measured.
+pub const MAX_CACHE_SECTORS: u32 = 4096;
+/// Distance between two probes, so read-ahead doesn't reach the next one.
+const PROBE_GAP: u32 = MAX_CACHE_SECTORS / 4;
+/// Number of overlapping reads that are compared for the accurate-stream test.
+const ACCURACY_PROBES: u32 = 4;
+/// Number of sectors per read in the accurate-stream test.
+const ACCURACY_SECTORS: u32 = 2 * READ_SECTORS as u32;
+
+/// A source of timestamps for [`analyze_with_clock()`].
+pub trait Clock {
+    /// Get the time that has passed since an arbitrary, fixed point.
+    fn now(&self) -> Duration;
+}
+
+/// A [`Clock`] that measures real time.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock(Instant);
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self(Instant::now())
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// A [`Clock`] that only advances when a [`SimulatedDrive`] reads.
+///
+/// Clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedClock(Arc<AtomicU64>);
+
+impl SimulatedClock {
+    fn advance(&self, duration: Duration) {
+        let nanos = duration.as_nanos().try_into().unwrap_or(u64::MAX);
+        self.0.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// How a drive analysis measured a drive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The average time of a long seek, including the read of one sector.
+    pub seek_time: Duration,
+    /// The time to read one sector during a sequential read.
+    pub read_time: Duration,
+    /// Whether a sector that has just been read is served from the cache
+    /// when it is read again.
+    pub cached: bool,
+    /// The number of previously read sectors that the drive retains,
+    /// if it caches at all.
+    ///
+    /// Caches larger than [`MAX_CACHE_SECTORS`] are reported as that size.
+    pub cache_sectors: Option<u32>,
+    /// How to make sure that a read comes from the disc.
+    pub cache_defeat: CacheDefeat,
+    /// Whether overlapping reads after a seek return exactly the same data.
+    ///
+    /// Drives without an accurate stream need paranoia's jitter correction.
+    pub accurate_stream: bool,
+}
+
+/// A strategy to force a drive to read from the disc instead of its cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheDefeat {
+    /// The drive doesn't cache previously read sectors.
+    NotNeeded,
+    /// Reading this many sectors at another position evicts the cache.
+    ReadElsewhere { sectors: u32 },
+    /// No strategy was found, verification can't be trusted.
+    Failed,
+}
+
+impl Report {
+    /// Get the sequential read speed as a multiple of single speed.
+    pub fn read_speed(&self) -> f64 {
+        1.0 / (self.read_time.as_secs_f64() * f64::from(SECTORS_PER_SECOND))
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Drive analysis:")?;
+        writeln!(f, "  seek time:        {:.1} ms", millis(self.seek_time))?;
+        writeln!(
+            f,
+            "  read time:        {:.2} ms per sector ({:.1}x)",
+            millis(self.read_time),
+            self.read_speed()
+        )?;
+        match self.cache_sectors {
+            Some(sectors) => writeln!(
+                f,
+                "  cache:            {sectors} sectors ({} KiB)",
+                sectors as usize * SECTOR_BYTES / 1024
+            )?,
+            None => writeln!(f, "  cache:            none detected")?,
+        }
+        match self.cache_defeat {
+            CacheDefeat::NotNeeded => writeln!(f, "  cache defeat:     not needed")?,
+            CacheDefeat::ReadElsewhere { sectors } => {
+                writeln!(f, "  cache defeat:     read {sectors} sectors elsewhere")?
+            }
+            CacheDefeat::Failed => writeln!(f, "  cache defeat:     FAILED")?,
+        }
+        writeln!(
+            f,
+            "  accurate stream:  {}",
+            if self.accurate_stream { "yes" } else { "no" }
+        )
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Analyze a drive using the real time.
+///
+/// This reads a few thousand sectors spread across the whole disc and
+/// takes a while on a physical drive.
+pub fn analyze<B: CdBackend + ?Sized>(backend: &mut B) -> Result<Report> {
+    analyze_with_clock(backend, &SystemClock::new())
+}
+
+/// Analyze a drive, measuring time with the given clock.
+///
+/// The disc should be at least ten minutes long, on shorter discs the
+/// probes come closer to each other and may influence each other. Probes
+/// that don't fit on the disc read the whole disc instead.
+pub fn analyze_with_clock<B, C>(backend: &mut B, clock: &C) -> Result<Report>
+where
+    B: CdBackend + ?Sized,
+    C: Clock + ?Sized,
+{
+    let first = backend.disc_first_sector()?;
+    let last = backend.disc_last_sector()?;
+    let mut probe = Probe {
+        backend,
+        clock,
+        first,
+        last,
+        cursor: first + (last - first) / 4,
+        threshold: Duration::ZERO,
+    };
+
+    let seek_time = probe.seek_time()?;
+    let read_time = probe.read_time()?;
+    probe.threshold = probe.uncached_time()? / 2;
+
+    let cached = probe.retained(1)? && probe.retained(1)?;
+    let cache_sectors = if cached {
+        Some(probe.cache_sectors()?)
+    } else {
+        None
+    };
+    let cache_defeat = match cache_sectors {
+        Some(sectors) => probe.cache_defeat(sectors)?,
+        None => CacheDefeat::NotNeeded,
+    };
+    let accurate_stream = probe.accurate_stream(cache_defeat)?;
+
+    Ok(Report {
+        seek_time,
+        read_time,
+        cached,
+        cache_sectors,
+        cache_defeat,
+        accurate_stream,
+    })
+}
+
+struct Probe<'a, B: ?Sized, C: ?Sized> {
+    backend: &'a mut B,
+    clock: &'a C,
+    first: u32,
+    last: u32,
+    /// The start of the next fresh range.
+    cursor: u32,
+    /// Reads faster than this are served from the cache.
+    threshold: Duration,
+}
+
+impl<B: CdBackend + ?Sized, C: Clock + ?Sized> Probe<'_, B, C> {
+    /// Read sectors and measure the time it took.
+    fn read(&mut self, lsn: u32, sectors: u32) -> Result<(Duration, Vec<i16>)> {
+        let sectors = sectors as usize;
+        let mut data = vec![0; sectors * SECTOR_WORDS];
+        let start = self.clock.now();
+        let mut done = 0;
+        while done < sectors {
+            let end = (done + READ_SECTORS).min(sectors);
+            let read = self.backend.read_raw(
+                lsn + done as u32,
+                &mut data[done * SECTOR_WORDS..end * SECTOR_WORDS],
+            )?;
+            if read == 0 {
+                return Err(Error::Read);
+            }
+            done += read;
+        }
+        Ok((self.clock.now().saturating_sub(start), data))
+    }
+    fn time(&mut self, lsn: u32, sectors: u32) -> Result<Duration> {
+        Ok(self.read(lsn, sectors)?.0)
+    }
+    fn is_hit(&self, time: Duration) -> bool {
+        time < self.threshold
+    }
+    fn disc_sectors(&self) -> u32 {
+        self.last - self.first + 1
+    }
+    /// Get a range of sectors that hasn't been read before.
+    ///
+    /// Returns the first sector and the length of the range, which is
+    /// shorter than `sectors` if the disc is.
+    fn fresh(&mut self, sectors: u32) -> (u32, u32) {
+        let sectors = sectors.min(self.disc_sectors());
+        if self.cursor + sectors > self.last + 1 {
+            self.cursor = self.first;
+        }
+        let lsn = self.cursor;
+        self.cursor += sectors + PROBE_GAP;
+        (lsn, sectors)
+    }
+    /// Measure seeks back and forth across the disc.
+    fn seek_time(&mut self) -> Result<Duration> {
+        let step = (self.last - self.first) / 16;
+        let mut total = Duration::ZERO;
+        for i in 0..SEEK_PROBES {
+            let lsn = if i % 2 == 0 {
+                self.first + i * step
+            } else {
+                self.last - i * step
+            };
+            total += self.time(lsn, 1)?;
+        }
+        Ok(total / SEEK_PROBES)
+    }
+    /// Measure the time per sector of a sequential read.
+    fn read_time(&mut self) -> Result<Duration> {
+        let (lsn, sectors) = self.fresh(SEQUENTIAL_SECTORS + 1);
+        self.time(lsn, 1)?;
+        Ok(self.time(lsn + 1, sectors - 1)? / (sectors - 1).max(1))
+    }
+    /// Measure a short seek to a sector that can't be in the cache.
+    ///
+    /// Drives only read ahead, so a sector shortly before the last read
+    /// one is uncached if it hasn't been read before.
+    fn uncached_time(&mut self) -> Result<Duration> {
+        let mut fastest = Duration::MAX;
+        for _ in 0..3 {
+            let (lsn, sectors) = self.fresh(BACKSTEP_SECTORS + 1);
+            self.time(lsn + sectors - 1, 1)?;
+            fastest = fastest.min(self.time(lsn, 1)?);
+        }
+        Ok(fastest)
+    }
+    /// Check if the first of `sectors` read sectors is still cached.
+    fn retained(&mut self, sectors: u32) -> Result<bool> {
+        let (lsn, sectors) = self.fresh(sectors);
+        self.time(lsn, sectors)?;
+        let time = self.time(lsn, 1)?;
+        Ok(self.is_hit(time))
+    }
+    /// Find the largest number of sectors that is retained.
+    ///
+    /// This is at most the length of the disc.
+    fn cache_sectors(&mut self) -> Result<u32> {
+        let limit = MAX_CACHE_SECTORS.min(self.disc_sectors());
+        let mut retained = 1;
+        let mut evicted = None;
+        while retained < limit {
+            let sectors = (2 * retained).min(limit);
+            if !self.retained(sectors)? {
+                evicted = Some(sectors);
+                break;
+            }
+            retained = sectors;
+        }
+        if let Some(mut evicted) = evicted {
+            while evicted - retained > 1 {
+                let sectors = retained + (evicted - retained) / 2;
+                if self.retained(sectors)? {
+                    retained = sectors;
+                } else {
+                    evicted = sectors;
+                }
+            }
+        }
+        Ok(retained)
+    }
+    /// Find out how many sectors need to be read elsewhere to evict a sector.
+    fn cache_defeat(&mut self, cache_sectors: u32) -> Result<CacheDefeat> {
+        for factor in [1, 2, 4] {
+            let (lsn, _) = self.fresh(1);
+            self.time(lsn, 1)?;
+            let (elsewhere, sectors) = self.fresh(factor * (cache_sectors + 1));
+            self.time(elsewhere, sectors)?;
+            let time = self.time(lsn, 1)?;
+            if !self.is_hit(time) {
+                return Ok(CacheDefeat::ReadElsewhere { sectors });
+            }
+        }
+        Ok(CacheDefeat::Failed)
+    }
+    /// Compare overlapping reads that start with a seek.
+    fn accurate_stream(&mut self, cache_defeat: CacheDefeat) -> Result<bool> {
+        for _ in 0..ACCURACY_PROBES {
+            // two reads that overlap by half of their length
+            let (lsn, sectors) = self.fresh(ACCURACY_SECTORS + ACCURACY_SECTORS / 2);
+            let offset = sectors / 3;
+            let (_, first) = self.read(lsn, sectors - offset)?;
+            if let CacheDefeat::ReadElsewhere { sectors } = cache_defeat {
+                let (elsewhere, sectors) = self.fresh(sectors);
+                self.time(elsewhere, sectors)?;
+            }
+            let (_, second) = self.read(lsn + offset, sectors - offset)?;
+            let words = (sectors - 2 * offset) as usize * SECTOR_WORDS;
+            if first[offset as usize * SECTOR_WORDS..] != second[..words] {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The cache behavior of a [`SimulatedDrive`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheModel {
+    /// The size of the cache in sectors, 0 disables it.
+    pub sectors: u32,
+    /// The number of sectors the drive reads beyond each read.
+    ///
+    /// Read-ahead sectors share the cache with previously read ones.
+    pub read_ahead: u32,
+}
+
+/// The timing of a [`SimulatedDrive`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// The time of every seek.
+    pub seek: Duration,
+    /// The additional time of a seek across the whole disc, shorter seeks
+    /// take proportionally less.
+    pub full_stroke: Duration,
+    /// The time to read a sector from the disc.
+    pub media: Duration,
+    /// The time to read a sector from the cache.
+    pub cache: Duration,
+}
+
+impl Default for Timing {
+    /// A typical 8x drive.
+    fn default() -> Self {
+        Self {
+            seek: Duration::from_millis(20),
+            full_stroke: Duration::from_millis(80),
+            media: Duration::from_secs(1) / (8 * SECTORS_PER_SECOND),
+            cache: Duration::from_micros(50),
+        }
+    }
+}
+
+/// A simulated drive with a single audio track of generated noise.
+///
+/// Reads advance the [`SimulatedClock`] according to the [`Timing`] and the
+/// [`CacheModel`] instead of taking real time.
+#[derive(Debug, Clone)]
+pub struct SimulatedDrive {
+    sectors: u32,
+    cache: CacheModel,
+    timing: Timing,
+    jitter: u32,
+    rng: Rng,
+    clock: SimulatedClock,
+    /// The sector under the read head.
+    head: u32,
+    /// The first sector of the current sequential read.
+    run_start: u32,
+    cached: Range<u32>,
+    /// The first cached sector that was read ahead and hasn't been
+    /// transferred yet.
+    read_ahead: u32,
+}
+
+impl SimulatedDrive {
+    /// Create a drive with a disc of the given length, without cache,
+    /// jitter or read-ahead.
+    pub fn new(sectors: u32) -> Self {
+        Self {
+            sectors,
+            cache: CacheModel::default(),
+            timing: Timing::default(),
+            jitter: 0,
+            rng: Rng(0),
+            clock: SimulatedClock::default(),
+            head: 0,
+            run_start: 0,
+            cached: 0..0,
+            read_ahead: 0,
+        }
+    }
+    /// Set the cache model.
+    pub fn with_cache(mut self, cache: CacheModel) -> Self {
+        self.cache = cache;
+        self
+    }
+    /// Set the timing.
+    pub fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+    /// Shift every read that starts with a seek by a random number of stereo
+    /// frames in the range `-max_frames..=max_frames`.
+    pub fn with_jitter(mut self, max_frames: u32, seed: u64) -> Self {
+        self.jitter = max_frames;
+        self.rng = Rng(seed);
+        self
+    }
+    /// Get the clock that is advanced by reads.
+    pub fn clock(&self) -> SimulatedClock {
+        self.clock.clone()
+    }
+    /// Get the correct audio data of a sample, by its index on the disc.
+    fn sample(&self, index: i64) -> i16 {
+        if index < 0 || index >= i64::from(self.sectors) * SECTOR_WORDS as i64 {
+            return 0;
+        }
+        Rng(index as u64).next_u64() as i16
+    }
+    fn seek_time(&self, lsn: u32) -> Duration {
+        let distance = self.head.abs_diff(lsn);
+        self.timing.seek
+            + self
+                .timing
+                .full_stroke
+                .mul_f64(f64::from(distance) / f64::from(self.sectors))
+    }
+}
+
+impl CdBackend for SimulatedDrive {
+    fn tracks(&self) -> u8 {
+        1
+    }
+    fn track_first_sector(&self, track: u8) -> Result<u32> {
+        match track {
+            1 => Ok(0),
+            _ => Err(crate::ParanoiaError::InvalidTrackNumber.into()),
+        }
+    }
+    fn track_last_sector(&self, track: u8) -> Result<u32> {
+        match track {
+            1 => Ok(self.sectors - 1),
+            _ => Err(crate::ParanoiaError::InvalidTrackNumber.into()),
+        }
+    }
+    fn track_channels(&self, track: u8) -> Option<u8> {
+        (track == 1).then_some(2)
+    }
+    fn track_audio(&self, track: u8) -> bool {
+        track == 1
+    }
+    fn track_copy_permitted(&self, _track: u8) -> bool {
+        false
+    }
+    fn track_linear_preemphasis(&self, _track: u8) -> bool {
+        false
+    }
+    fn read_raw(&mut self, first_lsn: u32, buf: &mut [i16]) -> Result<usize> {
+        let sectors =
+            (buf.len() / SECTOR_WORDS).min(self.sectors.saturating_sub(first_lsn) as usize);
+        if sectors == 0 {
+            return Err(crate::ParanoiaError::UnaddressableSector.into());
+        }
+
+        let mut elapsed = Duration::ZERO;
+        let mut media = false;
+        let mut shift = 0;
+        for lsn in first_lsn..first_lsn + sectors as u32 {
+            if self.cached.contains(&lsn) {
+                // Read-ahead happens at media speed, so a sequential read
+                // can't be faster than that.
+                elapsed += if lsn >= self.read_ahead {
+                    self.timing.media
+                } else {
+                    self.timing.cache
+                };
+                self.read_ahead = self.read_ahead.max(lsn + 1);
+                continue;
+            }
+            if lsn != self.head {
+                elapsed += self.seek_time(lsn);
+                self.run_start = lsn;
+                if lsn == first_lsn && self.jitter > 0 {
+                    let max_frames = i64::from(self.jitter);
+                    shift = 2 * self.rng.range(-max_frames, max_frames);
+                }
+            }
+            elapsed += self.timing.media;
+            self.head = lsn + 1;
+            media = true;
+        }
+        if media {
+            let read_ahead = self.cache.read_ahead.min(self.cache.sectors);
+            let end = (self.head + read_ahead).min(self.sectors);
+            self.cached = self.run_start.max(end.saturating_sub(self.cache.sectors))..end;
+            self.read_ahead = self.head;
+            self.head = end;
+        }
+        self.clock.advance(elapsed);
+
+        let start = i64::from(first_lsn) * SECTOR_WORDS as i64 + shift;
+        for (i, sample) in buf[..sectors * SECTOR_WORDS].iter_mut().enumerate() {
+            *sample = self.sample(start + i as i64);
+        }
+        Ok(sectors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISC_SECTORS: u32 = 75 * 60 * 60;
+
+    fn analyze(drive: SimulatedDrive) -> Report {
+        let mut drive = drive;
+        let clock = drive.clock();
+        analyze_with_clock(&mut drive, &clock).unwrap()
+    }
+
+    #[test]
+    fn uncached_drive() {
+        let report = analyze(SimulatedDrive::new(DISC_SECTORS));
+        assert!(!report.cached);
+        assert_eq!(report.cache_sectors, None);
+        assert_eq!(report.cache_defeat, CacheDefeat::NotNeeded);
+        assert!(report.accurate_stream);
+        assert!((7.9..8.1).contains(&report.read_speed()));
+        assert!(report.seek_time > Timing::default().seek);
+    }
+
+    #[test]
+    fn cache_without_read_ahead() {
+        let report = analyze(SimulatedDrive::new(DISC_SECTORS).with_cache(CacheModel {
+            sectors: 500,
+            read_ahead: 0,
+        }));
+        assert!(report.cached);
+        assert_eq!(report.cache_sectors, Some(500));
+        assert_eq!(
+            report.cache_defeat,
+            CacheDefeat::ReadElsewhere { sectors: 501 }
+        );
+        assert!(report.accurate_stream);
+
+        let report = report.to_string();
+        assert!(report.contains("cache:            500 sectors (1148 KiB)"));
+        assert!(report.contains("cache defeat:     read 501 sectors elsewhere"));
+    }
+
+    #[test]
+    fn caches_beyond_the_limit() {
+        let report = analyze(SimulatedDrive::new(DISC_SECTORS).with_cache(CacheModel {
+            sectors: 2 * MAX_CACHE_SECTORS,
+            read_ahead: 0,
+        }));
+        assert_eq!(report.cache_sectors, Some(MAX_CACHE_SECTORS));
+        // the simulated drive only caches the current sequential read
+        assert_eq!(
+            report.cache_defeat,
+            CacheDefeat::ReadElsewhere {
+                sectors: MAX_CACHE_SECTORS + 1
+            }
+        );
+    }
+
+    #[test]
+    fn reads_past_the_disc_fail() {
+        let mut drive = SimulatedDrive::new(10);
+        let mut buf = vec![0; 4 * SECTOR_WORDS];
+        assert_eq!(drive.read_raw(8, &mut buf).unwrap(), 2);
+        assert!(drive.read_raw(10, &mut buf).is_err());
+    }
+
+    #[test]
+    fn short_discs() {
+        // the probes overlap, but the analysis runs to the end
+        let report = analyze(SimulatedDrive::new(10));
+        assert!(report.accurate_stream);
+
+        // a cache larger than the disc holds all of it
+        let report = analyze(SimulatedDrive::new(2000).with_cache(CacheModel {
+            sectors: 3000,
+            read_ahead: 0,
+        }));
+        assert!(report.cached);
+        assert_eq!(report.cache_sectors, Some(2000));
+        assert_eq!(report.cache_defeat, CacheDefeat::Failed);
+    }
+}
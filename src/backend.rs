@@ -0,0 +1,415 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::{Drive, ParanoiaError, Result, SECTOR_WORDS};
+
+/// Maximum number of tracks on a CD.
+const MAX_TRACKS: usize = 99;
+
+/// Provides the table of contents and raw audio sectors of a CD.
+///
+/// [`Drive`] implements this trait for physical drives,
+/// [`VirtualDisc`] implements it for a disc that is kept in memory.
+/// Code that is generic over this trait can therefore be tested without
+/// a physical drive.
+///
+/// Track numbers start at 1, logical sector numbers (LSNs) at 0.
+pub trait CdBackend {
+    /// Get the number of tracks on the CD.
+    fn tracks(&self) -> u8;
+    /// Get the logical sector number for the start of a track.
+    fn track_first_sector(&self, track: u8) -> Result<u32>;
+    /// Get the last logical sector number of a track.
+    fn track_last_sector(&self, track: u8) -> Result<u32>;
+    /// Get the number of channels in a track.
+    ///
+    /// Returns `Some(2)` or `Some(4)` on success or
+    /// `None` if the value could not be retrieved.
+    fn track_channels(&self, track: u8) -> Option<u8>;
+    /// Check if a track is an audio track.
+    fn track_audio(&self, track: u8) -> bool;
+    /// Check if a track has copy permit set.
+    fn track_copy_permitted(&self, track: u8) -> bool;
+    /// Check if a track has linear preemphasis set.
+    fn track_linear_preemphasis(&self, track: u8) -> bool;
+    /// Get the first logical sector number of the first audio track.
+    fn disc_first_sector(&self) -> Result<u32> {
+        let track = (1..=self.tracks())
+            .find(|&track| self.track_audio(track))
+            .ok_or(ParanoiaError::NoAudioTracks)?;
+        self.track_first_sector(track)
+    }
+    /// Get the last logical sector number of the last audio track.
+    fn disc_last_sector(&self) -> Result<u32> {
+        let track = (1..=self.tracks())
+            .rev()
+            .find(|&track| self.track_audio(track))
+            .ok_or(ParanoiaError::NoAudioTracks)?;
+        self.track_last_sector(track)
+    }
+    /// Get the track containing the given logical sector number.
+    ///
+    /// If the LSN is before the first track (in the pregap), 0 is returned.
+    fn sector_track(&self, lsn: u32) -> Result<u8> {
+        for track in 1..=self.tracks() {
+            if lsn < self.track_first_sector(track)? {
+                return Ok(track - 1);
+            }
+            if lsn <= self.track_last_sector(track)? {
+                return Ok(track);
+            }
+        }
+        Err(ParanoiaError::InvalidTrackNumber.into())
+    }
+    /// Get the media catalog number (UPC/EAN) of the CD, if available.
+    fn disc_mcn(&self) -> Option<String> {
+        None
+    }
+    /// Get the International Standard Recording Code of a track, if available.
+    fn track_isrc(&self, _track: u8) -> Option<String> {
+        None
+    }
+    /// Get the first logical sector number of a track's pregap (index 0),
+    /// if it is known.
+    fn track_pregap_sector(&self, _track: u8) -> Option<u32> {
+        None
+    }
+    /// Read raw audio sectors without any verification or error correction.
+    ///
+    /// Reads as many whole sectors starting at `first_lsn` as fit into `buf`
+    /// and returns the number of sectors read.
+    fn read_raw(&mut self, first_lsn: u32, buf: &mut [i16]) -> Result<usize>;
+    /// Get the underlying [`Drive`] if this backend is one.
+    ///
+    /// [`Paranoia`](crate::Paranoia) uses this to hand physical drives to
+    /// libcdio-paranoia/cdparanoia-3 if one of them is enabled.
+    fn as_drive(&self) -> Option<&Drive> {
+        None
+    }
+}
+
+impl CdBackend for Drive {
+    fn tracks(&self) -> u8 {
+        Drive::tracks(self)
+    }
+    fn track_first_sector(&self, track: u8) -> Result<u32> {
+        Drive::track_first_sector(self, track)
+    }
+    fn track_last_sector(&self, track: u8) -> Result<u32> {
+        Drive::track_last_sector(self, track)
+    }
+    fn track_channels(&self, track: u8) -> Option<u8> {
+        Drive::track_channels(self, track)
+    }
+    fn track_audio(&self, track: u8) -> bool {
+        Drive::track_audio(self, track)
+    }
+    fn track_copy_permitted(&self, track: u8) -> bool {
+        Drive::track_copy_permitted(self, track)
+    }
+    fn track_linear_preemphasis(&self, track: u8) -> bool {
+        Drive::track_linear_preemphasis(self, track)
+    }
+    fn disc_first_sector(&self) -> Result<u32> {
+        Drive::disc_first_sector(self)
+    }
+    fn disc_last_sector(&self) -> Result<u32> {
+        Drive::disc_last_sector(self)
+    }
+    fn sector_track(&self, lsn: u32) -> Result<u8> {
+        Drive::sector_track(self, lsn)
+    }
+    fn disc_mcn(&self) -> Option<String> {
+        Drive::disc_mcn(self)
+    }
+    fn track_isrc(&self, track: u8) -> Option<String> {
+        Drive::track_isrc(self, track)
+    }
+    fn track_pregap_sector(&self, track: u8) -> Option<u32> {
+        Drive::track_pregap_sector(self, track)
+    }
+    fn read_raw(&mut self, first_lsn: u32, buf: &mut [i16]) -> Result<usize> {
+        Drive::read_raw(self, first_lsn, buf)
+    }
+    fn as_drive(&self) -> Option<&Drive> {
+        Some(self)
+    }
+}
+
+/// A CD that only exists in memory.
+///
+/// The disc is built from a list of [`VirtualTrack`]s which are laid out
+/// back to back. Sectors before the first track read as digital silence.
+///
+/// # Example
+///
+/// ```
+/// use cdparanoia::{CdBackend, VirtualDisc, VirtualTrack, SECTOR_WORDS};
+///
+/// let mut disc = VirtualDisc::new([
+///     VirtualTrack::new(vec![1; 10 * SECTOR_WORDS]),
+///     VirtualTrack::new(vec![2; 5 * SECTOR_WORDS]).with_preemphasis(true),
+/// ])?
+/// .with_first_sector(150);
+///
+/// assert_eq!(disc.tracks(), 2);
+/// assert_eq!(disc.track_first_sector(2)?, 160);
+/// assert_eq!(disc.track_last_sector(2)?, 164);
+/// assert_eq!(disc.sector_track(42)?, 0);
+/// assert!(disc.track_linear_preemphasis(2));
+///
+/// let mut buf = vec![0; 2 * SECTOR_WORDS];
+/// assert_eq!(disc.read_raw(159, &mut buf)?, 2);
+/// assert_eq!(buf[0], 1);
+/// assert_eq!(buf[SECTOR_WORDS], 2);
+/// # Ok::<(), cdparanoia::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualDisc {
+    first_lsn: u32,
+    mcn: Option<String>,
+    tracks: Vec<VirtualTrack>,
+}
+
+impl VirtualDisc {
+    /// Create a disc from a list of tracks, starting at LSN 0.
+    ///
+    /// Fails with [`ParanoiaError::IllegalNumberOfTracks`] if there are more
+    /// than 99 tracks, like on a real CD, and with
+    /// [`ParanoiaError::UnaddressableSector`] if the tracks have more sectors
+    /// than a `u32` can address.
+    pub fn new(tracks: impl IntoIterator<Item = VirtualTrack>) -> Result<Self> {
+        let tracks: Vec<_> = tracks.into_iter().collect();
+        if tracks.len() > MAX_TRACKS {
+            return Err(ParanoiaError::IllegalNumberOfTracks.into());
+        }
+        let sectors: usize = tracks
+            .iter()
+            .map(|track| track.samples.len() / SECTOR_WORDS)
+            .sum();
+        if u32::try_from(sectors).is_err() {
+            return Err(ParanoiaError::UnaddressableSector.into());
+        }
+
+        Ok(Self {
+            first_lsn: 0,
+            mcn: None,
+            tracks,
+        })
+    }
+    /// Move the start of the first track to the given LSN.
+    pub fn with_first_sector(mut self, lsn: u32) -> Self {
+        self.first_lsn = lsn;
+        self
+    }
+    /// Set the media catalog number of the disc.
+    pub fn with_mcn(mut self, mcn: impl Into<String>) -> Self {
+        self.mcn = Some(mcn.into());
+        self
+    }
+    /// Get the audio data of a track, padded to whole sectors.
+    pub fn track_samples(&self, track: u8) -> Option<&[i16]> {
+        self.track(track).ok().map(|track| track.samples.as_slice())
+    }
+    fn track(&self, track: u8) -> Result<&VirtualTrack> {
+        track
+            .checked_sub(1)
+            .and_then(|index| self.tracks.get(usize::from(index)))
+            .ok_or_else(|| ParanoiaError::InvalidTrackNumber.into())
+    }
+    /// Get the sector after the last addressable sector of the tracks.
+    ///
+    /// This is a `u64`, so the sector at `u32::MAX` can be read as well.
+    fn end_lsn(&self) -> u64 {
+        let sectors: u64 = self
+            .tracks
+            .iter()
+            .map(|track| u64::from(track.sectors()))
+            .sum();
+        (u64::from(self.first_lsn) + sectors).min(u64::from(u32::MAX) + 1)
+    }
+}
+
+impl CdBackend for VirtualDisc {
+    fn tracks(&self) -> u8 {
+        // at most 99, see `VirtualDisc::new()`
+        self.tracks.len() as u8
+    }
+    fn track_first_sector(&self, track: u8) -> Result<u32> {
+        self.track(track)?;
+        self.first_lsn
+            .checked_add(
+                self.tracks[..usize::from(track - 1)]
+                    .iter()
+                    .map(VirtualTrack::sectors)
+                    .sum::<u32>(),
+            )
+            .ok_or_else(|| ParanoiaError::UnaddressableSector.into())
+    }
+    fn track_last_sector(&self, track: u8) -> Result<u32> {
+        let sectors = self.track(track)?.sectors();
+        self.track_first_sector(track)?
+            .checked_add(sectors.max(1) - 1)
+            .ok_or_else(|| ParanoiaError::UnaddressableSector.into())
+    }
+    fn track_channels(&self, track: u8) -> Option<u8> {
+        self.track(track).ok().map(|track| track.channels)
+    }
+    fn track_audio(&self, track: u8) -> bool {
+        self.track(track).is_ok_and(|track| track.audio)
+    }
+    fn track_copy_permitted(&self, track: u8) -> bool {
+        self.track(track).is_ok_and(|track| track.copy_permitted)
+    }
+    fn track_linear_preemphasis(&self, track: u8) -> bool {
+        self.track(track).is_ok_and(|track| track.preemphasis)
+    }
+    fn disc_mcn(&self) -> Option<String> {
+        self.mcn.clone()
+    }
+    fn track_isrc(&self, track: u8) -> Option<String> {
+        self.track(track).ok()?.isrc.clone()
+    }
+    fn track_pregap_sector(&self, track: u8) -> Option<u32> {
+        let pregap = self.track(track).ok()?.pregap?;
+        self.track_first_sector(track).ok()?.checked_sub(pregap)
+    }
+    fn read_raw(&mut self, first_lsn: u32, buf: &mut [i16]) -> Result<usize> {
+        let sectors = (buf.len() as u64 / SECTOR_WORDS as u64)
+            .min(self.end_lsn().saturating_sub(first_lsn.into())) as usize;
+        if sectors == 0 {
+            return Err(ParanoiaError::UnaddressableSector.into());
+        }
+
+        let mut offset = first_lsn as usize * SECTOR_WORDS;
+        let mut buf = &mut buf[..sectors * SECTOR_WORDS];
+
+        let pregap = (self.first_lsn as usize * SECTOR_WORDS).saturating_sub(offset);
+        let (silence, rest) = buf.split_at_mut(pregap.min(buf.len()));
+        silence.fill(0);
+        buf = rest;
+        offset += silence.len();
+
+        let mut track_start = self.first_lsn as usize * SECTOR_WORDS;
+        for track in &self.tracks {
+            if buf.is_empty() {
+                break;
+            }
+            let track_end = track_start + track.samples.len();
+            if offset < track_end {
+                let len = (track_end - offset).min(buf.len());
+                let (chunk, rest) = buf.split_at_mut(len);
+                chunk.copy_from_slice(&track.samples[offset - track_start..][..len]);
+                buf = rest;
+                offset += len;
+            }
+            track_start = track_end;
+        }
+
+        Ok(sectors)
+    }
+}
+
+/// A track of a [`VirtualDisc`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualTrack {
+    samples: Vec<i16>,
+    channels: u8,
+    audio: bool,
+    copy_permitted: bool,
+    preemphasis: bool,
+    isrc: Option<String>,
+    pregap: Option<u32>,
+}
+
+impl VirtualTrack {
+    /// Create a two-channel audio track from interleaved samples.
+    ///
+    /// The samples are padded with silence to a whole number of sectors.
+    pub fn new(mut samples: Vec<i16>) -> Self {
+        samples.resize(samples.len().div_ceil(SECTOR_WORDS) * SECTOR_WORDS, 0);
+        Self {
+            samples,
+            channels: 2,
+            audio: true,
+            copy_permitted: false,
+            preemphasis: false,
+            isrc: None,
+            pregap: None,
+        }
+    }
+    /// Set the number of channels reported for this track.
+    pub fn with_channels(mut self, channels: u8) -> Self {
+        self.channels = channels;
+        self
+    }
+    /// Mark this track as an audio or data track.
+    pub fn with_audio(mut self, audio: bool) -> Self {
+        self.audio = audio;
+        self
+    }
+    /// Set the copy permit flag of this track.
+    pub fn with_copy_permitted(mut self, copy_permitted: bool) -> Self {
+        self.copy_permitted = copy_permitted;
+        self
+    }
+    /// Set the linear preemphasis flag of this track.
+    pub fn with_preemphasis(mut self, preemphasis: bool) -> Self {
+        self.preemphasis = preemphasis;
+        self
+    }
+    /// Set the International Standard Recording Code of this track.
+    pub fn with_isrc(mut self, isrc: impl Into<String>) -> Self {
+        self.isrc = Some(isrc.into());
+        self
+    }
+    /// Mark the last `sectors` sectors before this track as its pregap.
+    pub fn with_pregap(mut self, sectors: u32) -> Self {
+        self.pregap = Some(sectors);
+        self
+    }
+    fn sectors(&self) -> u32 {
+        // fits, see `VirtualDisc::new()`
+        (self.samples.len() / SECTOR_WORDS) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn rejects_more_than_99_tracks() {
+        let track = VirtualTrack::new(vec![0; SECTOR_WORDS]);
+        let disc = VirtualDisc::new(vec![track.clone(); 99]).unwrap();
+        assert_eq!(disc.tracks(), 99);
+        assert_eq!(disc.track_first_sector(99).unwrap(), 98);
+
+        assert!(matches!(
+            VirtualDisc::new(vec![track; 256]),
+            Err(Error::Paranoia(ParanoiaError::IllegalNumberOfTracks))
+        ));
+    }
+
+    #[test]
+    fn sectors_past_u32_are_unaddressable() {
+        let mut disc = VirtualDisc::new([
+            VirtualTrack::new(vec![0; 2 * SECTOR_WORDS]),
+            VirtualTrack::new(vec![0; 2 * SECTOR_WORDS]),
+        ])
+        .unwrap()
+        .with_first_sector(u32::MAX - 1);
+
+        assert!(matches!(
+            disc.track_first_sector(2),
+            Err(Error::Paranoia(ParanoiaError::UnaddressableSector))
+        ));
+
+        // the sectors of the first track can all be read
+        assert_eq!(disc.track_last_sector(1).unwrap(), u32::MAX);
+        let mut buf = vec![0; 4 * SECTOR_WORDS];
+        assert_eq!(disc.read_raw(u32::MAX - 1, &mut buf).unwrap(), 2);
+        assert_eq!(disc.read_raw(u32::MAX, &mut buf).unwrap(), 1);
+    }
+}
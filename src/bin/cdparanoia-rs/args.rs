@@ -0,0 +1,374 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Parsing of cdparanoia-compatible command-line arguments.
+
+use std::{ffi::OsString, path::PathBuf};
+
+use cdparanoia::{sink::Endianness, ParanoiaMode};
+
+pub const USAGE: &str = "\
+Usage: cdparanoia-rs [options] <span> [outfile]
+
+Reads audio from a CD and writes it to outfile (default: cdda.wav,
+`-` for standard output).
+
+Options:
+  -d, --force-cdrom-device <dev>    read from the given device
+  -g, --force-generic-device <dev>  use the given generic SCSI device
+  -k, --force-cooked-device <dev>   use the cooked ioctl interface of the device
+  -n, --force-default-sectors <n>   read n sectors at a time
+  -c, --force-cdrom-little-endian   assume the drive returns little-endian data
+  -C, --force-cdrom-big-endian      assume the drive returns big-endian data
+  -T, --toc-bias                    treat the start of track 1 as sector 0
+  -B, --batch                       write each track to a separate file
+  -w, --output-wav                  write a WAV file (default)
+  -f, --output-aiff                 write an AIFF file
+  -a, --output-aifc                 write an AIFF-C file
+  -p, --output-raw                  write raw samples in host byte order
+  -r, --output-raw-little-endian    write raw little-endian samples
+  -R, --output-raw-big-endian       write raw big-endian samples
+  -S, --force-read-speed <n>        set the read speed of the drive
+  -Z, --disable-paranoia            disable all verification
+  -z, --never-skip[=<retries>]      retry unreadable data forever, or the given
+                                    number of times before skipping it
+  -O, --sample-offset <n>           correct a read offset of n samples
+                                    (default: the offset of the drive)
+  -Q, --query                       print the table of contents and exit
+      --json                        print the table of contents as JSON
+  -A, --analyze-drive               analyze the cache and timing of the drive
+      --list-drives                 list all drives and exit
+  -v, --verbose                     print more information
+  -q, --quiet                       print no information
+  -V, --version                     print the version and exit
+  -h, --help                        print this help and exit
+
+Spans:
+  1              track 1
+  1-3            tracks 1 to 3
+  2-             track 2 to the end of the disc
+  -- -3          the start of the disc to track 3
+  1[1:20.35]     1 minute, 20 seconds and 35 sectors into track 1 to its end
+  1[30]-2[1:00]  30 seconds into track 1 to 1 minute into track 2
+";
+
+/// The format of the output file(s).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Wav,
+    Aiff,
+    Aifc,
+    Raw(Endianness),
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Wav => "wav",
+            Format::Aiff => "aiff",
+            Format::Aifc => "aifc",
+            Format::Raw(_) => "raw",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub device: Option<PathBuf>,
+    pub generic_device: Option<PathBuf>,
+    pub cooked_device: Option<PathBuf>,
+    pub sectors_per_read: Option<u32>,
+    pub drive_endianness: Option<Endianness>,
+    pub toc_bias: bool,
+    pub batch: bool,
+    pub format: Format,
+    pub speed: Option<i32>,
+    pub disable_paranoia: bool,
+    /// `Some` if `-z` is given, with the maximum number of retries.
+    pub never_skip: Option<Option<i32>>,
+    /// Overrides the read offset of the drive quirks.
+    pub sample_offset: Option<i32>,
+    pub query: bool,
+    pub json: bool,
+    pub analyze: bool,
+    pub list_drives: bool,
+    pub verbosity: Verbosity,
+    pub span: Option<String>,
+    pub outfile: Option<PathBuf>,
+}
+
+impl Args {
+    /// Get the verification mode selected by `-Z` and `-z`.
+    ///
+    /// Like in cdparanoia, only `-z` without a retry count disables skipping,
+    /// `-z=<retries>` skips once the retries are exhausted.
+    pub fn mode(&self) -> ParanoiaMode {
+        if self.disable_paranoia {
+            ParanoiaMode::DISABLE
+        } else if self.never_skip == Some(None) {
+            ParanoiaMode::FULL
+        } else {
+            ParanoiaMode::FULL ^ ParanoiaMode::NEVERSKIP
+        }
+    }
+}
+
+/// What to do after parsing the arguments.
+#[derive(Debug)]
+pub enum Command {
+    Run(Args),
+    Help,
+    Version,
+}
+
+/// Parse the arguments without the program name.
+pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let mut parsed = Args::default();
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let arg = arg
+            .into_string()
+            .map_err(|arg| format!("invalid argument: {}", arg.to_string_lossy()))?;
+
+        if arg == "--" {
+            positional.extend(args.by_ref().map(|arg| arg.to_string_lossy().into_owned()));
+            break;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (long, None),
+            };
+            let short = LONG_OPTIONS
+                .iter()
+                .find(|(long, _)| *long == name)
+                .map(|(_, short)| *short)
+                .ok_or_else(|| format!("unknown option: --{name}"))?;
+            let value = match (takes_value(short), value) {
+                (Value::Required, None) => Some(next_value(&mut args, &arg)?),
+                (Value::None, Some(_)) => {
+                    return Err(format!("option --{name} doesn't take a value"))
+                }
+                (_, value) => value,
+            };
+            if let Some(command) = apply(&mut parsed, short, value)? {
+                return Ok(command);
+            }
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            for (i, short) in arg[1..].char_indices() {
+                if LONG_ONLY.contains(&short) {
+                    return Err(format!("unknown option: -{short}"));
+                }
+                let rest = &arg[1 + i + short.len_utf8()..];
+                let value = match takes_value(short) {
+                    Value::None => None,
+                    Value::Optional => {
+                        let rest = rest.strip_prefix('=').unwrap_or(rest);
+                        Some(rest.to_owned()).filter(|rest| !rest.is_empty())
+                    }
+                    Value::Required if rest.is_empty() => Some(next_value(&mut args, &arg)?),
+                    Value::Required => Some(rest.to_owned()),
+                };
+                if let Some(command) = apply(&mut parsed, short, value)? {
+                    return Ok(command);
+                }
+                if takes_value(short) != Value::None {
+                    break;
+                }
+            }
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    parsed.span = positional.next();
+    parsed.outfile = positional.next().map(PathBuf::from);
+    if let Some(arg) = positional.next() {
+        return Err(format!("unexpected argument: {arg}"));
+    }
+    if parsed.span.is_none()
+        && !parsed.query
+        && !parsed.json
+        && !parsed.analyze
+        && !parsed.list_drives
+    {
+        return Err("no span was given".to_owned());
+    }
+    Ok(Command::Run(parsed))
+}
+
+/// Stand-ins for options without a short form.
+const JSON: char = '\u{e000}';
+const LIST_DRIVES: char = '\u{e001}';
+const LONG_ONLY: &[char] = &[JSON, LIST_DRIVES];
+
+const LONG_OPTIONS: &[(&str, char)] = &[
+    ("force-cdrom-device", 'd'),
+    ("force-generic-device", 'g'),
+    ("force-cooked-device", 'k'),
+    ("force-default-sectors", 'n'),
+    ("force-cdrom-little-endian", 'c'),
+    ("force-cdrom-big-endian", 'C'),
+    ("toc-bias", 'T'),
+    ("batch", 'B'),
+    ("output-wav", 'w'),
+    ("output-aiff", 'f'),
+    ("output-aifc", 'a'),
+    ("output-raw", 'p'),
+    ("output-raw-little-endian", 'r'),
+    ("output-raw-big-endian", 'R'),
+    ("force-read-speed", 'S'),
+    ("disable-paranoia", 'Z'),
+    ("never-skip", 'z'),
+    ("sample-offset", 'O'),
+    ("query", 'Q'),
+    ("json", JSON),
+    ("analyze-drive", 'A'),
+    ("list-drives", LIST_DRIVES),
+    ("verbose", 'v'),
+    ("quiet", 'q'),
+    ("version", 'V'),
+    ("help", 'h'),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    None,
+    Optional,
+    Required,
+}
+
+fn takes_value(short: char) -> Value {
+    match short {
+        'd' | 'g' | 'k' | 'n' | 'S' | 'O' => Value::Required,
+        'z' => Value::Optional,
+        _ => Value::None,
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = OsString>, option: &str) -> Result<String, String> {
+    args.next()
+        .map(|value| value.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("option {option} requires a value"))
+}
+
+fn number(option: char, value: &str) -> Result<i32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for -{option}: {value}"))
+}
+
+fn apply(args: &mut Args, short: char, value: Option<String>) -> Result<Option<Command>, String> {
+    let value = value.unwrap_or_default();
+    match short {
+        'd' => args.device = Some(value.into()),
+        'g' => args.generic_device = Some(value.into()),
+        'k' => args.cooked_device = Some(value.into()),
+        'n' => {
+            args.sectors_per_read = Some(
+                number(short, &value)?
+                    .try_into()
+                    .map_err(|_| format!("invalid value for -{short}: {value}"))?,
+            )
+        }
+        'c' => args.drive_endianness = Some(Endianness::Little),
+        'C' => args.drive_endianness = Some(Endianness::Big),
+        'T' => args.toc_bias = true,
+        'B' => args.batch = true,
+        'w' => args.format = Format::Wav,
+        'f' => args.format = Format::Aiff,
+        'a' => args.format = Format::Aifc,
+        'p' if cfg!(target_endian = "big") => args.format = Format::Raw(Endianness::Big),
+        'p' => args.format = Format::Raw(Endianness::Little),
+        'r' => args.format = Format::Raw(Endianness::Little),
+        'R' => args.format = Format::Raw(Endianness::Big),
+        'S' => args.speed = Some(number(short, &value)?),
+        'Z' => args.disable_paranoia = true,
+        'z' if value.is_empty() => args.never_skip = Some(None),
+        'z' => args.never_skip = Some(Some(number(short, &value)?)),
+        'O' => args.sample_offset = Some(number(short, &value)?),
+        'Q' => args.query = true,
+        JSON => args.json = true,
+        'A' => args.analyze = true,
+        LIST_DRIVES => args.list_drives = true,
+        'v' => args.verbosity = Verbosity::Verbose,
+        'q' => args.verbosity = Verbosity::Quiet,
+        'V' => return Ok(Some(Command::Version)),
+        'h' => return Ok(Some(Command::Help)),
+        _ => return Err(format!("unknown option: -{short}")),
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<Args, String> {
+        match parse(args.iter().map(OsString::from))? {
+            Command::Run(args) => Ok(args),
+            command => panic!("unexpected command {command:?}"),
+        }
+    }
+
+    #[test]
+    fn raw_formats() {
+        let host = if cfg!(target_endian = "big") {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        };
+        for (option, endianness) in [
+            ("-p", host),
+            ("--output-raw", host),
+            ("-r", Endianness::Little),
+            ("--output-raw-little-endian", Endianness::Little),
+            ("-R", Endianness::Big),
+            ("--output-raw-big-endian", Endianness::Big),
+        ] {
+            assert_eq!(
+                run(&[option, "1"]).unwrap().format,
+                Format::Raw(endianness),
+                "{option}"
+            );
+        }
+    }
+
+    #[test]
+    fn long_only_options() {
+        assert!(run(&["--json"]).unwrap().json);
+        assert!(run(&["--list-drives"]).unwrap().list_drives);
+        for option in ["-J", "-L", "-\u{e000}", "-v\u{e001}"] {
+            assert!(run(&[option, "1"]).is_err(), "{option}");
+        }
+    }
+
+    #[test]
+    fn never_skip_modes() {
+        let skip = ParanoiaMode::FULL ^ ParanoiaMode::NEVERSKIP;
+        for (args, mode, retries) in [
+            (&["1"][..], skip, None),
+            (&["-z", "1"], ParanoiaMode::FULL, Some(None)),
+            (&["--never-skip", "1"], ParanoiaMode::FULL, Some(None)),
+            (&["-z=5", "1"], skip, Some(Some(5))),
+            (&["-z5", "1"], skip, Some(Some(5))),
+            (&["--never-skip=5", "1"], skip, Some(Some(5))),
+            (&["-Z", "-z", "1"], ParanoiaMode::DISABLE, Some(None)),
+        ] {
+            let parsed = run(args).unwrap();
+            assert_eq!(parsed.mode(), mode, "{args:?}");
+            assert_eq!(parsed.never_skip, retries, "{args:?}");
+        }
+    }
+}
@@ -0,0 +1,265 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! A command-line interface that mirrors the classic `cdparanoia` program.
+
+use std::{
+    error::Error,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use cdparanoia::{
+    analysis,
+    message::Stderr,
+    options::Interface,
+    sink::{Aifc, Aiff, Raw, Wav},
+    span::Span,
+    toc::Toc,
+    Drive, DriveOptions, Paranoia,
+};
+
+use crate::{
+    args::{Args, Command, Format, Verbosity, USAGE},
+    rip::Rip,
+};
+
+mod args;
+mod rip;
+
+/// Number of sectors per second of audio.
+const SECTORS_PER_SECOND: u32 = 75;
+/// Retry count of cdparanoia if `-z` is given without a value.
+const DEFAULT_RETRIES: i32 = 20;
+
+fn main() -> ExitCode {
+    let args = match args::parse(std::env::args_os().skip(1)) {
+        Ok(Command::Run(args)) => args,
+        Ok(Command::Help) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("cdparanoia-rs {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("cdparanoia-rs: {err}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("cdparanoia-rs: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let quiet = args.verbosity == Verbosity::Quiet;
+    let verbose = args.verbosity == Verbosity::Verbose;
+
+    if args.list_drives {
+        for drive in Drive::list() {
+            println!("{drive}");
+        }
+        return Ok(());
+    }
+
+    // like cdparanoia, only print library errors unless -v is given
+    let mut options = DriveOptions::new()
+        .message_sink(Stderr)
+        .verbosity(match args.verbosity {
+            Verbosity::Quiet => cdparanoia::options::Verbosity::Quiet,
+            Verbosity::Normal => cdparanoia::options::Verbosity::Errors,
+            Verbosity::Verbose => cdparanoia::options::Verbosity::Verbose,
+        });
+    if let Some(generic_device) = &args.generic_device {
+        options = options.interface(Interface::GenericScsi(generic_device.clone()));
+    } else if args.cooked_device.is_some() {
+        options = options.interface(Interface::Cooked);
+    }
+    if let Some(sectors) = args.sectors_per_read {
+        options = options.sectors_per_read(sectors);
+    }
+    if let Some(endianness) = args.drive_endianness {
+        options = options.endianness(endianness);
+    }
+    options = options.toc_bias(args.toc_bias);
+
+    let mut drive = match args.cooked_device.as_ref().or(args.device.as_ref()) {
+        Some(device) => options.open(device)?,
+        None => options.find()?,
+    };
+    if let Some(speed) = args.speed {
+        if let Err(err) = drive.set_speed(speed) {
+            if !quiet {
+                eprintln!("Unable to set the read speed: {err}");
+            }
+        }
+    }
+    if args.query || args.json || verbose {
+        let toc = Toc::read(&drive)?;
+        if args.json {
+            print!("{}", toc.to_json());
+        } else if !quiet {
+            eprintln!("\n{toc}");
+        }
+    }
+    if args.query || args.json {
+        return Ok(());
+    }
+    if args.analyze {
+        if !quiet {
+            eprintln!("Analyzing the drive, this may take a while...");
+        }
+        print!("{}", analysis::analyze(&mut drive)?);
+        return Ok(());
+    }
+
+    let span = args.span.as_deref().unwrap_or_default();
+    let (first_lsn, last_lsn) = span
+        .parse::<Span>()
+        .map_err(cdparanoia::Error::from)
+        .and_then(|span| span.resolve(&drive))
+        .map_err(|err| format!("invalid span {span:?}: {err}"))?
+        .into_inner();
+
+    let jobs = if args.batch {
+        // Split the span at track boundaries.
+        let mut jobs = Vec::new();
+        let mut lsn = first_lsn;
+        while lsn <= last_lsn {
+            let track = drive.sector_track(lsn)?;
+            let last = drive.track_last_sector(track)?.min(last_lsn);
+            if drive.track_audio(track) {
+                jobs.push((Some(track), lsn, last));
+            }
+            lsn = last + 1;
+        }
+        jobs
+    } else {
+        vec![(None, first_lsn, last_lsn)]
+    };
+
+    let mut paranoia = drive.paranoia();
+    paranoia.set_mode(args.mode());
+    // the read offset of the drive quirks, unless `-O` overrides it
+    if let Some(offset) = args.sample_offset {
+        paranoia.set_read_offset(offset);
+    }
+    if verbose && paranoia.read_offset() != 0 {
+        eprintln!(
+            "correcting a read offset of {} samples",
+            paranoia.read_offset()
+        );
+    }
+
+    for (track, first_lsn, last_lsn) in jobs {
+        let path = output_path(&args, track)?;
+        if !quiet {
+            eprintln!(
+                "Ripping from sector {first_lsn:7} ({})\n\
+                 \x20         to sector {last_lsn:7} ({})",
+                position(paranoia.drive(), first_lsn),
+                position(paranoia.drive(), last_lsn),
+            );
+            match &path {
+                Some(path) => eprintln!("outputting to {}", path.display()),
+                None => eprintln!("outputting to stdout"),
+            }
+        }
+
+        let rip = Rip {
+            first_lsn,
+            last_lsn,
+            max_retries: args.never_skip.flatten().unwrap_or(DEFAULT_RETRIES),
+            progress: verbose,
+        };
+        match path {
+            Some(path) => write_file(&rip, &mut paranoia, args.format, &path)?,
+            None => write_stdout(&rip, &mut paranoia, args.format)?,
+        }
+
+        if !quiet {
+            eprintln!("Done.");
+        }
+    }
+    Ok(())
+}
+
+/// Get the output file for a track, or `None` for standard output.
+fn output_path(args: &Args, track: Option<u8>) -> Result<Option<PathBuf>, String> {
+    let default = format!("cdda.{}", args.format.extension());
+    let outfile = args.outfile.as_deref().unwrap_or(Path::new(&default));
+    match track {
+        None if outfile == Path::new("-") => Ok(None),
+        None => Ok(Some(outfile.to_owned())),
+        Some(_) if outfile == Path::new("-") => {
+            Err("batch mode can't write to standard output".to_owned())
+        }
+        Some(track) => {
+            let name = outfile
+                .file_name()
+                .ok_or_else(|| format!("invalid output file: {}", outfile.display()))?;
+            let mut name_with_track = OsString::from(format!("track{track:02}."));
+            name_with_track.push(name);
+            Ok(Some(outfile.with_file_name(name_with_track)))
+        }
+    }
+}
+
+fn write_file(
+    rip: &Rip,
+    paranoia: &mut Paranoia,
+    format: Format,
+    path: &Path,
+) -> cdparanoia::Result<()> {
+    match format {
+        Format::Wav => rip.run(paranoia, Wav::create(path)?).map(drop),
+        Format::Aiff => rip.run(paranoia, Aiff::create(path)?).map(drop),
+        Format::Aifc => rip.run(paranoia, Aifc::create(path)?).map(drop),
+        Format::Raw(endianness) => rip
+            .run(paranoia, Raw::create(path)?.with_endianness(endianness))
+            .map(drop),
+    }
+}
+
+fn write_stdout(rip: &Rip, paranoia: &mut Paranoia, format: Format) -> cdparanoia::Result<()> {
+    let stdout = io::stdout().lock();
+    match format {
+        Format::Wav => rip.run(paranoia, Wav::streaming(stdout)).map(drop),
+        Format::Aiff => rip.run(paranoia, Aiff::streaming(stdout)).map(drop),
+        Format::Aifc => rip.run(paranoia, Aifc::streaming(stdout)).map(drop),
+        Format::Raw(endianness) => rip
+            .run(paranoia, Raw::new(stdout).with_endianness(endianness))
+            .map(drop),
+    }
+}
+
+/// Format a number of sectors as `mm:ss.ff`.
+fn timestamp(sectors: u32) -> String {
+    let seconds = sectors / SECTORS_PER_SECOND;
+    format!(
+        "{:02}:{:02}.{:02}",
+        seconds / 60,
+        seconds % 60,
+        sectors % SECTORS_PER_SECOND
+    )
+}
+
+/// Describe the position of a sector relative to its track.
+fn position(drive: &Drive, lsn: u32) -> String {
+    match drive
+        .sector_track(lsn)
+        .and_then(|track| Ok((track, drive.track_first_sector(track)?)))
+    {
+        Ok((track, first)) => format!("track {track:2} [{}]", timestamp(lsn - first)),
+        Err(_) => format!("[{}]", timestamp(lsn)),
+    }
+}
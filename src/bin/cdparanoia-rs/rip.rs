@@ -0,0 +1,46 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Reading a range of sectors into a sink.
+
+use cdparanoia::{sink::Sink, CdBackend, Paranoia, Result};
+
+/// Progress is reported every second of audio.
+const PROGRESS_INTERVAL: u32 = 75;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rip {
+    pub first_lsn: u32,
+    pub last_lsn: u32,
+    pub max_retries: i32,
+    pub progress: bool,
+}
+
+impl Rip {
+    /// Read the sectors, corrected by the read offset of `paranoia`.
+    pub fn run<B: CdBackend, S: Sink>(
+        &self,
+        paranoia: &mut Paranoia<B>,
+        mut sink: S,
+    ) -> Result<S::Output> {
+        sink.begin(paranoia.drive(), self.first_lsn, self.last_lsn)?;
+
+        let mut reader =
+            paranoia.read_sectors_limited(self.first_lsn, self.last_lsn, self.max_retries);
+        loop {
+            let lsn = reader.current_lsn();
+            let Some(sector) = reader.next_sector() else {
+                break;
+            };
+            sink.write_sector(sector?)?;
+            if self.progress && (lsn - self.first_lsn).is_multiple_of(PROGRESS_INTERVAL) {
+                eprint!("\r  reading sector {lsn} of {}", self.last_lsn);
+            }
+        }
+        if self.progress {
+            eprintln!();
+        }
+
+        sink.finish()
+    }
+}
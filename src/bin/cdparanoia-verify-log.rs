@@ -0,0 +1,112 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Checks whether signed rip logs have been edited after the rip.
+
+use std::{error::Error, fs, path::PathBuf, process::ExitCode};
+
+use cdparanoia::signature::{self, StationKey};
+
+const USAGE: &str = "\
+Usage: cdparanoia-verify-log -k <station>=<keyfile> [-k ...] <log>...
+
+Checks the signature blocks of rip logs and reports whether each log is
+authentic. Exits with a failure status unless all logs are valid.
+
+Options:
+  -k, --key <station>=<keyfile>      the secret HMAC key of a station, the
+                                     whole content of the file is used as
+                                     the key
+  -e, --ed25519 <station>=<keyfile>  the Ed25519 public key of a station,
+                                     as 64 hexadecimal digits
+  -h, --help                         print this help and exit
+";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("cdparanoia-verify-log: {err}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<bool, Box<dyn Error>> {
+    let mut keys = Vec::new();
+    let mut logs = Vec::new();
+
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy().into_owned();
+        let (ed25519, key) = match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                return Ok(true);
+            }
+            "-k" | "--key" | "-e" | "--ed25519" => (
+                matches!(arg.as_str(), "-e" | "--ed25519"),
+                args.next()
+                    .ok_or_else(|| format!("option {arg} requires a value"))?
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            _ => match (arg.strip_prefix("--key="), arg.strip_prefix("--ed25519=")) {
+                (Some(key), _) => (false, key.to_owned()),
+                (_, Some(key)) => (true, key.to_owned()),
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(format!("unknown option: {arg}").into())
+                }
+                _ => {
+                    logs.push(PathBuf::from(arg));
+                    continue;
+                }
+            },
+        };
+        let (station, path) = key
+            .split_once('=')
+            .ok_or_else(|| format!("invalid key {key:?}, expected <station>=<keyfile>"))?;
+        let key = fs::read(path).map_err(|err| format!("can't read key file {path}: {err}"))?;
+        keys.push(if ed25519 {
+            StationKey::ed25519_public(
+                station,
+                public_key(&key).ok_or_else(|| {
+                    format!("invalid key file {path}, expected 64 hexadecimal digits")
+                })?,
+            )?
+        } else {
+            StationKey::new(station, key)
+        });
+    }
+    if logs.is_empty() {
+        return Err("no log was given".into());
+    }
+
+    let mut all_valid = true;
+    for path in logs {
+        let log = if path.as_os_str() == "-" {
+            std::io::read_to_string(std::io::stdin())?
+        } else {
+            fs::read_to_string(&path)
+                .map_err(|err| format!("can't read {}: {err}", path.display()))?
+        };
+        let status = signature::verify(&log, &keys);
+        println!("{}: {status}", path.display());
+        all_valid &= status.is_valid();
+    }
+    Ok(all_valid)
+}
+
+/// Parse a public key that is written as 64 hexadecimal digits.
+fn public_key(file: &[u8]) -> Option<[u8; 32]> {
+    let hex = std::str::from_utf8(file).ok()?.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut key = [0; 32];
+    for (byte, i) in key.iter_mut().zip((0..64).step_by(2)) {
+        *byte = u8::from_str_radix(&hex[i..i + 2], 16).ok()?;
+    }
+    Some(key)
+}
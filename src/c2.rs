@@ -0,0 +1,285 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Reads with C2 error pointers.
+//!
+//! Many drives can report which bytes of a sector they failed to correct
+//! (the C2 error pointers, see [`ReadCd::with_c2_pointers()`]).
+//! [`Drive::read_raw_c2()`] returns them alongside the audio data.
+//! [`C2Secure`] uses them to rip without paranoia: all sectors are read once
+//! and only the sectors with C2 errors are read again.
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{
+//!     c2::C2Secure,
+//!     mmc::{Command, MockTransport, ReadCd, C2_BYTES},
+//!     SECTOR_BYTES,
+//! };
+//!
+//! let sector = |c2: u8| [vec![1; SECTOR_BYTES], vec![c2; C2_BYTES]].concat();
+//!
+//! // the second sector has C2 errors on the first read
+//! let transport = MockTransport::new()
+//!     .with_response(
+//!         ReadCd::new(100, 3).with_c2_pointers().cdb(),
+//!         [sector(0), sector(0x80), sector(0)].concat(),
+//!     )
+//!     .with_response(ReadCd::new(101, 1).with_c2_pointers().cdb(), sector(0));
+//!
+//! let rip = C2Secure::new().read_sectors(&transport, 100, 102);
+//! assert_eq!(rip.flagged, [101..=101]);
+//! assert_eq!(rip.rereads, 1);
+//! assert!(rip.is_clean());
+//! assert_eq!(rip.samples, vec![0x0101; 3 * SECTOR_BYTES / 2]);
+//! ```
+
+use std::ops::RangeInclusive;
+
+use crate::{
+    mmc::{Command, ReadCd, Transport, C2_BYTES},
+    read::ranges,
+    Drive, Result, SECTOR_BYTES, SECTOR_WORDS,
+};
+
+/// Number of sectors per READ CD, which keeps a transfer below 64 KiB.
+const BATCH_SECTORS: u32 = 24;
+
+impl Drive {
+    /// Read raw audio sectors with their C2 error pointers, without any
+    /// verification or error correction.
+    ///
+    /// Reads as many whole sectors starting at `first_lsn` as fit into both
+    /// `buf` and `c2` ([`C2_BYTES`] per sector) and returns the number of
+    /// sectors read. This requires raw MMC commands, see
+    /// [`mmc`](crate::mmc).
+    pub fn read_raw_c2(&self, first_lsn: u32, buf: &mut [i16], c2: &mut [u8]) -> Result<usize> {
+        read_raw_c2(self, first_lsn, buf, c2)
+    }
+}
+
+/// Read raw audio sectors with their C2 error pointers through a
+/// [`Transport`], see [`Drive::read_raw_c2()`].
+///
+/// The samples are decoded as little-endian, which is what drives return
+/// unless they are configured otherwise.
+pub fn read_raw_c2(
+    transport: &(impl Transport + ?Sized),
+    first_lsn: u32,
+    buf: &mut [i16],
+    c2: &mut [u8],
+) -> Result<usize> {
+    let sectors = (buf.len() / SECTOR_WORDS).min(c2.len() / C2_BYTES);
+    let mut done = 0;
+    while done < sectors {
+        let count = (sectors - done).min(BATCH_SECTORS as usize);
+        let data = ReadCd::new(first_lsn + done as u32, count as u32)
+            .with_c2_pointers()
+            .run(transport)?;
+        for (i, sector) in data.chunks_exact(SECTOR_BYTES + C2_BYTES).enumerate() {
+            let (audio, pointers) = sector.split_at(SECTOR_BYTES);
+            let index = done + i;
+            for (sample, bytes) in buf[index * SECTOR_WORDS..(index + 1) * SECTOR_WORDS]
+                .iter_mut()
+                .zip(audio.chunks_exact(2))
+            {
+                *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+            }
+            c2[index * C2_BYTES..(index + 1) * C2_BYTES].copy_from_slice(pointers);
+        }
+        done += count;
+    }
+    Ok(sectors)
+}
+
+/// Count the bytes of a sector that are flagged in its C2 error pointers.
+pub fn error_bytes(pointers: &[u8]) -> usize {
+    pointers.iter().map(|byte| byte.count_ones() as usize).sum()
+}
+
+/// Rips by re-reading only the sectors that the drive flags with C2 errors.
+///
+/// This is much faster than paranoia on drives with reliable C2 error
+/// pointers, but it can't detect errors that the drive doesn't report.
+/// Drives that cache audio data may return the same data on a re-read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct C2Secure {
+    max_rereads: u32,
+}
+
+/// The result of [`C2Secure::read_sectors()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct C2Rip {
+    pub first_lsn: u32,
+    pub samples: Vec<i16>,
+    /// Sectors that had C2 errors or couldn't be read in the first pass.
+    pub flagged: Vec<RangeInclusive<u32>>,
+    /// The number of sectors that have been read again.
+    pub rereads: u32,
+    /// Sectors that still had C2 errors (or couldn't be read) after all
+    /// re-reads.
+    ///
+    /// They contain the read with the fewest errors, or silence if they
+    /// couldn't be read at all.
+    pub unrecovered: Vec<RangeInclusive<u32>>,
+}
+
+impl Default for C2Secure {
+    fn default() -> Self {
+        Self { max_rereads: 16 }
+    }
+}
+
+impl C2Secure {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set how often a sector with C2 errors is read again, default 16.
+    pub fn with_max_rereads(mut self, max_rereads: u32) -> Self {
+        self.max_rereads = max_rereads;
+        self
+    }
+    /// Read the sectors `first_lsn..=last_lsn`.
+    ///
+    /// Failed reads aren't returned as errors, the affected sectors are
+    /// re-read and reported in [`C2Rip::unrecovered`] if that doesn't help.
+    pub fn read_sectors(
+        &self,
+        transport: &(impl Transport + ?Sized),
+        first_lsn: u32,
+        last_lsn: u32,
+    ) -> C2Rip {
+        let mut samples = Vec::new();
+        let mut flagged = Vec::new();
+        let mut unrecovered = Vec::new();
+        let mut rereads = 0;
+
+        let mut lsn = first_lsn;
+        while lsn <= last_lsn {
+            let count = (last_lsn - lsn + 1).min(BATCH_SECTORS);
+            let batch = read(transport, lsn, count as usize);
+            for i in 0..count {
+                let mut best = batch
+                    .as_ref()
+                    .ok()
+                    .map(|sectors| sectors[i as usize].clone());
+                let is_clean = |best: &Option<(Vec<i16>, usize)>| {
+                    best.as_ref().is_some_and(|(_, errors)| *errors == 0)
+                };
+                if !is_clean(&best) {
+                    flagged.push(lsn + i);
+                    for _ in 0..self.max_rereads {
+                        rereads += 1;
+                        if let Ok(mut sectors) = read(transport, lsn + i, 1) {
+                            let sector = sectors.remove(0);
+                            if best.as_ref().is_none_or(|(_, errors)| sector.1 < *errors) {
+                                best = Some(sector);
+                            }
+                        }
+                        if is_clean(&best) {
+                            break;
+                        }
+                    }
+                    if !is_clean(&best) {
+                        unrecovered.push(lsn + i);
+                    }
+                }
+                match best {
+                    Some((audio, _)) => samples.extend(audio),
+                    None => samples.extend([0; SECTOR_WORDS]),
+                }
+            }
+            lsn += count;
+        }
+
+        C2Rip {
+            first_lsn,
+            samples,
+            flagged: ranges(flagged),
+            rereads,
+            unrecovered: ranges(unrecovered),
+        }
+    }
+}
+
+impl C2Rip {
+    /// Check if all sectors have been read without C2 errors.
+    pub fn is_clean(&self) -> bool {
+        self.unrecovered.is_empty()
+    }
+}
+
+/// Read sectors and get their samples and number of C2 error bytes.
+fn read(
+    transport: &(impl Transport + ?Sized),
+    lsn: u32,
+    sectors: usize,
+) -> Result<Vec<(Vec<i16>, usize)>> {
+    let mut buf = vec![0; sectors * SECTOR_WORDS];
+    let mut c2 = vec![0; sectors * C2_BYTES];
+    read_raw_c2(transport, lsn, &mut buf, &mut c2)?;
+    Ok(buf
+        .chunks_exact(SECTOR_WORDS)
+        .zip(c2.chunks_exact(C2_BYTES))
+        .map(|(audio, pointers)| (audio.to_vec(), error_bytes(pointers)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmc::{MockTransport, Sense};
+
+    fn sector(audio: u8, c2: u8) -> Vec<u8> {
+        [vec![audio; SECTOR_BYTES], vec![c2; C2_BYTES]].concat()
+    }
+
+    #[test]
+    fn failed_batch_is_read_sector_by_sector() {
+        let medium_error = Sense {
+            key: 0x03,
+            asc: 0x11,
+            ascq: 0x00,
+        };
+        let read = |lsn, sectors| ReadCd::new(lsn, sectors).with_c2_pointers().cdb();
+        let transport = MockTransport::new()
+            .with_sense(read(10, 3), medium_error)
+            .with_response(read(10, 1), sector(1, 0))
+            .with_sense(read(11, 1), medium_error)
+            .with_response(read(11, 1), sector(2, 0))
+            // too short
+            .with_response(read(12, 1), vec![3; SECTOR_BYTES])
+            .with_sense(read(12, 1), medium_error);
+
+        let rip = C2Secure::new()
+            .with_max_rereads(2)
+            .read_sectors(&transport, 10, 12);
+        assert_eq!(transport.remaining(), 0);
+        assert_eq!(rip.flagged, [10..=12]);
+        assert_eq!(rip.rereads, 5);
+        assert_eq!(rip.unrecovered, [12..=12]);
+        assert_eq!(rip.samples[..SECTOR_WORDS], [0x0101; SECTOR_WORDS]);
+        assert_eq!(
+            rip.samples[SECTOR_WORDS..2 * SECTOR_WORDS],
+            [0x0202; SECTOR_WORDS]
+        );
+        assert_eq!(rip.samples[2 * SECTOR_WORDS..], [0; SECTOR_WORDS]);
+    }
+
+    #[test]
+    fn keeps_the_read_with_fewest_errors() {
+        let read = ReadCd::new(0, 1).with_c2_pointers().cdb();
+        let transport = MockTransport::new()
+            .with_response(read.clone(), sector(1, 0xff))
+            .with_response(read.clone(), sector(2, 0x01))
+            .with_response(read, sector(3, 0x03));
+
+        let rip = C2Secure::new()
+            .with_max_rereads(2)
+            .read_sectors(&transport, 0, 0);
+        assert_eq!(rip.unrecovered, [0..=0]);
+        assert_eq!(rip.samples, [0x0202; SECTOR_WORDS]);
+        assert_eq!(error_bytes(&[0xff, 0x01, 0x03]), 11);
+    }
+}
@@ -0,0 +1,381 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! What a drive can do when ripping audio.
+//!
+//! [`Drive::capabilities()`] asks the drive with the raw MMC commands
+//! [`ModeSense2A`] and [`GetConfiguration`] and, if a disc is inserted,
+//! probes whether the drive reads into the lead-in and lead-out.
+//! [`Capabilities::detect()`] does the same through any [`Transport`], so a
+//! report can be created from a recorded transcript, see
+//! [`Recorder`](crate::mmc::Recorder).
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{capabilities::Capabilities, mmc::MockTransport};
+//!
+//! let transport = MockTransport::from_transcript(
+//!     "\
+//! ## INQUIRY
+//! > 12 00 00 00 60 00
+//! < 05 80 05 32 5b 00 00 00 50 4c 45 58 54 4f 52 20
+//! < 44 56 44 52 20 20 20 50 58 2d 37 31 36 41 20 20
+//! < 31 2e 31 31
+//! ## MODE SENSE page 2Ah
+//! > 5a 08 2a 00 00 00 00 01 00 00
+//! < 00 1c 00 00 00 00 00 00 2a 14 03 00 01 33 00 00
+//! < 1b 90 00 00 08 00
+//! ## GET CONFIGURATION
+//! > 46 00 00 00 00 00 00 10 00 00
+//! < 00 00 00 0c 00 00 00 08 00 1e 09 04 03 00 00 00
+//! ## READ TOC, no disc
+//! > 43 00 00 00 00 00 00 10 00 00
+//! ! 02 3a 00
+//! ",
+//! )?;
+//! let capabilities = Capabilities::detect(&transport)?;
+//!
+//! assert_eq!(capabilities.product, "DVDR   PX-716A");
+//! assert!(capabilities.cdda);
+//! assert!(capabilities.accurate_stream);
+//! assert!(capabilities.c2_pointers);
+//! assert!(capabilities.cd_text);
+//! assert_eq!(capabilities.lead_out_overread, None);
+//! assert_eq!(capabilities.max_read_speed, 7056);
+//! assert_eq!(capabilities.cache_size, 2048);
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::fmt::{self, Display};
+
+use serde_json::{json, Value};
+
+use crate::{
+    mmc::{
+        Command, Configuration, GetConfiguration, Inquiry, MmcError, ModeSense2A, ReadCd, ReadToc,
+        TocFormat, Transport,
+    },
+    Drive, Error, Result,
+};
+
+/// Read speed of 1x in kB/s.
+const SPEED_1X: f64 = 176.4;
+/// The track number of the lead-out in the table of contents.
+const LEAD_OUT: u8 = 0xaa;
+/// The last sector of the lead-in, right before the pregap of the first
+/// track at LBA -150.
+const LEAD_IN_END: i32 = -151;
+
+impl Drive {
+    /// Detect what the drive can do when ripping audio.
+    ///
+    /// This requires raw MMC commands, see [`mmc`](crate::mmc).
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        Capabilities::detect(self)
+    }
+}
+
+/// The capabilities of a drive, see [`Drive::capabilities()`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Capabilities {
+    pub vendor: String,
+    pub product: String,
+    pub revision: String,
+    /// Whether audio can be read digitally (CD-DA extraction).
+    pub cdda: bool,
+    /// Whether the drive can continue an audio read at the exact position
+    /// after it was interrupted, i.e. doesn't produce jitter.
+    pub accurate_stream: bool,
+    /// Whether the drive reports C2 error pointers.
+    pub c2_pointers: bool,
+    /// Whether sectors of the lead-in can be read, or `None` if no disc was
+    /// inserted.
+    ///
+    /// This is probed with the last sector of the lead-in, not the pregap of
+    /// the first track that lies between the lead-in and LBA 0.
+    pub lead_in_overread: Option<bool>,
+    /// Whether sectors after the last track can be read, or `None` if no
+    /// disc was inserted.
+    pub lead_out_overread: Option<bool>,
+    pub subchannels: Subchannels,
+    /// Whether CD-TEXT can be read from the lead-in.
+    pub cd_text: bool,
+    /// The size of the drive's buffer in KiB.
+    pub cache_size: u16,
+    /// The maximum read speed in kB/s.
+    pub max_read_speed: u16,
+}
+
+/// The subchannel data a drive can read, in addition to the Q subchannel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Subchannels {
+    /// Raw interleaved R-W subchannels.
+    pub rw_raw: bool,
+    /// Deinterleaved and error corrected R-W subchannels.
+    pub rw_corrected: bool,
+    /// The ISRC of tracks.
+    pub isrc: bool,
+    /// The media catalog number (UPC/EAN) of the disc.
+    pub mcn: bool,
+}
+
+impl Capabilities {
+    /// Detect the capabilities of a drive through a [`Transport`].
+    ///
+    /// Drives that don't support GET CONFIGURATION are only checked with
+    /// MODE SENSE. Fails if the drive rejects INQUIRY or MODE SENSE.
+    pub fn detect(transport: &(impl Transport + ?Sized)) -> Result<Self> {
+        let inquiry = Inquiry.run(transport)?;
+        let page = ModeSense2A.run(transport)?;
+        let configuration = match GetConfiguration::new().run(transport) {
+            Err(Error::Mmc(MmcError::CheckCondition(_))) => Configuration {
+                current_profile: 0,
+                features: Vec::new(),
+            },
+            result => result?,
+        };
+
+        // byte 0 of CD Read: CD-TEXT (bit 0) and C2 error pointers (bit 1)
+        let cd_read = configuration
+            .feature(GetConfiguration::CD_READ)
+            .map(|feature| feature.data.first().copied().unwrap_or(0));
+        let (lead_in_overread, lead_out_overread) = probe_overread(transport)?;
+
+        Ok(Self {
+            vendor: inquiry.vendor,
+            product: inquiry.product,
+            revision: inquiry.revision,
+            cdda: page.cdda || cd_read.is_some(),
+            accurate_stream: page.accurate_stream,
+            c2_pointers: page.c2_pointers || cd_read.is_some_and(|flags| flags & 0x02 != 0),
+            lead_in_overread,
+            lead_out_overread,
+            subchannels: Subchannels {
+                rw_raw: page.rw_subchannel,
+                rw_corrected: page.rw_subchannel && page.rw_deinterleaved,
+                isrc: page.isrc,
+                mcn: page.upc,
+            },
+            cd_text: cd_read.is_some_and(|flags| flags & 0x01 != 0),
+            cache_size: page.buffer_size,
+            max_read_speed: page.max_read_speed,
+        })
+    }
+    /// Get the maximum read speed as a multiple of 1x, e.g. `40` for
+    /// 7056 kB/s.
+    pub fn max_read_speed_factor(&self) -> u32 {
+        (f64::from(self.max_read_speed) / SPEED_1X).round() as u32
+    }
+    /// Get the capabilities as a JSON document.
+    ///
+    /// Probes that couldn't be run without a disc are `null`.
+    pub fn to_json(&self) -> String {
+        format!("{:#}\n", self.to_value())
+    }
+    fn to_value(&self) -> Value {
+        json!({
+            "vendor": self.vendor,
+            "product": self.product,
+            "revision": self.revision,
+            "cdda": self.cdda,
+            "accurate_stream": self.accurate_stream,
+            "c2_pointers": self.c2_pointers,
+            "lead_in_overread": self.lead_in_overread,
+            "lead_out_overread": self.lead_out_overread,
+            "subchannels": {
+                "rw_raw": self.subchannels.rw_raw,
+                "rw_corrected": self.subchannels.rw_corrected,
+                "isrc": self.subchannels.isrc,
+                "mcn": self.subchannels.mcn,
+            },
+            "cd_text": self.cd_text,
+            "cache_size_kib": self.cache_size,
+            "max_read_speed_kbps": self.max_read_speed,
+        })
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        let probed = |value: Option<bool>| value.map_or("unknown (no disc)", yes_no);
+
+        writeln!(f, "{} {} {}", self.vendor, self.product, self.revision)?;
+        writeln!(f, "CD-DA extraction:   {}", yes_no(self.cdda))?;
+        writeln!(f, "accurate stream:    {}", yes_no(self.accurate_stream))?;
+        writeln!(f, "C2 error pointers:  {}", yes_no(self.c2_pointers))?;
+        writeln!(f, "lead-in overread:   {}", probed(self.lead_in_overread))?;
+        writeln!(f, "lead-out overread:  {}", probed(self.lead_out_overread))?;
+        let subchannels = [
+            (true, "Q"),
+            (self.subchannels.rw_raw, "R-W raw"),
+            (self.subchannels.rw_corrected, "R-W corrected"),
+            (self.subchannels.isrc, "ISRC"),
+            (self.subchannels.mcn, "MCN"),
+        ]
+        .into_iter()
+        .filter_map(|(supported, name)| supported.then_some(name))
+        .collect::<Vec<_>>();
+        writeln!(f, "subchannels:        {}", subchannels.join(", "))?;
+        writeln!(f, "CD-TEXT:            {}", yes_no(self.cd_text))?;
+        writeln!(f, "cache size:         {} KiB", self.cache_size)?;
+        writeln!(
+            f,
+            "max read speed:     {} kB/s ({}x)",
+            self.max_read_speed,
+            self.max_read_speed_factor()
+        )
+    }
+}
+
+/// Try to read the last sector of the lead-in and the first one of the
+/// lead-out.
+///
+/// Returns `None` for both if there is no disc with a table of contents.
+fn probe_overread(transport: &(impl Transport + ?Sized)) -> Result<(Option<bool>, Option<bool>)> {
+    let toc = match ReadToc::new(TocFormat::Toc).run(transport) {
+        Err(Error::Mmc(MmcError::CheckCondition(_))) => return Ok((None, None)),
+        result => result?,
+    };
+    let Some(lead_out) = toc
+        .tracks()
+        .into_iter()
+        .find(|track| track.number == LEAD_OUT)
+    else {
+        return Ok((None, None));
+    };
+
+    let readable = |lba: i32| match ReadCd::new(lba as u32, 1).run(transport) {
+        Ok(_) => Ok(true),
+        Err(Error::Mmc(MmcError::CheckCondition(_))) => Ok(false),
+        Err(err) => Err(err),
+    };
+    // the CDB takes the two's complement of negative addresses
+    Ok((
+        Some(readable(LEAD_IN_END)?),
+        Some(readable(lead_out.address)?),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmc::{MockTransport, Sense};
+
+    const INVALID_OPCODE: Sense = Sense {
+        key: 0x05,
+        asc: 0x20,
+        ascq: 0x00,
+    };
+
+    /// A drive without GET CONFIGURATION, like many drives before MMC-2.
+    const OLD_DRIVE: &str = "\
+## INQUIRY
+> 12 00 00 00 60 00
+< 05 00 02 02 1f 00 00 00 54 4f 53 48 49 42 41 20
+< 43 44 2d 52 4f 4d 20 58 4d 2d 36 32 30 32 42 20
+< 31 31 31 32
+## MODE SENSE page 2Ah
+> 5a 08 2a 00 00 00 00 01 00 00
+< 00 1a 00 00 00 00 00 00 2a 12 03 00 01 23 00 00
+< 10 89 00 00 01 00
+## GET CONFIGURATION, invalid command operation code
+> 46 00 00 00 00 00 00 10 00 00
+! 05 20 00
+";
+
+    fn transport(transcript: &str) -> MockTransport {
+        MockTransport::from_transcript(transcript).unwrap()
+    }
+
+    #[test]
+    fn without_get_configuration() {
+        let transport = transport(&format!(
+            "{OLD_DRIVE}\
+## READ TOC, no disc
+> 43 00 00 00 00 00 00 10 00 00
+! 02 3a 00
+"
+        ));
+        let capabilities = Capabilities::detect(&transport).unwrap();
+        assert_eq!(transport.remaining(), 0);
+
+        assert_eq!(capabilities.vendor, "TOSHIBA");
+        assert_eq!(capabilities.product, "CD-ROM XM-6202B");
+        // from MODE SENSE only
+        assert!(capabilities.cdda);
+        assert!(capabilities.accurate_stream);
+        assert!(!capabilities.c2_pointers);
+        assert!(!capabilities.cd_text);
+        assert!(capabilities.subchannels.isrc);
+        assert!(!capabilities.subchannels.rw_raw);
+        assert_eq!(capabilities.max_read_speed, 4233);
+        assert_eq!(capabilities.max_read_speed_factor(), 24);
+        assert_eq!(capabilities.cache_size, 256);
+        assert_eq!(capabilities.lead_in_overread, None);
+        assert_eq!(capabilities.lead_out_overread, None);
+    }
+
+    #[test]
+    fn overread_probes() {
+        let mut lead_out = [0; crate::SECTOR_BYTES];
+        lead_out[0] = 1;
+        let transport = transport(OLD_DRIVE)
+            .with_response(
+                ReadToc::new(TocFormat::Toc).cdb(),
+                [
+                    [0, 0x12, 1, 1].as_slice(),
+                    &[0, 0x10, 1, 0, 0, 0, 0, 0],
+                    &[0, 0x10, LEAD_OUT, 0, 0, 0, 0x10, 0],
+                ]
+                .concat(),
+            )
+            .with_sense(
+                ReadCd::new(LEAD_IN_END as u32, 1).cdb(),
+                Sense {
+                    key: 0x05,
+                    asc: 0x21,
+                    ascq: 0x00,
+                },
+            )
+            .with_response(ReadCd::new(0x1000, 1).cdb(), lead_out);
+
+        let capabilities = Capabilities::detect(&transport).unwrap();
+        assert_eq!(transport.remaining(), 0);
+        assert_eq!(capabilities.lead_in_overread, Some(false));
+        assert_eq!(capabilities.lead_out_overread, Some(true));
+        // the lead-in is probed at LBA -151, not in the pregap
+        assert_eq!(
+            transport.commands()[4],
+            [0xbe, 0x04, 0xff, 0xff, 0xff, 0x69, 0, 0, 1, 0x10, 0, 0]
+        );
+
+        let json = capabilities.to_json();
+        assert!(json.contains(r#""lead_in_overread": false"#));
+        assert!(json.contains(r#""lead_out_overread": true"#));
+    }
+
+    #[test]
+    fn short_probe_read_is_an_error() {
+        let transport = transport(OLD_DRIVE)
+            .with_response(
+                ReadToc::new(TocFormat::Toc).cdb(),
+                [0, 0x0a, 1, 1, 0, 0x10, LEAD_OUT, 0, 0, 0, 0x10, 0],
+            )
+            .with_response(ReadCd::new(LEAD_IN_END as u32, 1).cdb(), [0; 16]);
+        assert!(matches!(
+            Capabilities::detect(&transport),
+            Err(Error::Mmc(MmcError::InvalidResponse))
+        ));
+    }
+
+    #[test]
+    fn rejected_inquiry_fails() {
+        let transport = MockTransport::new().with_sense(Inquiry.cdb(), INVALID_OPCODE);
+        assert!(matches!(
+            Capabilities::detect(&transport),
+            Err(Error::Mmc(MmcError::CheckCondition(INVALID_OPCODE)))
+        ));
+    }
+}
@@ -0,0 +1,690 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    ffi::{c_char, CStr, CString, OsStr},
+    fmt::{self, Debug},
+    os::unix::prelude::OsStrExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[cfg(not(feature = "libcdio-paranoia"))]
+use crate::SECTOR_BYTES;
+use crate::{
+    device,
+    message::{Discard, Level, Message, MessageSink},
+    options::{Interface, Verbosity},
+    quirks::Quirks,
+    sink::Endianness,
+    DriveInfo, DriveOptions, Error, Paranoia, ParanoiaError, Result, SECTOR_WORDS,
+};
+
+/// Represents a physical or virtual CD-ROM drive.
+///
+/// Use [`Drive::find()`] to get a default drive or [`Drive::open()`]
+/// to get a specific drive with a CD-DA in it. [`DriveOptions`] opens
+/// drives with non-default options.
+///
+/// For reading audio data, get a [`Paranoia`] instance using the [`paranoia()`](Drive::paranoia) method.
+pub struct Drive {
+    ptr: *mut crate::ffi::cdrom_drive,
+    message_sink: Arc<dyn MessageSink>,
+    quirks: Quirks,
+}
+
+impl Debug for Drive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Drive")
+            .field("ptr", &self.ptr)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Drive {
+    fn drop(&mut self) {
+        self.check_messages();
+        unsafe { crate::ffi::cdda_close(self.ptr) };
+    }
+}
+
+impl Drive {
+    /// Open a default CD-ROM drive with a CD-DA in it.
+    pub fn find() -> Result<Self> {
+        DriveOptions::new().find()
+    }
+    /// Open a specific CD-ROM drive with a CD-DA in it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        DriveOptions::new().open(path)
+    }
+    pub(crate) fn find_with(options: &DriveOptions) -> Result<Self> {
+        options.validate()?;
+        let ptr = match &options.interface {
+            Interface::Auto => {
+                let mut messages = std::ptr::null_mut();
+                let ptr =
+                    unsafe { crate::ffi::cdda_find_a_cdrom(identify_dest(options), &mut messages) };
+                route_messages(options.message_sink.as_ref(), None, messages);
+                ptr
+            }
+            #[cfg(not(feature = "libcdio-paranoia"))]
+            Interface::GenericScsi(generic) => {
+                let generic = c_path(generic)?;
+                let mut messages = std::ptr::null_mut();
+                let ptr = unsafe {
+                    crate::ffi::cdda_identify_scsi(
+                        generic.as_ptr(),
+                        std::ptr::null(),
+                        identify_dest(options),
+                        &mut messages,
+                    )
+                };
+                route_messages(options.message_sink.as_ref(), None, messages);
+                ptr
+            }
+            _ => return Err(ParanoiaError::InterfaceNotSupported.into()),
+        };
+        if ptr.is_null() {
+            return Err(Error::CantOpenDrive);
+        }
+        Drive {
+            ptr,
+            message_sink: options.message_sink.clone(),
+            quirks: Quirks::default(),
+        }
+        .open_identified(options)
+    }
+    pub(crate) fn open_with(path: &Path, options: &DriveOptions) -> Result<Self> {
+        options.validate()?;
+        Self::identify(path, options)?.open_identified(options)
+    }
+    /// List all CD-ROM drives, whether they contain a disc or not.
+    ///
+    /// libcdio-paranoia asks libcdio for all devices, cdparanoia-3 tries the
+    /// same device paths as [`Drive::find()`].
+    pub fn list() -> Vec<DriveInfo> {
+        device::dedup_devices(device_paths())
+            .into_iter()
+            .filter_map(|path| {
+                let drive =
+                    Self::identify(&path, &DriveOptions::new().message_sink(Discard)).ok()?;
+                let (vendor, model, revision) = drive.hardware();
+                let disc_present =
+                    ParanoiaError::check_result(unsafe { crate::ffi::cdda_open(drive.as_ptr()) })
+                        .is_ok();
+                let audio =
+                    disc_present && (1..=drive.tracks()).any(|track| drive.track_audio(track));
+                Some(DriveInfo {
+                    path,
+                    vendor,
+                    model,
+                    revision,
+                    disc_present,
+                    audio,
+                })
+            })
+            .collect()
+    }
+    /// Identify a drive without reading the table of contents.
+    fn identify(path: &Path, options: &DriveOptions) -> Result<Self> {
+        let device = c_path(path)?;
+        let dest = identify_dest(options);
+        let mut messages = std::ptr::null_mut();
+        let ptr = unsafe {
+            match &options.interface {
+                Interface::Auto => crate::ffi::cdda_identify(device.as_ptr(), dest, &mut messages),
+                #[cfg(not(feature = "libcdio-paranoia"))]
+                Interface::Cooked => {
+                    crate::ffi::cdda_identify_cooked(device.as_ptr(), dest, &mut messages)
+                }
+                #[cfg(not(feature = "libcdio-paranoia"))]
+                Interface::GenericScsi(generic) => crate::ffi::cdda_identify_scsi(
+                    c_path(generic)?.as_ptr(),
+                    device.as_ptr(),
+                    dest,
+                    &mut messages,
+                ),
+                #[cfg(feature = "libcdio-paranoia")]
+                _ => return Err(ParanoiaError::InterfaceNotSupported.into()),
+            }
+        };
+        route_messages(
+            options.message_sink.as_ref(),
+            Some(path.to_owned()),
+            messages,
+        );
+        if ptr.is_null() {
+            return Err(Error::CantOpenDrive);
+        }
+        let drive = Drive {
+            ptr,
+            message_sink: options.message_sink.clone(),
+            quirks: Quirks::default(),
+        };
+
+        drive.check_messages();
+
+        Ok(drive)
+    }
+    /// Apply the options and read the table of contents, in the same order
+    /// as cdparanoia.
+    fn open_identified(mut self, options: &DriveOptions) -> Result<Self> {
+        self.quirks = options.quirks.lookup_hardware(self.hardware());
+
+        let (errors, messages) = match options.verbosity {
+            Verbosity::Quiet => (FORGET, FORGET),
+            Verbosity::Errors => (LOG, FORGET),
+            Verbosity::Verbose => (LOG, LOG),
+        };
+        unsafe {
+            crate::ffi::cdda_verbose_set(self.as_ptr(), errors, messages);
+            let drive = &mut *self.as_ptr();
+            if let Some(endianness) = options.endianness {
+                drive.bigendianp = (endianness == Endianness::Big).into();
+            }
+            if let Some(sectors) = options.sectors_per_read.or(self.quirks.sectors_per_read) {
+                drive.nsectors = sectors as _;
+                // like cdparanoia -d, the SCSI buffer has to grow with the reads,
+                // libcdio-paranoia doesn't have one
+                #[cfg(not(feature = "libcdio-paranoia"))]
+                {
+                    drive.bigbuff = (sectors as usize * SECTOR_BYTES) as _;
+                }
+            }
+        }
+
+        ParanoiaError::check_result(unsafe { crate::ffi::cdda_open(self.as_ptr()) })?;
+
+        self.check_messages();
+
+        if options.toc_bias {
+            let offset = self.track_first_sector(1)?;
+            let drive = unsafe { &mut *self.as_ptr() };
+            for entry in drive.disc_toc.iter_mut().take(drive.tracks as usize + 1) {
+                entry.dwStartSector -= offset as i32;
+            }
+        }
+        if let Some(speed) = options.speed {
+            self.set_speed(speed)?;
+        }
+
+        Ok(self)
+    }
+}
+
+const LOG: i32 = crate::ffi::CDDA_MESSAGE_LOGIT as i32;
+const FORGET: i32 = crate::ffi::CDDA_MESSAGE_FORGETIT as i32;
+
+/// Get the message destination while a drive is identified.
+fn identify_dest(options: &DriveOptions) -> i32 {
+    match options.verbosity {
+        Verbosity::Quiet => FORGET,
+        Verbosity::Errors | Verbosity::Verbose => LOG,
+    }
+}
+
+fn c_path(path: &Path) -> Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+/// Get the device paths that [`Drive::list()`] checks.
+#[cfg(feature = "libcdio-paranoia")]
+fn device_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    unsafe {
+        let list = crate::ffi::cdio_get_devices(crate::ffi::driver_id_t::DRIVER_DEVICE);
+        if list.is_null() {
+            return paths;
+        }
+        let mut entry = list;
+        while !(*entry).is_null() {
+            paths.push(PathBuf::from(OsStr::from_bytes(
+                CStr::from_ptr(*entry).to_bytes(),
+            )));
+            entry = entry.add(1);
+        }
+        crate::ffi::cdio_free_device_list(list);
+    }
+    paths
+}
+
+/// Get the device paths that [`Drive::list()`] checks.
+///
+/// This is the search list of `cdda_find_a_cdrom()`, where `?` stands for
+/// `0` to `3` and `a` to `d`.
+#[cfg(not(feature = "libcdio-paranoia"))]
+fn device_paths() -> Vec<PathBuf> {
+    const SEARCH_PATHS: &[&str] = &[
+        "/dev/cdrom",
+        "/dev/cdroms/cdrom?",
+        "/dev/hd?",
+        "/dev/sg?",
+        "/dev/cdu31a",
+        "/dev/cdu535",
+        "/dev/sbpcd",
+        "/dev/sbpcd?",
+        "/dev/sonycd",
+        "/dev/mcd",
+        "/dev/sjcd",
+        "/dev/cm206cd",
+        "/dev/gscd",
+        "/dev/optcd",
+    ];
+    SEARCH_PATHS
+        .iter()
+        .flat_map(|path| match path.strip_suffix('?') {
+            Some(prefix) => ('0'..='3')
+                .chain('a'..='d')
+                .map(|c| PathBuf::from(format!("{prefix}{c}")))
+                .collect(),
+            None => vec![PathBuf::from(path)],
+        })
+        .filter(|path| path.exists())
+        .collect()
+}
+
+impl Drive {
+    /// Get a [`Paranoia`] instance for reading audio data.
+    pub fn paranoia(self) -> Paranoia {
+        Paranoia::new(self)
+    }
+}
+
+impl Drive {
+    /// Get the logical sector number for the start of a track.
+    pub fn track_first_sector(&self, track: u8) -> Result<u32> {
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        let track = track.into();
+        let lsn = ParanoiaError::check_result(unsafe {
+            crate::ffi::cdda_track_firstsector(self.as_ptr(), track)
+        })? as u32;
+        self.check_messages();
+        Ok(lsn)
+    }
+    /// Get the last logical sector number of a track.
+    /// This is generally one less than the start of the next track.
+    pub fn track_last_sector(&self, track: u8) -> Result<u32> {
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        let track = track.into();
+        let lsn = ParanoiaError::check_result(unsafe {
+            crate::ffi::cdda_track_lastsector(self.as_ptr(), track)
+        })? as u32;
+        self.check_messages();
+        Ok(lsn)
+    }
+    /// Get the number of tracks on the CD.
+    #[allow(clippy::let_and_return)]
+    pub fn tracks(&self) -> u8 {
+        let tracks = unsafe { crate::ffi::cdda_tracks(self.as_ptr()) };
+
+        self.check_messages();
+
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        let tracks = tracks.try_into().unwrap();
+        tracks
+    }
+    /// Get the track containing the given logical sector number.
+    ///
+    /// If the LSN is before the first track (in the pregap), 0 is returned.
+    pub fn sector_track(&self, lsn: u32) -> Result<u8> {
+        #[cfg(feature = "libcdio-paranoia")]
+        let lsn = lsn.try_into().unwrap();
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        let lsn = lsn.into();
+
+        let track = ParanoiaError::check_result(unsafe {
+            crate::ffi::cdda_sector_gettrack(self.as_ptr(), lsn)
+        })?;
+
+        self.check_messages();
+
+        #[cfg(feature = "libcdio-paranoia")]
+        if track as u32 == crate::ffi::cdio_track_enums::CDIO_INVALID_TRACK {
+            return Err(ParanoiaError::InvalidTrackNumber.into());
+        }
+
+        Ok(track.try_into().unwrap())
+    }
+    /// Get the number of channels in a track.
+    ///
+    /// Returns `Some(2)` or `Some(4)` on success or
+    /// `None` if the value could not be retrieved.
+    pub fn track_channels(&self, track: u8) -> Option<u8> {
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        let track = track.into();
+        let track_channels = unsafe { crate::ffi::cdda_track_channels(self.as_ptr(), track) }
+            .try_into()
+            .ok();
+
+        self.check_messages();
+
+        track_channels
+    }
+    /// Check if a track is an audio track.
+    pub fn track_audio(&self, track: u8) -> bool {
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        let track = track.into();
+        let track_audio = unsafe { crate::ffi::cdda_track_audiop(self.as_ptr(), track) == 1 };
+
+        self.check_messages();
+
+        track_audio
+    }
+    /// Check if a track has copy permit set.
+    pub fn track_copy_permitted(&self, track: u8) -> bool {
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        let track = track.into();
+        let track_copy_permitted =
+            unsafe { crate::ffi::cdda_track_copyp(self.as_ptr(), track) == 1 };
+
+        self.check_messages();
+
+        track_copy_permitted
+    }
+    /// Check if a track has linear preemphasis set.
+    ///
+    /// Only makes sense for audio tracks.
+    pub fn track_linear_preemphasis(&self, track: u8) -> bool {
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        let track = track.into();
+        let track_linear_preemphasis =
+            unsafe { crate::ffi::cdda_track_preemp(self.as_ptr(), track) == 1 };
+
+        self.check_messages();
+
+        track_linear_preemphasis
+    }
+    /// Get the first logical sector number of the first audio track.
+    pub fn disc_first_sector(&self) -> Result<u32> {
+        let lsn = ParanoiaError::check_result(unsafe {
+            crate::ffi::cdda_disc_firstsector(self.as_ptr())
+        })? as u32;
+
+        self.check_messages();
+
+        Ok(lsn)
+    }
+    /// Get the last logical sector number of the last audio track.
+    pub fn disc_last_sector(&self) -> Result<u32> {
+        let lsn = ParanoiaError::check_result(unsafe {
+            crate::ffi::cdda_disc_lastsector(self.as_ptr())
+        })? as u32;
+
+        self.check_messages();
+
+        Ok(lsn)
+    }
+}
+
+impl Drive {
+    /// Get the media catalog number (UPC/EAN) of the CD, if available.
+    ///
+    /// Always returns `None` with cdparanoia-3.
+    pub fn disc_mcn(&self) -> Option<String> {
+        #[cfg(feature = "libcdio-paranoia")]
+        return unsafe { take_cdio_string(crate::ffi::cdio_get_mcn((*self.as_ptr()).p_cdio)) };
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        None
+    }
+    /// Get the International Standard Recording Code of a track, if available.
+    ///
+    /// Always returns `None` with cdparanoia-3.
+    pub fn track_isrc(&self, track: u8) -> Option<String> {
+        #[cfg(feature = "libcdio-paranoia")]
+        return unsafe {
+            take_cdio_string(crate::ffi::cdio_get_track_isrc(
+                (*self.as_ptr()).p_cdio,
+                track,
+            ))
+        };
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        {
+            let _ = track;
+            None
+        }
+    }
+    /// Get the first logical sector number of a track's pregap (index 0),
+    /// if it is known.
+    ///
+    /// Always returns `None` with cdparanoia-3.
+    pub fn track_pregap_sector(&self, track: u8) -> Option<u32> {
+        #[cfg(feature = "libcdio-paranoia")]
+        return unsafe { crate::ffi::cdio_get_track_pregap_lsn((*self.as_ptr()).p_cdio, track) }
+            .try_into()
+            .ok();
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        {
+            let _ = track;
+            None
+        }
+    }
+}
+
+/// Convert a string allocated by libcdio and free it.
+///
+/// Empty strings are treated as missing.
+#[cfg(feature = "libcdio-paranoia")]
+unsafe fn take_cdio_string(ptr: *mut std::ffi::c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let string = std::ffi::CStr::from_ptr(ptr).to_string_lossy().into_owned();
+    crate::ffi::cdio_free(ptr.cast());
+    Some(string).filter(|string| !string.is_empty())
+}
+
+impl Drive {
+    /// Get the vendor, model and revision of the drive as reported by
+    /// the library, e.g. `"PLEXTOR DVDR PX-716A 1.11"`.
+    pub fn model(&self) -> Option<String> {
+        unsafe { c_string((*self.as_ptr()).drive_model) }
+    }
+    /// Get the quirks of the drive model, see [`quirks`](crate::quirks).
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+    /// Get the path of the device that is used for reading audio.
+    pub fn device_name(&self) -> Option<PathBuf> {
+        unsafe { (*self.as_ptr()).cdda_device_name.as_ref() }.map(|name| {
+            PathBuf::from(OsStr::from_bytes(
+                unsafe { CStr::from_ptr(name) }.to_bytes(),
+            ))
+        })
+    }
+    /// Get vendor, model and revision separately.
+    #[cfg(feature = "libcdio-paranoia")]
+    fn hardware(&self) -> (Option<String>, Option<String>, Option<String>) {
+        let mut info: crate::ffi::cdio_hwinfo_t = unsafe { std::mem::zeroed() };
+        if !unsafe { crate::ffi::cdio_get_hwinfo((*self.as_ptr()).p_cdio, &mut info) } {
+            return (None, self.model(), None);
+        }
+        unsafe {
+            (
+                c_string(info.psz_vendor.as_ptr()),
+                c_string(info.psz_model.as_ptr()),
+                c_string(info.psz_revision.as_ptr()),
+            )
+        }
+    }
+    /// Get vendor, model and revision separately.
+    #[cfg(not(feature = "libcdio-paranoia"))]
+    fn hardware(&self) -> (Option<String>, Option<String>, Option<String>) {
+        self.model()
+            .map_or((None, None, None), |model| device::split_model(&model))
+    }
+}
+
+/// Copy a string that is owned by the library.
+///
+/// Empty strings are treated as missing.
+unsafe fn c_string(ptr: *const std::ffi::c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let string = CStr::from_ptr(ptr).to_string_lossy().trim().to_owned();
+    Some(string).filter(|string| !string.is_empty())
+}
+
+impl Drive {
+    /// Set the read speed as a multiple of 176.4 kB/s, or `-1` for the
+    /// maximum speed.
+    ///
+    /// Not all drives support this.
+    pub fn set_speed(&self, speed: i32) -> Result<()> {
+        let result = ParanoiaError::check_result(unsafe {
+            crate::ffi::cdda_speed_set(self.as_ptr(), speed)
+        });
+
+        self.check_messages();
+
+        result?;
+        Ok(())
+    }
+}
+
+impl Drive {
+    /// Read raw audio sectors without any verification or error correction.
+    ///
+    /// Reads as many whole sectors starting at `first_lsn` as fit into `buf`
+    /// and returns the number of sectors read.
+    pub fn read_raw(&self, first_lsn: u32, buf: &mut [i16]) -> Result<usize> {
+        #[cfg(feature = "libcdio-paranoia")]
+        let first_lsn = first_lsn.try_into().unwrap();
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        let first_lsn = first_lsn.into();
+
+        let sectors = ParanoiaError::check_result(unsafe {
+            crate::ffi::cdda_read(
+                self.as_ptr(),
+                buf.as_mut_ptr().cast(),
+                first_lsn,
+                (buf.len() / SECTOR_WORDS) as std::ffi::c_long,
+            )
+        })?;
+
+        self.check_messages();
+
+        Ok(sectors as usize)
+    }
+}
+
+/// Timeout of raw MMC commands in milliseconds.
+#[cfg(feature = "libcdio-paranoia")]
+const MMC_TIMEOUT_MS: u32 = 10_000;
+
+impl crate::mmc::Transport for Drive {
+    /// Send a raw command through libcdio's MMC layer.
+    ///
+    /// libcdio doesn't report short transfers, so the whole buffer is
+    /// assumed to be filled. Always fails with
+    /// [`MmcError::Unsupported`](crate::mmc::MmcError::Unsupported) with
+    /// cdparanoia-3.
+    fn execute(&self, cdb: &[u8], data: crate::mmc::Data<'_>) -> Result<usize> {
+        #[cfg(feature = "libcdio-paranoia")]
+        {
+            use crate::{
+                ffi::cdio_mmc_direction_t::{
+                    SCSI_MMC_DATA_NONE, SCSI_MMC_DATA_READ, SCSI_MMC_DATA_WRITE,
+                },
+                mmc::{Data, MmcError, Sense},
+            };
+
+            let mut command = crate::ffi::mmc_cdb_t { field: [0; 12] };
+            let len = cdb.len().min(command.field.len());
+            command.field[..len].copy_from_slice(&cdb[..len]);
+            let (direction, len, buf) = match data {
+                Data::None => (SCSI_MMC_DATA_NONE, 0, std::ptr::null_mut()),
+                Data::In(buf) => (SCSI_MMC_DATA_READ, buf.len(), buf.as_mut_ptr()),
+                Data::Out(buf) => (SCSI_MMC_DATA_WRITE, buf.len(), buf.as_ptr().cast_mut()),
+            };
+
+            let p_cdio = unsafe { (*self.as_ptr()).p_cdio };
+            let code = unsafe {
+                crate::ffi::mmc_run_cmd(
+                    p_cdio,
+                    MMC_TIMEOUT_MS,
+                    &command,
+                    direction,
+                    len as u32,
+                    buf.cast(),
+                )
+            };
+            if code == 0 {
+                return Ok(len);
+            }
+
+            let mut sense = std::ptr::null_mut();
+            let sense_len = unsafe { crate::ffi::mmc_last_cmd_sense(p_cdio, &mut sense) };
+            let parsed = (!sense.is_null() && sense_len > 0)
+                .then(|| {
+                    Sense::parse(unsafe {
+                        std::slice::from_raw_parts(sense.cast::<u8>(), sense_len as usize)
+                    })
+                })
+                .flatten();
+            if !sense.is_null() {
+                unsafe { crate::ffi::cdio_free(sense.cast()) };
+            }
+            Err(match parsed {
+                Some(sense) => MmcError::CheckCondition(sense),
+                None => MmcError::Failed(code as i32),
+            }
+            .into())
+        }
+        #[cfg(not(feature = "libcdio-paranoia"))]
+        {
+            let _ = (cdb, data);
+            Err(crate::mmc::MmcError::Unsupported.into())
+        }
+    }
+}
+
+impl Drive {
+    #[inline]
+    pub fn as_ptr(&self) -> *mut crate::ffi::cdrom_drive {
+        self.ptr
+    }
+    /// Pass the pending messages of the library to the [`MessageSink`].
+    pub(crate) fn check_messages(&self) {
+        let sink = self.message_sink.as_ref();
+        unsafe {
+            if let Some(errors) = take_messages(crate::ffi::cdda_errors(self.as_ptr())) {
+                route(sink, Level::Error, self.device_name(), &errors);
+            }
+            if let Some(messages) = take_messages(crate::ffi::cdda_messages(self.as_ptr())) {
+                route(sink, Level::Info, self.device_name(), &messages);
+            }
+        }
+    }
+}
+
+/// Pass the messages that were logged before a drive was identified to
+/// the [`MessageSink`] and free them.
+fn route_messages(sink: &dyn MessageSink, device: Option<PathBuf>, messages: *mut c_char) {
+    if let Some(messages) = unsafe { take_messages(messages) } {
+        route(sink, Level::Info, device, &messages);
+    }
+}
+
+fn route(sink: &dyn MessageSink, level: Level, device: Option<PathBuf>, text: &str) {
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        sink.message(&Message {
+            level,
+            device: device.clone(),
+            text: line.to_owned(),
+        });
+    }
+}
+
+/// Convert a message buffer of the library and free it.
+unsafe fn take_messages(ptr: *mut c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let messages = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+    #[cfg(feature = "libcdio-paranoia")]
+    crate::ffi::cdio_cddap_free_messages(ptr);
+    #[cfg(not(feature = "libcdio-paranoia"))]
+    crate::ffi::libc::free(ptr.cast());
+    Some(messages)
+}
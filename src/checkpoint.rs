@@ -0,0 +1,583 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Resumable rips with checkpoints in a sidecar file.
+//!
+//! A [`Checkpoint`] remembers for every track how far it has been ripped and
+//! the running [`TrackChecksum`] of the ripped part. It is saved next to the
+//! output regularly while ripping, so a rip that is interrupted (e.g. by a
+//! power loss or a disconnected drive) can be resumed in a new session: tracks
+//! that are complete are skipped and partial tracks continue at the first
+//! sector that hasn't been saved.
+//!
+//! The file is keyed by the [`disc_id()`] of the table of contents, a
+//! checkpoint of another disc is ignored. Tracks are written as raw
+//! little-endian samples, like [`Raw`](crate::sink::Raw).
+//!
+//! # Example
+//!
+//! ```
+//! use std::fs::File;
+//!
+//! use cdparanoia::{
+//!     checkpoint::Checkpoint,
+//!     fault::{Fault, FaultyDisc},
+//!     toc::Toc,
+//!     Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+//! };
+//!
+//! let samples: Vec<i16> = (0..2000 * SECTOR_WORDS)
+//!     .map(|i| (i * 7919 % 65521) as i16)
+//!     .collect();
+//! let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())])?;
+//! let toc = Toc::read(&disc)?;
+//! let dir = std::env::temp_dir().join("cdparanoia-checkpoint-example");
+//! std::fs::create_dir_all(&dir)?;
+//! let output = dir.join("track01.raw");
+//! let sidecar = dir.join("track01.checkpoint");
+//! # let _ = std::fs::remove_file(&sidecar);
+//!
+//! // the drive fails in the middle of the track
+//! let broken = FaultyDisc::new(disc.clone(), 42).with_fault(Fault::Unreadable {
+//!     sectors: 1600..=1999,
+//! });
+//! let mut paranoia = Paranoia::new(broken);
+//! let mut checkpoint = Checkpoint::open(&sidecar, &toc)?;
+//! assert!(checkpoint.rip_track(&mut paranoia, 1, &mut File::create(&output)?).is_err());
+//!
+//! // a new session continues where the old one stopped
+//! let mut checkpoint = Checkpoint::open(&sidecar, &toc)?;
+//! let resume_lsn = checkpoint.track(1).unwrap().next_lsn;
+//! assert!((1..=1600).contains(&resume_lsn));
+//!
+//! let mut paranoia = Paranoia::new(disc);
+//! let mut file = File::options().write(true).open(&output)?;
+//! let checksum = checkpoint.rip_track(&mut paranoia, 1, &mut file)?;
+//! assert!(checkpoint.is_complete(1));
+//! checkpoint.remove()?;
+//!
+//! let ripped: Vec<i16> = std::fs::read(&output)?
+//!     .chunks_exact(2)
+//!     .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+//!     .collect();
+//! assert_eq!(ripped, samples);
+//! # let mut expected = cdparanoia::checksum::TrackChecksum::for_track(&toc, 1).unwrap();
+//! # expected.update(&samples);
+//! # assert_eq!(checksum, expected);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    checksum::{Crc32, TrackChecksum},
+    toc::Toc,
+    CdBackend, Error, Paranoia, ParanoiaError, Result, SECTOR_BYTES,
+};
+
+/// The first line of a checkpoint file.
+const HEADER: &str = "cdparanoia-rs checkpoint 1";
+/// Number of sectors between two saves, 10 seconds of audio.
+const SAVE_INTERVAL: u32 = 750;
+
+/// Get an identifier of a disc that is derived from the start sectors of its
+/// tracks and the lead-out.
+///
+/// It consists of the number of tracks, the lead-out sector and a
+/// [`Crc32`] of the start sectors, e.g. `01-0000000a-6e0dd9d5`.
+pub fn disc_id(toc: &Toc) -> String {
+    let mut crc = Crc32::new();
+    for track in &toc.tracks {
+        crc.update_bytes(format!("{} {}\n", track.number, track.first_sector).as_bytes());
+    }
+    let lead_out = toc
+        .tracks
+        .last()
+        .map_or(0, |track| u64::from(track.last_sector) + 1);
+    format!(
+        "{:02}-{lead_out:08x}-{:08x}",
+        toc.tracks.len(),
+        crc.finish()
+    )
+}
+
+/// A file that [`Checkpoint::rip_track()`] writes a track to.
+///
+/// Besides writing, the output must be truncated to the part of the track
+/// that has been saved and flushed to disk before the progress is saved.
+pub trait Output: Write + Seek {
+    /// Get the length of the output in bytes.
+    fn size(&self) -> io::Result<u64>;
+    /// Truncate or extend the output to `len` bytes.
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+    /// Flush the written data to disk.
+    fn sync_data(&mut self) -> io::Result<()>;
+}
+
+impl Output for File {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+    fn sync_data(&mut self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+}
+
+/// The progress of a rip, stored in a sidecar file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    path: PathBuf,
+    disc_id: String,
+    tracks: BTreeMap<u8, TrackProgress>,
+}
+
+/// How far a track has been ripped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackProgress {
+    pub first_lsn: u32,
+    pub last_lsn: u32,
+    /// The first sector that hasn't been ripped yet.
+    pub next_lsn: u32,
+    /// The checksums of `first_lsn..next_lsn`.
+    pub checksum: TrackChecksum,
+}
+
+impl TrackProgress {
+    /// Check if the whole track has been ripped.
+    pub fn is_complete(&self) -> bool {
+        self.next_lsn > self.last_lsn
+    }
+    /// Get the number of bytes of the track that have been ripped.
+    pub fn ripped_bytes(&self) -> u64 {
+        u64::from(self.next_lsn - self.first_lsn) * SECTOR_BYTES as u64
+    }
+}
+
+impl Checkpoint {
+    /// Load the checkpoint file at `path`, or start a new checkpoint if the
+    /// file doesn't exist or belongs to another disc.
+    ///
+    /// Nothing is written until the first track is ripped.
+    pub fn open(path: impl Into<PathBuf>, toc: &Toc) -> Result<Self> {
+        let path = path.into();
+        let disc_id = disc_id(toc);
+        let tracks = match fs::read_to_string(&path) {
+            Ok(content) => parse(&content, &disc_id)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path,
+            disc_id,
+            tracks,
+        })
+    }
+    /// Get the path of the checkpoint file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Get the [`disc_id()`] the checkpoint belongs to.
+    pub fn disc_id(&self) -> &str {
+        &self.disc_id
+    }
+    /// Get the progress of a track, if it has been started.
+    pub fn track(&self, track: u8) -> Option<&TrackProgress> {
+        self.tracks.get(&track)
+    }
+    /// Check if a track has been ripped completely.
+    pub fn is_complete(&self, track: u8) -> bool {
+        self.track(track).is_some_and(TrackProgress::is_complete)
+    }
+    /// Write the checkpoint file.
+    ///
+    /// The file is replaced atomically, so it is never left half-written.
+    pub fn save(&self) -> Result<()> {
+        let mut content = format!("{HEADER}\ndisc {}\n", self.disc_id);
+        for (number, track) in &self.tracks {
+            let state = track.checksum.to_state();
+            // writing to a String can't fail
+            let _ = writeln!(
+                content,
+                "track {number} {} {} {} {}",
+                track.first_lsn,
+                track.last_lsn,
+                track.next_lsn,
+                state.map(|word| format!("{word:08x}")).join(" ")
+            );
+        }
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+    /// Delete the checkpoint file, e.g. after all tracks have been ripped.
+    pub fn remove(self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+    /// Rip a track to `output` as raw little-endian samples, continuing where
+    /// an earlier session stopped.
+    ///
+    /// `output` must contain what has been written for this track in the
+    /// earlier session. It is truncated to the ripped part before ripping
+    /// continues. A complete track isn't read again.
+    ///
+    /// The checkpoint is saved every few seconds of audio, after the track is
+    /// complete and when reading fails. If writing the output fails, the rip
+    /// stops and the checkpoint keeps the progress of the last save.
+    pub fn rip_track<B: CdBackend, W: Output>(
+        &mut self,
+        paranoia: &mut Paranoia<B>,
+        track: u8,
+        output: &mut W,
+    ) -> Result<TrackChecksum> {
+        let toc = Toc::read(paranoia.drive())?;
+        let Some(checksum) = TrackChecksum::for_track(&toc, track) else {
+            return Err(ParanoiaError::TrackNotAudioData.into());
+        };
+        let first_lsn = paranoia.drive().track_first_sector(track)?;
+        let last_lsn = paranoia.drive().track_last_sector(track)?;
+
+        let progress = match self.tracks.get(&track) {
+            Some(progress)
+                if (progress.first_lsn, progress.last_lsn) == (first_lsn, last_lsn)
+                    && output.size()? >= progress.ripped_bytes() =>
+            {
+                progress.clone()
+            }
+            _ => TrackProgress {
+                first_lsn,
+                last_lsn,
+                next_lsn: first_lsn,
+                checksum,
+            },
+        };
+        output.set_len(progress.ripped_bytes())?;
+        output.seek(SeekFrom::End(0))?;
+        self.tracks.insert(track, progress.clone());
+        if progress.is_complete() {
+            return Ok(progress.checksum);
+        }
+
+        let mut progress = progress;
+        let mut reader = paranoia.read_sectors(progress.next_lsn, last_lsn);
+        let mut buffer = Vec::new();
+        let result = loop {
+            let sector = match reader.next_sector() {
+                Some(Ok(sector)) => sector,
+                Some(Err(err)) => break Err(err),
+                None => break Ok(()),
+            };
+            buffer.extend(sector.iter().flat_map(|sample| sample.to_le_bytes()));
+            progress.checksum.update(sector);
+            progress.next_lsn += 1;
+
+            if (progress.next_lsn - first_lsn) % SAVE_INTERVAL == 0 {
+                // after a failed write, the output may contain a part of the
+                // buffer that is cut off again when the rip is resumed
+                self.commit(track, &progress, output, &mut buffer)?;
+            }
+        };
+        let saved = self.commit(track, &progress, output, &mut buffer);
+        result.and(saved)?;
+
+        Ok(progress.checksum)
+    }
+    /// Write the buffered samples and save the progress.
+    fn commit(
+        &mut self,
+        track: u8,
+        progress: &TrackProgress,
+        output: &mut impl Output,
+        buffer: &mut Vec<u8>,
+    ) -> Result<()> {
+        output.write_all(buffer)?;
+        buffer.clear();
+        // the samples must be on disk before the checkpoint claims them
+        output.sync_data()?;
+        self.tracks.insert(track, progress.clone());
+        self.save()
+    }
+}
+
+fn parse(content: &str, disc_id: &str) -> Result<BTreeMap<u8, TrackProgress>> {
+    let invalid = || {
+        Error::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid checkpoint file",
+        ))
+    };
+
+    let mut lines = content.lines();
+    if lines.next() != Some(HEADER) {
+        return Err(invalid());
+    }
+    if lines.next().and_then(|line| line.strip_prefix("disc ")) != Some(disc_id) {
+        // a checkpoint of another disc
+        return Ok(BTreeMap::new());
+    }
+
+    let mut tracks = BTreeMap::new();
+    for line in lines {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let ["track", number, first_lsn, last_lsn, next_lsn, state @ ..] = fields.as_slice() else {
+            return Err(invalid());
+        };
+        let state: Vec<u32> = state
+            .iter()
+            .map(|word| u32::from_str_radix(word, 16))
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| invalid())?;
+        let checksum = state
+            .try_into()
+            .ok()
+            .and_then(TrackChecksum::from_state)
+            .ok_or_else(invalid)?;
+        let progress = TrackProgress {
+            first_lsn: first_lsn.parse().map_err(|_| invalid())?,
+            last_lsn: last_lsn.parse().map_err(|_| invalid())?,
+            next_lsn: next_lsn.parse().map_err(|_| invalid())?,
+            checksum,
+        };
+        if progress.next_lsn < progress.first_lsn
+            || u64::from(progress.next_lsn) > u64::from(progress.last_lsn) + 1
+        {
+            return Err(invalid());
+        }
+        tracks.insert(number.parse().map_err(|_| invalid())?, progress);
+    }
+    Ok(tracks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+    use crate::{
+        testing::{samples, CountingDisc},
+        ParanoiaMode, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+    };
+
+    /// Fails to write once, `fail_at` bytes into the output.
+    struct Interrupted {
+        file: File,
+        fail_at: Option<u64>,
+    }
+
+    impl Write for Interrupted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let Some(fail_at) = self.fail_at else {
+                return self.file.write(buf);
+            };
+            let position = self.file.stream_position()?;
+            if position >= fail_at {
+                self.fail_at = None;
+                return Err(io::Error::other("disk full"));
+            }
+            let len = buf.len().min((fail_at - position) as usize);
+            self.file.write(&buf[..len])
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.file.flush()
+        }
+    }
+
+    impl Seek for Interrupted {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.file.seek(pos)
+        }
+    }
+
+    impl Output for Interrupted {
+        fn size(&self) -> io::Result<u64> {
+            self.file.size()
+        }
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.file.set_len(len)
+        }
+        fn sync_data(&mut self) -> io::Result<()> {
+            self.file.sync_data()
+        }
+    }
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cdparanoia-checkpoint-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read_samples(path: &Path) -> Vec<i16> {
+        fs::read(path)
+            .unwrap()
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn resume_after_kill() {
+        let samples = samples(2000);
+        let disc = VirtualDisc::new([
+            VirtualTrack::new(samples[..SECTOR_WORDS].to_vec()),
+            VirtualTrack::new(samples.clone()),
+        ])
+        .unwrap();
+        let toc = Toc::read(&disc).unwrap();
+        let dir = dir("kill");
+        let output = dir.join("track02.raw");
+        let sidecar = dir.join("track02.checkpoint");
+
+        let mut paranoia = Paranoia::new(CountingDisc::new(disc.clone()).with_limit(1700));
+        paranoia.set_mode(ParanoiaMode::DISABLE);
+        let mut checkpoint = Checkpoint::open(&sidecar, &toc).unwrap();
+        let mut file = File::create(&output).unwrap();
+        let killed = panic::catch_unwind(AssertUnwindSafe(|| {
+            checkpoint.rip_track(&mut paranoia, 2, &mut file)
+        }));
+        assert!(killed.is_err());
+        drop(file);
+        // the kill came after writing samples, but before saving their progress
+        File::options()
+            .append(true)
+            .open(&output)
+            .unwrap()
+            .write_all(&[0xff; 3 * SECTOR_BYTES])
+            .unwrap();
+
+        let mut checkpoint = Checkpoint::open(&sidecar, &toc).unwrap();
+        let progress = checkpoint.track(2).unwrap().clone();
+        assert_eq!(progress.first_lsn, 1);
+        assert_eq!(progress.next_lsn, 1 + 2 * SAVE_INTERVAL);
+        assert!(!checkpoint.is_complete(2));
+
+        // the resumed rip only reads the rest of the track
+        let rest = (progress.last_lsn + 1 - progress.next_lsn) as usize;
+        let mut paranoia = Paranoia::new(CountingDisc::new(disc).with_limit(rest + 100));
+        paranoia.set_mode(ParanoiaMode::DISABLE);
+        let mut file = File::options().write(true).open(&output).unwrap();
+        let checksum = checkpoint.rip_track(&mut paranoia, 2, &mut file).unwrap();
+        assert!(checkpoint.is_complete(2));
+
+        let mut expected = TrackChecksum::for_track(&toc, 2).unwrap();
+        expected.update(&samples);
+        assert_eq!(checksum, expected);
+        assert_eq!(read_samples(&output), samples);
+
+        // a complete track isn't read again
+        let mut checkpoint = Checkpoint::open(&sidecar, &toc).unwrap();
+        assert_eq!(
+            checkpoint.rip_track(&mut paranoia, 2, &mut file).unwrap(),
+            expected
+        );
+        checkpoint.remove().unwrap();
+        assert!(!sidecar.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_keeps_the_last_save() {
+        let samples = samples(2000);
+        let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())]).unwrap();
+        let toc = Toc::read(&disc).unwrap();
+        let dir = dir("write");
+        let output = dir.join("track01.raw");
+        let sidecar = dir.join("track01.checkpoint");
+
+        // the second save fails after writing a part of its buffer
+        let mut paranoia = Paranoia::new(disc.clone());
+        paranoia.set_mode(ParanoiaMode::DISABLE);
+        let mut checkpoint = Checkpoint::open(&sidecar, &toc).unwrap();
+        let mut file = Interrupted {
+            file: File::create(&output).unwrap(),
+            fail_at: Some((SAVE_INTERVAL as u64 + 100) * SECTOR_BYTES as u64 + 17),
+        };
+        assert!(checkpoint.rip_track(&mut paranoia, 1, &mut file).is_err());
+        assert_eq!(checkpoint.track(1).unwrap().next_lsn, SAVE_INTERVAL);
+        drop(file);
+
+        let mut checkpoint = Checkpoint::open(&sidecar, &toc).unwrap();
+        assert_eq!(checkpoint.track(1).unwrap().next_lsn, SAVE_INTERVAL);
+        let mut file = File::options().write(true).open(&output).unwrap();
+        let checksum = checkpoint.rip_track(&mut paranoia, 1, &mut file).unwrap();
+
+        let mut expected = TrackChecksum::for_track(&toc, 1).unwrap();
+        expected.update(&samples);
+        assert_eq!(checksum, expected);
+        assert_eq!(read_samples(&output), samples);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn restart_if_output_is_shorter() {
+        let samples = samples(800);
+        let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())]).unwrap();
+        let toc = Toc::read(&disc).unwrap();
+        let dir = dir("short");
+        let output = dir.join("track01.raw");
+        let sidecar = dir.join("track01.checkpoint");
+
+        let mut paranoia = Paranoia::new(CountingDisc::new(disc.clone()).with_limit(790));
+        paranoia.set_mode(ParanoiaMode::DISABLE);
+        let mut checkpoint = Checkpoint::open(&sidecar, &toc).unwrap();
+        let mut file = File::create(&output).unwrap();
+        let killed = panic::catch_unwind(AssertUnwindSafe(|| {
+            checkpoint.rip_track(&mut paranoia, 1, &mut file)
+        }));
+        assert!(killed.is_err());
+        file.set_len(100).unwrap();
+
+        let mut checkpoint = Checkpoint::open(&sidecar, &toc).unwrap();
+        assert_eq!(checkpoint.track(1).unwrap().next_lsn, SAVE_INTERVAL);
+        let mut paranoia = Paranoia::new(disc);
+        checkpoint.rip_track(&mut paranoia, 1, &mut file).unwrap();
+        assert_eq!(read_samples(&output), samples);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn other_discs_are_ignored() {
+        let dir = dir("other");
+        let sidecar = dir.join("checkpoint");
+        let one = Toc::read(&VirtualDisc::new([VirtualTrack::new(samples(10))]).unwrap()).unwrap();
+        let two = Toc::read(&VirtualDisc::new([VirtualTrack::new(samples(11))]).unwrap()).unwrap();
+        assert_ne!(disc_id(&one), disc_id(&two));
+        assert_eq!(disc_id(&one), "01-0000000a-6e0dd9d5");
+
+        let mut checkpoint = Checkpoint::open(&sidecar, &one).unwrap();
+        let mut paranoia =
+            Paranoia::new(VirtualDisc::new([VirtualTrack::new(samples(10))]).unwrap());
+        let mut file = File::create(dir.join("track01.raw")).unwrap();
+        checkpoint.rip_track(&mut paranoia, 1, &mut file).unwrap();
+
+        assert!(Checkpoint::open(&sidecar, &one).unwrap().is_complete(1));
+        assert!(Checkpoint::open(&sidecar, &two).unwrap().track(1).is_none());
+
+        fs::write(
+            &sidecar,
+            format!("{HEADER}\ndisc {}\ntrack 1\n", disc_id(&one)),
+        )
+        .unwrap();
+        assert!(Checkpoint::open(&sidecar, &one).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Checksums of ripped audio, as used in rip logs.
+//!
+//! [`Crc32`] is the CRC that EAC and XLD print as test and copy CRC,
+//! [`AccurateRip`] computes the v1 and v2 checksums that are looked up in the
+//! AccurateRip database. [`TrackChecksum`] computes both, together with the
+//! CRC that CTDB uses and the peak level, while a track is read.
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{checksum::TrackChecksum, toc::Toc, Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS};
+//!
+//! let disc = VirtualDisc::new([
+//!     VirtualTrack::new(vec![1000; 750 * SECTOR_WORDS]),
+//!     VirtualTrack::new(vec![-2000; 750 * SECTOR_WORDS]),
+//! ])?;
+//! let toc = Toc::read(&disc)?;
+//! let mut paranoia = Paranoia::new(disc);
+//!
+//! let mut checksum = TrackChecksum::for_track(&toc, 2).unwrap();
+//! for sector in paranoia.read_track(2)? {
+//!     checksum.update(&sector?);
+//! }
+//! assert_eq!(checksum.peak(), 2000);
+//! assert_eq!(checksum.crc(), 0xa80c_0500);
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use crate::toc::Toc;
+
+/// Number of stereo frames in a sector.
+const SECTOR_FRAMES: u32 = 588;
+/// Number of stereo frames at the start of the first track and the end of
+/// the last track that are left out of the AccurateRip checksums.
+const ACCURATERIP_SKIP_FRAMES: u32 = 5 * SECTOR_FRAMES;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC-32 (IEEE 802.3) of little-endian audio data.
+///
+/// ```
+/// use cdparanoia::checksum::Crc32;
+///
+/// let mut crc = Crc32::new();
+/// crc.update_bytes(b"123456789");
+/// assert_eq!(crc.finish(), 0xcbf4_3926);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self(u32::MAX)
+    }
+    /// Add raw bytes.
+    pub fn update_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = CRC32_TABLE[usize::from(self.0 as u8 ^ byte)] ^ (self.0 >> 8);
+        }
+    }
+    /// Add samples, which are hashed as little-endian bytes.
+    pub fn update(&mut self, samples: &[i16]) {
+        for sample in samples {
+            self.update_bytes(&sample.to_le_bytes());
+        }
+    }
+    /// Get the CRC of everything that has been added so far.
+    pub const fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The AccurateRip v1 and v2 checksums of a track.
+///
+/// The first 2939 stereo frames of the first track and the last 2940 stereo
+/// frames of the last track are left out, because drives with a different
+/// read offset can't read them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AccurateRip {
+    v1: u32,
+    v2: u32,
+    /// The 1-based position of the next stereo frame.
+    position: u32,
+    first_position: u32,
+    last_position: u32,
+}
+
+impl AccurateRip {
+    /// Prepare the checksums of a track with this many sectors.
+    pub fn new(sectors: u32, first_track: bool, last_track: bool) -> Self {
+        let frames = sectors * SECTOR_FRAMES;
+        Self {
+            v1: 0,
+            v2: 0,
+            position: 1,
+            first_position: if first_track {
+                ACCURATERIP_SKIP_FRAMES
+            } else {
+                1
+            },
+            last_position: if last_track {
+                frames.saturating_sub(ACCURATERIP_SKIP_FRAMES)
+            } else {
+                frames
+            },
+        }
+    }
+    /// Add interleaved stereo samples.
+    ///
+    /// A trailing left sample without its right sample is ignored.
+    pub fn update(&mut self, samples: &[i16]) {
+        for frame in samples.chunks_exact(2) {
+            if (self.first_position..=self.last_position).contains(&self.position) {
+                let value = u32::from(frame[0] as u16) | u32::from(frame[1] as u16) << 16;
+                self.v1 = self.v1.wrapping_add(value.wrapping_mul(self.position));
+                let product = u64::from(value) * u64::from(self.position);
+                self.v2 = self
+                    .v2
+                    .wrapping_add(product as u32)
+                    .wrapping_add((product >> 32) as u32);
+            }
+            self.position += 1;
+        }
+    }
+    /// Get the v1 checksum.
+    pub const fn v1(&self) -> u32 {
+        self.v1
+    }
+    /// Get the v2 checksum.
+    pub const fn v2(&self) -> u32 {
+        self.v2
+    }
+}
+
+/// The CRCs, AccurateRip checksums and peak level of a track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TrackChecksum {
+    crc: Crc32,
+    ctdb: Crc32,
+    accuraterip: AccurateRip,
+    peak: u16,
+}
+
+impl TrackChecksum {
+    /// Prepare the checksums of a track with this many sectors,
+    /// see [`AccurateRip::new()`].
+    pub fn new(sectors: u32, first_track: bool, last_track: bool) -> Self {
+        Self {
+            crc: Crc32::new(),
+            ctdb: Crc32::new(),
+            accuraterip: AccurateRip::new(sectors, first_track, last_track),
+            peak: 0,
+        }
+    }
+    /// Prepare the checksums of an audio track of a disc.
+    ///
+    /// Returns `None` if the track doesn't exist or isn't an audio track.
+    pub fn for_track(toc: &Toc, track: u8) -> Option<Self> {
+        let mut audio_tracks = toc.audio_tracks().peekable();
+        let first = audio_tracks.peek()?.number;
+        let last = audio_tracks.last()?.number;
+        let entry = toc.audio_tracks().find(|entry| entry.number == track)?;
+        Some(Self::new(entry.sectors(), track == first, track == last))
+    }
+    /// Add interleaved stereo samples, usually a sector.
+    pub fn update(&mut self, samples: &[i16]) {
+        self.crc.update(samples);
+        self.ctdb.update(self.ctdb_samples(samples));
+        self.accuraterip.update(samples);
+        self.peak = samples
+            .iter()
+            .map(|sample| sample.unsigned_abs())
+            .fold(self.peak, u16::max);
+    }
+    /// Get the CRC-32 of all samples, like the test and copy CRC of EAC.
+    pub const fn crc(&self) -> u32 {
+        self.crc.finish()
+    }
+    /// Get the CRC-32 that CTDB stores for the track.
+    ///
+    /// Like [`crc()`](Self::crc), but without the first 5 sectors of the
+    /// first track and the last 5 sectors of the last track.
+    pub const fn ctdb_crc(&self) -> u32 {
+        self.ctdb.finish()
+    }
+    /// Get the AccurateRip checksums.
+    pub const fn accuraterip(&self) -> AccurateRip {
+        self.accuraterip
+    }
+    /// Get the largest absolute sample value, from 0 to 32768.
+    pub const fn peak(&self) -> u16 {
+        self.peak
+    }
+    /// Get the part of `samples` that is part of the CTDB CRC.
+    ///
+    /// `samples` start at the next stereo frame of the AccurateRip checksums,
+    /// which leave out one frame less at the start of the first track.
+    fn ctdb_samples<'a>(&self, samples: &'a [i16]) -> &'a [i16] {
+        let AccurateRip {
+            position,
+            first_position,
+            last_position,
+            ..
+        } = self.accuraterip;
+        let first = if first_position > 1 {
+            first_position + 1
+        } else {
+            first_position
+        };
+        let frames = samples.len() / 2;
+        let start = (first.saturating_sub(position) as usize).min(frames);
+        let end = ((u64::from(last_position) + 1).saturating_sub(position.into()) as usize)
+            .clamp(start, frames);
+        &samples[2 * start..2 * end]
+    }
+    /// Get the running state, so it can be stored in a checkpoint.
+    pub(crate) fn to_state(self) -> [u32; 8] {
+        let AccurateRip {
+            v1,
+            v2,
+            position,
+            first_position,
+            last_position,
+        } = self.accuraterip;
+        [
+            self.crc.0,
+            self.ctdb.0,
+            v1,
+            v2,
+            position,
+            first_position,
+            last_position,
+            self.peak.into(),
+        ]
+    }
+    /// Restore the running state from [`to_state()`](Self::to_state).
+    pub(crate) fn from_state(state: [u32; 8]) -> Option<Self> {
+        let [crc, ctdb, v1, v2, position, first_position, last_position, peak] = state;
+        Some(Self {
+            crc: Crc32(crc),
+            ctdb: Crc32(ctdb),
+            accuraterip: AccurateRip {
+                v1,
+                v2,
+                position,
+                first_position,
+                last_position,
+            },
+            peak: peak.try_into().ok()?,
+        })
+    }
+}
@@ -0,0 +1,581 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Per-sector status of a disc, for deciding whether it needs cleaning.
+//!
+//! While reading, paranoia reports [`Event`]s like corrected jitter, failed
+//! reads or skipped data (see [`DiscReader::events()`]). A [`DamageMap`]
+//! condenses the events of every sector into a [`SectorStatus`] and exports
+//! them as CSV, JSON or a heat strip image (SVG or PNG) in which the disc is
+//! laid out by playing time or by radius, so damaged areas can be located on
+//! the disc.
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{
+//!     damage::{DamageMap, Layout, SectorStatus},
+//!     fault::{Fault, FaultyDisc},
+//!     Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+//! };
+//!
+//! let samples: Vec<i16> = (0..40 * SECTOR_WORDS as i32)
+//!     .map(|i| (i * 7919 % 65521) as i16)
+//!     .collect();
+//! let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())])?;
+//! let disc = FaultyDisc::new(disc, 42).with_fault(Fault::Unstable {
+//!     sectors: 20..=21,
+//!     failures: 3,
+//! });
+//! let mut paranoia = Paranoia::new(disc);
+//!
+//! let mut map = DamageMap::new(0, 39);
+//! let ripped = map.read(paranoia.read_track(1)?)?;
+//! assert_eq!(ripped, samples);
+//! assert_eq!(map.get(0), Some(SectorStatus::Clean));
+//! assert!(matches!(map.get(20), Some(SectorStatus::Reread(_))));
+//!
+//! let csv = map.to_csv();
+//! assert_eq!(csv.lines().nth(1), Some("0,clean,0"));
+//! let svg = map.to_svg(Layout::Time);
+//! assert!(svg.starts_with("<svg"));
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::{
+    fmt::{self, Display, Write as _},
+    ops::RangeInclusive,
+};
+
+use serde_json::{json, Map, Value};
+
+use crate::{checksum::Crc32, CdBackend, DiscReader, Result};
+
+/// Radius at which the program area starts, in millimeters.
+const INNER_RADIUS: f64 = 25.0;
+/// Radius at which the program area of a full disc ends, in millimeters.
+const OUTER_RADIUS: f64 = 58.5;
+/// Distance between two turns of the spiral, in millimeters.
+const TRACK_PITCH: f64 = 0.0016;
+/// Scanning velocity at 1x speed, in millimeters per second.
+const SCANNING_VELOCITY: f64 = 1300.0;
+/// Number of sectors per second at 1x speed.
+const SECTORS_PER_SECOND: f64 = 75.0;
+/// Size of the heat strip in pixels.
+const STRIP_WIDTH: usize = 1024;
+const STRIP_HEIGHT: usize = 48;
+/// Color of sectors that haven't been read.
+const UNREAD_COLOR: [u8; 3] = [0xd9, 0xd9, 0xd9];
+
+/// Something that happened while reading a sector.
+///
+/// These correspond to the `PARANOIA_CB_*` callback codes of
+/// libcdio-paranoia/cdparanoia-3. The Rust engine reports the subset that
+/// applies to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// Data has been read from the drive.
+    Read,
+    /// Two reads have been compared successfully.
+    Verify,
+    /// Data at the edge of a read has been corrected.
+    FixupEdge,
+    /// Data inside a read has been corrected.
+    FixupAtom,
+    /// A scratch has been detected.
+    Scratch,
+    /// Data in a scratch has been reconstructed.
+    Repair,
+    /// Unverified data has been accepted after the retries were exhausted.
+    Skip,
+    /// Reads have been aligned with a jitter offset.
+    Drift,
+    /// A sector has been read again because its data couldn't be verified.
+    Backoff,
+    /// The jitter window has been changed.
+    Overlap,
+    /// Dropped samples have been corrected.
+    FixupDropped,
+    /// Duplicated samples have been corrected.
+    FixupDuped,
+    /// The drive reported a read error.
+    ReadError,
+    /// The drive's cache couldn't be defeated.
+    CacheError,
+}
+
+/// The condition of a sector, derived from the [`Event`]s of reading it.
+///
+/// The variants are ordered by severity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SectorStatus {
+    /// The sector has been verified without any corrections.
+    Clean,
+    /// The sector has been verified after correcting jitter or
+    /// dropped/duplicated samples.
+    JitterCorrected,
+    /// The sector has been verified after reading it again this many times.
+    Reread(u32),
+    /// The sector couldn't be verified and has been reconstructed from all
+    /// reads.
+    ScratchRepaired,
+    /// The sector couldn't be verified and unverified data has been used,
+    /// or it couldn't be read at all.
+    Skipped,
+}
+
+/// How a heat strip is laid out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Layout {
+    /// Proportional to the distance from the center of the disc.
+    ///
+    /// The strip always covers the whole program area of a 74-minute disc,
+    /// from the inside to the outside, so the position of damage on the
+    /// strip is its position on the disc.
+    #[default]
+    Radius,
+    /// Proportional to the playing time of the mapped sectors.
+    Time,
+}
+
+/// The [`SectorStatus`] of every sector in a range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DamageMap {
+    first_lsn: u32,
+    sectors: Vec<Option<SectorStatus>>,
+}
+
+impl Event {
+    /// Convert a `PARANOIA_CB_*` code of the C libraries.
+    #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+    pub(crate) fn from_code(code: i64) -> Option<Self> {
+        Some(match code {
+            0 => Event::Read,
+            1 => Event::Verify,
+            2 => Event::FixupEdge,
+            3 => Event::FixupAtom,
+            4 => Event::Scratch,
+            5 => Event::Repair,
+            6 => Event::Skip,
+            7 => Event::Drift,
+            8 => Event::Backoff,
+            9 => Event::Overlap,
+            10 => Event::FixupDropped,
+            11 => Event::FixupDuped,
+            12 => Event::ReadError,
+            13 => Event::CacheError,
+            _ => return None,
+        })
+    }
+}
+
+impl SectorStatus {
+    /// Get the status of a sector from the events of reading it.
+    pub fn from_events(events: &[Event]) -> Self {
+        let count =
+            |kinds: &[Event]| events.iter().filter(|event| kinds.contains(event)).count() as u32;
+
+        if count(&[Event::Skip]) > 0 {
+            SectorStatus::Skipped
+        } else if count(&[Event::Scratch, Event::Repair]) > 0 {
+            SectorStatus::ScratchRepaired
+        } else if let rereads @ 1.. = count(&[Event::Backoff, Event::ReadError]) {
+            SectorStatus::Reread(rereads)
+        } else if count(&[
+            Event::Drift,
+            Event::FixupEdge,
+            Event::FixupAtom,
+            Event::FixupDropped,
+            Event::FixupDuped,
+        ]) > 0
+        {
+            SectorStatus::JitterCorrected
+        } else {
+            SectorStatus::Clean
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            SectorStatus::Clean => "clean",
+            SectorStatus::JitterCorrected => "jitter-corrected",
+            SectorStatus::Reread(_) => "reread",
+            SectorStatus::ScratchRepaired => "scratch-repaired",
+            SectorStatus::Skipped => "skipped",
+        }
+    }
+    fn rereads(self) -> u32 {
+        match self {
+            SectorStatus::Reread(rereads) => rereads,
+            _ => 0,
+        }
+    }
+    fn color(self) -> [u8; 3] {
+        match self {
+            SectorStatus::Clean => [0x1a, 0x98, 0x50],
+            SectorStatus::JitterCorrected => [0xa6, 0xd9, 0x6a],
+            SectorStatus::Reread(1..=2) => [0xfe, 0xe0, 0x8b],
+            SectorStatus::Reread(_) => [0xfd, 0xae, 0x61],
+            SectorStatus::ScratchRepaired => [0xd7, 0x30, 0x27],
+            SectorStatus::Skipped => [0x1a, 0x1a, 0x1a],
+        }
+    }
+}
+
+impl Display for SectorStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SectorStatus::Reread(rereads) => write!(f, "re-read {rereads} times"),
+            status => f.write_str(status.name()),
+        }
+    }
+}
+
+impl DamageMap {
+    /// Create a map of the sectors `first_lsn..=last_lsn`, none of which
+    /// have been read yet.
+    pub fn new(first_lsn: u32, last_lsn: u32) -> Self {
+        let len = last_lsn.saturating_sub(first_lsn) as usize + 1;
+        Self {
+            first_lsn,
+            sectors: vec![None; len],
+        }
+    }
+    pub fn first_lsn(&self) -> u32 {
+        self.first_lsn
+    }
+    pub fn last_lsn(&self) -> u32 {
+        self.first_lsn + self.sectors.len() as u32 - 1
+    }
+    /// Get the status of a sector, if it has been read.
+    pub fn get(&self, lsn: u32) -> Option<SectorStatus> {
+        let index = lsn.checked_sub(self.first_lsn)? as usize;
+        self.sectors.get(index).copied().flatten()
+    }
+    /// Iterate over all sectors and their status.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, Option<SectorStatus>)> + '_ {
+        (self.first_lsn..).zip(self.sectors.iter().copied())
+    }
+    /// Get the most severe status of all sectors that have been read.
+    pub fn worst(&self) -> Option<SectorStatus> {
+        self.sectors.iter().flatten().max().copied()
+    }
+    /// Set the status of a sector, replacing an earlier one.
+    ///
+    /// Sectors outside of the map are ignored.
+    pub fn record(&mut self, lsn: u32, status: SectorStatus) {
+        if let Some(sector) = lsn
+            .checked_sub(self.first_lsn)
+            .and_then(|index| self.sectors.get_mut(index as usize))
+        {
+            *sector = Some(status);
+        }
+    }
+    /// Set the status of a sector from the events of reading it,
+    /// see [`SectorStatus::from_events()`].
+    pub fn record_events(&mut self, lsn: u32, events: &[Event]) {
+        self.record(lsn, SectorStatus::from_events(events));
+    }
+    /// Read all remaining sectors of a [`DiscReader`] and record their status.
+    ///
+    /// A sector that can't be read is recorded as
+    /// [`Skipped`](SectorStatus::Skipped) before the error is returned.
+    pub fn read<B: CdBackend>(&mut self, mut reader: DiscReader<'_, B>) -> Result<Vec<i16>> {
+        let mut samples = Vec::new();
+        loop {
+            let lsn = reader.current_lsn();
+            match reader.next_sector() {
+                Some(Ok(sector)) => samples.extend_from_slice(sector),
+                Some(Err(err)) => {
+                    self.record(lsn, SectorStatus::Skipped);
+                    return Err(err);
+                }
+                None => return Ok(samples),
+            }
+            self.record_events(lsn, reader.events());
+        }
+    }
+    /// Export the map as CSV with one line per sector.
+    ///
+    /// The columns are `lsn`, `status` (`clean`, `jitter-corrected`,
+    /// `reread`, `scratch-repaired`, `skipped` or `unread`) and `rereads`.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("lsn,status,rereads\n");
+        for (lsn, status) in self.iter() {
+            // writing to a String can't fail
+            let _ = writeln!(
+                csv,
+                "{lsn},{},{}",
+                status.map_or("unread", SectorStatus::name),
+                status.map_or(0, SectorStatus::rereads)
+            );
+        }
+        csv
+    }
+    /// Export the map as JSON, with the number of sectors per status and
+    /// runs of consecutive sectors with the same status.
+    pub fn to_json(&self) -> String {
+        const STATUSES: [&str; 6] = [
+            "clean",
+            "jitter-corrected",
+            "reread",
+            "scratch-repaired",
+            "skipped",
+            "unread",
+        ];
+        let mut counts = [0u32; STATUSES.len()];
+        for status in &self.sectors {
+            let name = status.map_or("unread", SectorStatus::name);
+            if let Some(i) = STATUSES.iter().position(|&status| status == name) {
+                counts[i] += 1;
+            }
+        }
+        let summary: Map<String, Value> = STATUSES
+            .into_iter()
+            .zip(counts)
+            .map(|(name, count)| (name.to_owned(), count.into()))
+            .collect();
+
+        let runs: Vec<Value> = self
+            .runs()
+            .into_iter()
+            .map(|(sectors, status)| {
+                json!({
+                    "first_lsn": sectors.start(),
+                    "last_lsn": sectors.end(),
+                    "status": status.map_or("unread", SectorStatus::name),
+                    "rereads": status.map(SectorStatus::rereads),
+                })
+            })
+            .collect();
+
+        format!(
+            "{:#}",
+            json!({
+                "first_lsn": self.first_lsn,
+                "last_lsn": self.last_lsn(),
+                "summary": summary,
+                "runs": runs,
+            })
+        )
+    }
+    /// Render the map as an SVG heat strip.
+    ///
+    /// Every column shows the most severe status of its sectors. Sectors that
+    /// haven't been read are gray, areas of the disc outside of the map are
+    /// left empty.
+    pub fn to_svg(&self, layout: Layout) -> String {
+        let columns = self.columns(layout);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{STRIP_WIDTH}\" \
+             height=\"{STRIP_HEIGHT}\" viewBox=\"0 0 {} {STRIP_HEIGHT}\" \
+             preserveAspectRatio=\"none\" shape-rendering=\"crispEdges\">\n",
+            columns.len()
+        );
+        let mut x = 0;
+        for run in columns.chunk_by(|a, b| a == b) {
+            if let Some(color) = run[0] {
+                let [r, g, b] = color;
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{x}\" y=\"0\" width=\"{}\" height=\"{STRIP_HEIGHT}\" \
+                     fill=\"#{r:02x}{g:02x}{b:02x}\"/>",
+                    run.len()
+                );
+            }
+            x += run.len();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+    /// Render the map as a PNG heat strip, see [`to_svg()`](Self::to_svg).
+    ///
+    /// Areas outside of the map are white.
+    pub fn to_png(&self, layout: Layout) -> Vec<u8> {
+        let columns = self.columns(layout);
+        let row: Vec<u8> =
+            std::iter::once(0) // no filter
+                .chain(columns.iter().flat_map(|color| color.unwrap_or([0xff; 3])))
+                .collect();
+        let pixels = row.repeat(STRIP_HEIGHT);
+
+        let mut header = Vec::new();
+        header.extend((columns.len() as u32).to_be_bytes());
+        header.extend((STRIP_HEIGHT as u32).to_be_bytes());
+        // 8 bit RGB, default compression/filter, not interlaced
+        header.extend([8, 2, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        write_chunk(&mut png, b"IHDR", &header);
+        write_chunk(&mut png, b"IDAT", &zlib_stored(&pixels));
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+    /// Get runs of consecutive sectors with the same status.
+    fn runs(&self) -> Vec<(RangeInclusive<u32>, Option<SectorStatus>)> {
+        let mut runs: Vec<(RangeInclusive<u32>, Option<SectorStatus>)> = Vec::new();
+        for (lsn, status) in self.iter() {
+            match runs.last_mut() {
+                Some((sectors, last)) if *last == status => *sectors = *sectors.start()..=lsn,
+                _ => runs.push((lsn..=lsn, status)),
+            }
+        }
+        runs
+    }
+    /// Get the color of every column of a heat strip, or `None` for columns
+    /// without any sectors.
+    fn columns(&self, layout: Layout) -> Vec<Option<[u8; 3]>> {
+        let width = match layout {
+            Layout::Radius => STRIP_WIDTH,
+            Layout::Time => STRIP_WIDTH.min(self.sectors.len()),
+        };
+        let mut columns: Vec<Option<Option<SectorStatus>>> = vec![None; width];
+        for (index, status) in self.sectors.iter().enumerate() {
+            let position = match layout {
+                Layout::Radius => {
+                    let lsn = f64::from(self.first_lsn) + index as f64;
+                    (radius(lsn) - INNER_RADIUS) / (OUTER_RADIUS - INNER_RADIUS)
+                }
+                Layout::Time => index as f64 / self.sectors.len() as f64,
+            };
+            let column = ((position * width as f64) as usize).min(width - 1);
+            // unread sectors are less severe than any read ones
+            columns[column] = columns[column].max(Some(*status));
+        }
+        columns
+            .into_iter()
+            .map(|column| column.map(|status| status.map_or(UNREAD_COLOR, SectorStatus::color)))
+            .collect()
+    }
+}
+
+/// Get the approximate distance of a sector from the center of the disc in
+/// millimeters.
+///
+/// The spiral starts at the inner radius and has a constant track pitch, so
+/// the area it covers grows linearly with the playing time.
+fn radius(lsn: f64) -> f64 {
+    let length = SCANNING_VELOCITY * lsn / SECTORS_PER_SECOND;
+    (INNER_RADIUS * INNER_RADIUS + length * TRACK_PITCH / std::f64::consts::PI).sqrt()
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let mut crc = Crc32::new();
+    crc.update_bytes(&png[start..]);
+    png.extend(crc.finish().to_be_bytes());
+}
+
+/// Wrap data in a zlib stream without compression.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        zlib.push(u8::from(blocks.peek().is_none()));
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    zlib.extend(((b << 16) | a).to_be_bytes());
+    zlib
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fault::{Fault, FaultyDisc},
+        testing::samples,
+        Paranoia, ParanoiaMode, VirtualDisc, VirtualTrack,
+    };
+
+    fn map(fault: Fault, mode: ParanoiaMode) -> DamageMap {
+        let disc = VirtualDisc::new([VirtualTrack::new(samples(40))]).unwrap();
+        let mut paranoia = Paranoia::new(FaultyDisc::new(disc, 42).with_fault(fault));
+        paranoia.set_mode(mode);
+
+        let mut map = DamageMap::new(0, 39);
+        map.read(paranoia.read_track_limited(1, 5).unwrap())
+            .unwrap();
+        map
+    }
+
+    fn assert_clean_except(map: &DamageMap, damaged: RangeInclusive<u32>) {
+        // the reads of the engine that include damaged sectors are 16 sectors
+        // long, so their neighbors may need corrections as well
+        for (lsn, status) in map.iter() {
+            if lsn + 16 <= *damaged.start() || lsn >= *damaged.end() + 16 {
+                assert_eq!(status, Some(SectorStatus::Clean), "sector {lsn}");
+            }
+        }
+    }
+
+    #[test]
+    fn clean_disc() {
+        let map = map(Fault::Unreadable { sectors: 100..=100 }, ParanoiaMode::FULL);
+        assert_eq!(map.worst(), Some(SectorStatus::Clean));
+    }
+
+    #[test]
+    fn rereads() {
+        let map = map(
+            Fault::Unstable {
+                sectors: 20..=20,
+                failures: 3,
+            },
+            ParanoiaMode::FULL ^ ParanoiaMode::NEVERSKIP,
+        );
+        assert!(matches!(map.get(20), Some(SectorStatus::Reread(_))));
+        assert_clean_except(&map, 20..=20);
+    }
+
+    #[test]
+    fn scratches_are_repaired_from_several_reads() {
+        let map = map(
+            Fault::Unstable {
+                sectors: 20..=20,
+                failures: 100,
+            },
+            ParanoiaMode::FULL ^ ParanoiaMode::NEVERSKIP,
+        );
+        assert_eq!(map.get(20), Some(SectorStatus::ScratchRepaired));
+        assert_clean_except(&map, 20..=20);
+    }
+
+    #[test]
+    fn scratches_are_skipped_without_repair() {
+        let map = map(
+            Fault::Unstable {
+                sectors: 20..=20,
+                failures: 100,
+            },
+            ParanoiaMode::FULL ^ ParanoiaMode::NEVERSKIP ^ ParanoiaMode::REPAIR,
+        );
+        assert_eq!(map.get(20), Some(SectorStatus::Skipped));
+        assert_clean_except(&map, 20..=20);
+    }
+
+    #[test]
+    fn unreadable_sectors_are_skipped() {
+        let map = map(
+            Fault::Unreadable { sectors: 20..=21 },
+            ParanoiaMode::FULL ^ ParanoiaMode::NEVERSKIP,
+        );
+        // there is no read to repair them from
+        assert_eq!(map.get(20), Some(SectorStatus::Skipped));
+        assert_eq!(map.get(21), Some(SectorStatus::Skipped));
+        assert_clean_except(&map, 20..=21);
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    path::PathBuf,
+};
+
+/// A CD-ROM drive as returned by [`Drive::list()`](crate::Drive::list).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DriveInfo {
+    /// The device path that can be passed to [`Drive::open()`](crate::Drive::open).
+    pub path: PathBuf,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub revision: Option<String>,
+    /// Whether a disc with a readable table of contents is in the drive.
+    pub disc_present: bool,
+    /// Whether the disc has at least one audio track.
+    pub audio: bool,
+}
+
+impl DriveInfo {
+    /// Get vendor, model and revision separated by spaces,
+    /// e.g. `"PLEXTOR DVDR PX-716A 1.11"`.
+    pub fn name(&self) -> String {
+        [&self.vendor, &self.model, &self.revision]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl Display for DriveInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        let name = self.name();
+        if !name.is_empty() {
+            write!(f, ": {name}")?;
+        }
+        match (self.disc_present, self.audio) {
+            (false, _) => write!(f, " (no disc)"),
+            (true, false) => write!(f, " (no audio tracks)"),
+            (true, true) => Ok(()),
+        }
+    }
+}
+
+/// Split a model string like `"VENDOR MODEL REV"` into its parts.
+///
+/// Strings with fewer than three words are returned as the model.
+#[cfg(all(feature = "cdparanoia-3", not(feature = "libcdio-paranoia")))]
+pub(crate) fn split_model(model: &str) -> (Option<String>, Option<String>, Option<String>) {
+    let words: Vec<_> = model.split_whitespace().collect();
+    match words.as_slice() {
+        [] => (None, None, None),
+        [vendor, model @ .., revision] if !model.is_empty() => (
+            Some(vendor.to_string()),
+            Some(model.join(" ")),
+            Some(revision.to_string()),
+        ),
+        _ => (None, Some(words.join(" ")), None),
+    }
+}
+
+/// Remove paths that point to the same device, e.g. `/dev/cdrom` and
+/// `/dev/sr0`, keeping the first one.
+pub(crate) fn dedup_devices(paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    paths
+        .into_iter()
+        .filter(|path| seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())))
+        .collect()
+}
@@ -0,0 +1,701 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Verification engine written in Rust.
+//!
+//! This follows the approach of cdparanoia: Data is read in overlapping
+//! blocks. Regions on which two reads agree (possibly at different offsets
+//! due to jitter) become verified fragments. The verified "root" is only
+//! started from a fragment on which two reads agree at the same position,
+//! so a single shifted read can't move the output. Further fragments are
+//! appended to the root after aligning them with its end, which corrects
+//! jitter and dropped/duplicated samples between reads. Regions that can't be
+//! verified within the retry limit are skipped, optionally reconstructed by a
+//! majority vote over all reads.
+
+use std::{collections::HashMap, collections::VecDeque, fmt::Debug};
+
+use crate::{damage::Event, CdBackend, Error, ParanoiaError, ParanoiaMode, Result, SECTOR_WORDS};
+
+/// Number of 16-bit samples in a raw audio sector.
+const W: i64 = SECTOR_WORDS as i64;
+/// Number of sectors requested from the backend per read.
+const READ_SECTORS: usize = 16;
+/// Minimum number of words two reads must agree on to be verified.
+const MIN_WORDS_VERIFY: usize = 64;
+/// Number of words at the end of the root that new data is aligned to.
+const MIN_WORDS_OVERLAP: usize = 64;
+/// Initial jitter window in words.
+const MIN_OVERLAP: i64 = W;
+/// Maximum jitter window in words.
+const MAX_OVERLAP: i64 = 4 * W;
+/// Number of raw reads that are kept for verification and repair.
+const CACHED_READS: usize = 8;
+/// Number of words of a read that are used to guess offsets to another read.
+const ANCHORS: usize = 32;
+/// Maximum number of offsets that are tried when comparing two reads.
+const MAX_CANDIDATES: usize = 16;
+
+/// A contiguous run of words, starting at an absolute word position.
+#[derive(Debug, Clone, Default)]
+struct Block {
+    begin: i64,
+    data: Vec<i16>,
+}
+
+/// A region on which two reads agree.
+#[derive(Debug, Clone)]
+struct Fragment {
+    block: Block,
+    /// Whether both reads placed the data at the same position, i.e. its
+    /// position is verified as well.
+    placed: bool,
+}
+
+impl Block {
+    fn end(&self) -> i64 {
+        self.begin + self.data.len() as i64
+    }
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    fn get(&self, pos: i64) -> Option<i16> {
+        usize::try_from(pos - self.begin)
+            .ok()
+            .and_then(|index| self.data.get(index).copied())
+    }
+}
+
+pub(crate) struct Engine {
+    mode: ParanoiaMode,
+    /// Verified, contiguous data.
+    root: Block,
+    /// Whether new data has to be aligned with the end of the root.
+    /// This is not the case after unverified data has been skipped.
+    anchored: bool,
+    /// The most recent raw reads.
+    reads: VecDeque<Block>,
+    /// The next sector to return.
+    ///
+    /// This is a `u64`, so it can move past the sector at `u32::MAX`.
+    cursor: u64,
+    /// Current jitter window in words.
+    overlap: i64,
+    sector: Vec<i16>,
+    /// What happened while reading the last sector.
+    events: Vec<Event>,
+}
+
+impl Debug for Engine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Engine")
+            .field("mode", &self.mode)
+            .field("root", &(self.root.begin..self.root.end()))
+            .field("reads", &self.reads.len())
+            .field("cursor", &self.cursor)
+            .field("overlap", &self.overlap)
+            .finish()
+    }
+}
+
+impl Engine {
+    pub(crate) fn new() -> Self {
+        Self {
+            mode: ParanoiaMode::FULL,
+            root: Block::default(),
+            anchored: false,
+            reads: VecDeque::new(),
+            cursor: 0,
+            overlap: MIN_OVERLAP,
+            sector: Vec::with_capacity(SECTOR_WORDS),
+            events: Vec::new(),
+        }
+    }
+    pub(crate) fn set_mode(&mut self, mode: ParanoiaMode) {
+        self.mode = mode;
+    }
+    pub(crate) fn seek(&mut self, lsn: u32) {
+        self.cursor = lsn.into();
+        self.events.clear();
+        let pos = i64::from(lsn) * W;
+        if !(self.root.begin..=self.root.end()).contains(&pos) {
+            self.reset();
+        }
+    }
+    fn reset(&mut self) {
+        self.root = Block::default();
+        self.anchored = false;
+        self.reads.clear();
+    }
+    /// Get the events of the last call to [`read_sector()`](Self::read_sector).
+    pub(crate) fn events(&self) -> &[Event] {
+        &self.events
+    }
+}
+
+impl Engine {
+    pub(crate) fn read_sector<B: CdBackend + ?Sized>(
+        &mut self,
+        backend: &mut B,
+        max_retries: i32,
+    ) -> Result<&[i16]> {
+        self.events.clear();
+        let Ok(lsn) = u32::try_from(self.cursor) else {
+            return Err(ParanoiaError::UnaddressableSector.into());
+        };
+        let begin = i64::from(lsn) * W;
+        let end = begin + W;
+
+        if self.mode == ParanoiaMode::DISABLE {
+            self.sector.resize(SECTOR_WORDS, 0);
+            let read = backend.read_raw(lsn, &mut self.sector);
+            if !matches!(read, Ok(1..)) {
+                self.events.push(Event::ReadError);
+                return Err(read.err().unwrap_or(Error::Read));
+            }
+            self.events.push(Event::Read);
+            self.cursor += 1;
+            return Ok(&self.sector);
+        }
+
+        if !self.root.is_empty() && (self.root.begin > begin || self.root.end() < begin) {
+            self.reset();
+        }
+
+        let mut retries = 0;
+        while self.root.is_empty() || self.root.end() < end {
+            let progress = (!self.root.is_empty()).then(|| self.root.end());
+
+            let lsn = self.next_read(begin, retries);
+            let last_lsn = ((progress.unwrap_or(begin)) / W) as u32;
+            if let Some(block) = read_block(backend, lsn, last_lsn, &mut self.events) {
+                self.process(block, begin);
+            }
+
+            if !self.root.is_empty() && progress < Some(self.root.end()) {
+                retries = 0;
+                continue;
+            }
+
+            retries += 1;
+            // new data is only verified by the second read that includes it
+            if retries > 1 {
+                self.events.push(Event::Backoff);
+            }
+            if retries % 5 == 0 && self.overlap < MAX_OVERLAP {
+                self.overlap = (self.overlap * 2).min(MAX_OVERLAP);
+                self.events.push(Event::Overlap);
+            }
+            if retries >= max_retries.max(1) {
+                if self.mode.contains(ParanoiaMode::NEVERSKIP) {
+                    return Err(Error::Read);
+                }
+                self.skip(begin, end);
+                retries = 0;
+            }
+        }
+
+        let offset = (begin - self.root.begin) as usize;
+        self.sector.clear();
+        self.sector
+            .extend_from_slice(&self.root.data[offset..offset + SECTOR_WORDS]);
+        self.cursor += 1;
+        self.trim(end);
+
+        Ok(&self.sector)
+    }
+    /// Get the sector to start the next read at.
+    ///
+    /// Reads start before the end of the root to allow aligning them and are
+    /// moved back a little further on each retry to avoid hitting the drive's
+    /// cache.
+    fn next_read(&self, begin: i64, retries: i32) -> u32 {
+        let from = if self.root.is_empty() {
+            begin
+        } else {
+            self.root.end()
+        };
+        let back = self.overlap + MIN_WORDS_OVERLAP as i64 + i64::from(retries % 3) * W;
+        ((from - back).max(0) / W) as u32
+    }
+    fn process(&mut self, block: Block, begin: i64) {
+        let mut fragments: Vec<Fragment> = if self.mode.contains(ParanoiaMode::VERIFY) {
+            let mut fragments = Vec::new();
+            for read in &self.reads {
+                if read.begin < block.end() + self.overlap
+                    && block.begin < read.end() + self.overlap
+                {
+                    fragments.extend(self.verify(&block, read));
+                }
+            }
+            if !fragments.is_empty() {
+                self.events.push(Event::Verify);
+            }
+            fragments
+        } else {
+            vec![Fragment {
+                block: block.clone(),
+                placed: true,
+            }]
+        };
+
+        self.reads.push_back(block);
+        if self.reads.len() > CACHED_READS {
+            self.reads.pop_front();
+        }
+
+        fragments.sort_by_key(|fragment| fragment.block.begin);
+        while !fragments.is_empty() {
+            let len = fragments.len();
+            fragments.retain(|fragment| !self.merge(fragment, begin));
+            if fragments.len() == len {
+                break;
+            }
+        }
+    }
+    /// Find the regions of `a` that agree with `b`.
+    fn verify(&self, a: &Block, b: &Block) -> Vec<Fragment> {
+        let window = if self.mode.contains(ParanoiaMode::FRAGMENT) {
+            self.overlap
+        } else {
+            0
+        };
+
+        let mut covered = vec![false; a.data.len()];
+        let mut fragments = Vec::new();
+        for offset in candidate_offsets(a, b, window) {
+            // a.data[i] corresponds to b.data[i + shift]
+            let shift = a.begin - b.begin + offset;
+            let start = (-shift).max(0) as usize;
+            let end = (b.data.len() as i64 - shift).clamp(0, a.data.len() as i64) as usize;
+
+            let mut i = start;
+            while i < end {
+                let run_start = i;
+                while i < end && a.data[i] == b.data[(i as i64 + shift) as usize] {
+                    i += 1;
+                }
+                if i - run_start >= MIN_WORDS_VERIFY {
+                    for (segment_start, segment_end) in uncovered(&covered, run_start, i) {
+                        covered[segment_start..segment_end].fill(true);
+                        fragments.push(Fragment {
+                            block: Block {
+                                begin: a.begin + segment_start as i64,
+                                data: a.data[segment_start..segment_end].to_vec(),
+                            },
+                            placed: offset == 0,
+                        });
+                    }
+                }
+                i += 1;
+            }
+
+            if covered.iter().all(|&covered| covered) {
+                break;
+            }
+        }
+        fragments
+    }
+    /// Append a fragment to the root if it can be aligned with its end.
+    ///
+    /// Without data to align with, only fragments with a verified position
+    /// are used.
+    fn merge(&mut self, fragment: &Fragment, begin: i64) -> bool {
+        let Fragment {
+            block: fragment,
+            placed,
+        } = fragment;
+        if self.root.is_empty() {
+            if *placed && fragment.begin <= begin && fragment.end() > begin {
+                self.root = fragment.clone();
+                self.anchored = true;
+                return true;
+            }
+            return false;
+        }
+
+        let index = if self.anchored {
+            let window = if self.mode.contains(ParanoiaMode::OVERLAP) {
+                self.overlap
+            } else {
+                0
+            };
+            match self.align(fragment, window) {
+                Some(index) => {
+                    if index as i64 != self.root.end() - fragment.begin {
+                        self.events.push(Event::Drift);
+                    }
+                    index
+                }
+                None => return false,
+            }
+        } else if *placed {
+            match usize::try_from(self.root.end() - fragment.begin) {
+                Ok(index) => index,
+                Err(_) => return false,
+            }
+        } else {
+            return false;
+        };
+        if index >= fragment.data.len() {
+            return false;
+        }
+
+        self.root.data.extend_from_slice(&fragment.data[index..]);
+        self.anchored = true;
+        true
+    }
+    /// Find the index in `fragment` that corresponds to the end of the root.
+    fn align(&self, fragment: &Block, window: i64) -> Option<usize> {
+        let k = MIN_WORDS_OVERLAP.min(self.root.data.len());
+        let tail = &self.root.data[self.root.data.len() - k..];
+
+        let expected = self.root.end() - k as i64 - fragment.begin;
+        let last = fragment.data.len() as i64 - k as i64;
+        (0..=window)
+            .flat_map(|delta| [expected - delta, expected + delta])
+            .filter(|start| (0..=last).contains(start))
+            .map(|start| start as usize)
+            .find(|&start| &fragment.data[start..start + k] == tail)
+            .map(|start| start + k)
+    }
+    /// Accept unverified data up to `end`.
+    fn skip(&mut self, begin: i64, end: i64) {
+        let from = if self.root.is_empty() {
+            self.root.begin = begin;
+            begin
+        } else {
+            self.root.end()
+        };
+        let repair = self
+            .mode
+            .contains(ParanoiaMode::SCRATCH | ParanoiaMode::REPAIR);
+        // only a vote over several reads reconstructs the data
+        let mut repaired = repair;
+        for pos in from..end {
+            let sample = if repair {
+                let (sample, reads) = self.vote(pos);
+                repaired &= reads > 1;
+                sample
+            } else {
+                self.reads.iter().rev().find_map(|read| read.get(pos))
+            };
+            self.root.data.push(sample.unwrap_or(0));
+        }
+        if repaired {
+            self.events.extend([Event::Scratch, Event::Repair]);
+        } else {
+            self.events.push(Event::Skip);
+        }
+        self.anchored = false;
+    }
+    /// Get the value most reads agree on at a position, and the number of
+    /// reads that include it.
+    fn vote(&self, pos: i64) -> (Option<i16>, usize) {
+        let mut counts: Vec<(i16, usize)> = Vec::new();
+        let mut reads = 0;
+        for sample in self.reads.iter().rev().filter_map(|read| read.get(pos)) {
+            reads += 1;
+            match counts.iter_mut().find(|(value, _)| *value == sample) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((sample, 1)),
+            }
+        }
+        // `max_by_key` returns the last maximum, prefer the most recent read
+        let sample = counts
+            .into_iter()
+            .rev()
+            .max_by_key(|&(_, count)| count)
+            .map(|(value, _)| value);
+        (sample, reads)
+    }
+    /// Drop data that is no longer needed.
+    fn trim(&mut self, end: i64) {
+        let keep_from = end - W;
+        if self.root.begin < keep_from {
+            self.root
+                .data
+                .drain(..(keep_from - self.root.begin) as usize);
+            self.root.begin = keep_from;
+        }
+        let overlap = self.overlap;
+        self.reads.retain(|read| read.end() + overlap + W > end);
+    }
+}
+
+/// Read a block of sectors that includes `last_lsn`, retrying with fewer
+/// sectors on errors.
+///
+/// If the read can't reach `last_lsn`, it is started at the next sector
+/// instead, so unreadable sectors before `last_lsn` are stepped over.
+/// Only the sectors that the backend returned are part of the block.
+fn read_block<B: CdBackend + ?Sized>(
+    backend: &mut B,
+    lsn: u32,
+    last_lsn: u32,
+    events: &mut Vec<Event>,
+) -> Option<Block> {
+    for lsn in lsn..=last_lsn.max(lsn) {
+        let mut sectors = READ_SECTORS;
+        loop {
+            let mut data = vec![0; sectors * SECTOR_WORDS];
+            let read = backend.read_raw(lsn, &mut data);
+            events.push(if read.is_ok() {
+                Event::Read
+            } else {
+                Event::ReadError
+            });
+            match read {
+                // u64, so a read of the sector at u32::MAX doesn't overflow
+                Ok(read) if u64::from(lsn) + read as u64 > u64::from(last_lsn) => {
+                    // the rest of the buffer hasn't been read
+                    data.truncate(read * SECTOR_WORDS);
+                    return Some(Block {
+                        begin: i64::from(lsn) * W,
+                        data,
+                    });
+                }
+                Err(_) if sectors > 1 => sectors /= 2,
+                _ => break,
+            }
+        }
+    }
+    None
+}
+
+/// Guess offsets at which `b` might agree with `a`, most likely first.
+fn candidate_offsets(a: &Block, b: &Block, window: i64) -> Vec<i64> {
+    if window == 0 {
+        return vec![0];
+    }
+
+    let first = (b.begin - window - a.begin).max(0);
+    let last = (b.end() + window - a.begin).min(a.data.len() as i64);
+    let mut counts = HashMap::from([(0, 0)]);
+    if first < last {
+        let step = ((last - first) as usize / ANCHORS).max(1);
+        for i in (first as usize..last as usize).step_by(step) {
+            let pos = a.begin + i as i64;
+            let from = (pos - window - b.begin).max(0) as usize;
+            let to = (pos + window + 1 - b.begin).clamp(0, b.data.len() as i64) as usize;
+            for j in from..to {
+                if b.data[j] == a.data[i] {
+                    *counts.entry(b.begin + j as i64 - pos).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    let mut offsets: Vec<(i64, usize)> = counts.into_iter().collect();
+    offsets.sort_by_key(|&(offset, count)| (std::cmp::Reverse(count), offset.abs(), offset));
+    offsets
+        .into_iter()
+        .take(MAX_CANDIDATES)
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+/// Get the segments of `start..end` that are not yet covered and long
+/// enough to be used as a fragment.
+fn uncovered(covered: &[bool], start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut segments = Vec::new();
+    let mut i = start;
+    while i < end {
+        while i < end && covered[i] {
+            i += 1;
+        }
+        let segment_start = i;
+        while i < end && !covered[i] {
+            i += 1;
+        }
+        if i - segment_start >= MIN_WORDS_VERIFY {
+            segments.push((segment_start, i));
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fault::{Fault, FaultEvent, FaultKind, FaultyDisc},
+        testing::samples,
+        VirtualDisc, VirtualTrack,
+    };
+
+    const SECTORS: usize = 100;
+
+    fn disc() -> VirtualDisc {
+        VirtualDisc::new([VirtualTrack::new(samples(SECTORS))]).unwrap()
+    }
+
+    fn rip<B: CdBackend>(backend: &mut B) -> Result<Vec<i16>> {
+        let mut engine = Engine::new();
+        engine.seek(0);
+        let mut samples = Vec::new();
+        for _ in 0..SECTORS {
+            samples.extend_from_slice(engine.read_sector(backend, 20)?);
+        }
+        Ok(samples)
+    }
+
+    /// Read every sector and collect the events of reading it.
+    fn rip_events<B: CdBackend>(
+        backend: &mut B,
+        mode: ParanoiaMode,
+        max_retries: i32,
+    ) -> Result<(Vec<i16>, Vec<Vec<Event>>)> {
+        let mut engine = Engine::new();
+        engine.set_mode(mode);
+        engine.seek(0);
+        let mut samples = Vec::new();
+        let mut events = Vec::new();
+        for _ in 0..SECTORS {
+            samples.extend_from_slice(engine.read_sector(backend, max_retries)?);
+            events.push(engine.events().to_vec());
+        }
+        Ok((samples, events))
+    }
+
+    /// Get the sectors whose events include `event`.
+    fn sectors_with(events: &[Vec<Event>], event: Event) -> Vec<usize> {
+        (0..events.len())
+            .filter(|&lsn| events[lsn].contains(&event))
+            .collect()
+    }
+
+    #[test]
+    fn first_read_does_not_place_the_root() {
+        let mut disc = FaultyDisc::new(disc(), 7).with_fault(Fault::DroppedBytes {
+            probability: 0.05,
+            max_bytes: 4,
+        });
+        assert_eq!(rip(&mut disc).unwrap(), samples(SECTORS));
+        assert_eq!(
+            disc.events(),
+            [FaultEvent {
+                lsn: 0,
+                kind: FaultKind::DroppedBytes(2),
+            }]
+        );
+    }
+
+    #[test]
+    fn dropped_and_duplicated_bytes() {
+        for seed in 0..4 {
+            for fault in [
+                Fault::DroppedBytes {
+                    probability: 0.3,
+                    max_bytes: 8,
+                },
+                Fault::DuplicatedBytes {
+                    probability: 0.3,
+                    max_bytes: 8,
+                },
+            ] {
+                let mut disc = FaultyDisc::new(disc(), seed).with_fault(fault.clone());
+                assert_eq!(
+                    rip(&mut disc).unwrap(),
+                    samples(SECTORS),
+                    "{fault:?} {seed}"
+                );
+                assert!(!disc.events().is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn jitter_between_reads() {
+        // the same data at a constant offset, with silence outside of the disc
+        let shifted = |offset: i64| -> Vec<i16> {
+            let samples = samples(SECTORS);
+            (0..samples.len() as i64)
+                .map(|i| {
+                    usize::try_from(i + offset).map_or(0, |i| samples.get(i).copied().unwrap_or(0))
+                })
+                .collect()
+        };
+        for seed in 0..4 {
+            for max_frames in [8, 20] {
+                let mut disc =
+                    FaultyDisc::new(disc(), seed).with_fault(Fault::Jitter { max_frames });
+                let ripped = rip(&mut disc).unwrap();
+                // every read is shifted, so the position of the first sector is
+                // only known up to the jitter, but nothing is dropped or repeated
+                let max_words = 2 * i64::from(max_frames);
+                assert!(
+                    (-max_words..=max_words).any(|offset| ripped == shifted(offset)),
+                    "{max_frames} {seed}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn short_reads() {
+        for max_sectors in [4, 2] {
+            let mut disc = FaultyDisc::new(disc(), 0).with_fault(Fault::ShortReads { max_sectors });
+            assert_eq!(rip(&mut disc).unwrap(), samples(SECTORS), "{max_sectors}");
+            assert!(!disc.events().is_empty());
+        }
+    }
+
+    #[test]
+    fn unreadable_sectors_are_skipped() {
+        let mut disc =
+            FaultyDisc::new(disc(), 0).with_fault(Fault::Unreadable { sectors: 30..=31 });
+        let (ripped, events) =
+            rip_events(&mut disc, ParanoiaMode::FULL ^ ParanoiaMode::NEVERSKIP, 5).unwrap();
+
+        let scratch = 30 * SECTOR_WORDS..32 * SECTOR_WORDS;
+        assert!(ripped[scratch.clone()].iter().all(|&sample| sample == 0));
+        assert_eq!(ripped[..scratch.start], samples(SECTORS)[..scratch.start]);
+        assert_eq!(ripped[scratch.end..], samples(SECTORS)[scratch.end..]);
+
+        assert_eq!(sectors_with(&events, Event::Skip), [30, 31]);
+        assert!(events[30].contains(&Event::ReadError));
+        // without any data there is nothing to vote on
+        assert!(sectors_with(&events, Event::Repair).is_empty());
+    }
+
+    #[test]
+    fn unreadable_sectors_fail_with_neverskip() {
+        let mut disc =
+            FaultyDisc::new(disc(), 0).with_fault(Fault::Unreadable { sectors: 30..=31 });
+        let mut engine = Engine::new();
+        engine.seek(0);
+        for lsn in 0..30 {
+            let sector = engine.read_sector(&mut disc, 5).unwrap();
+            assert_eq!(
+                sector,
+                &samples(SECTORS)[lsn * SECTOR_WORDS..][..SECTOR_WORDS]
+            );
+        }
+        assert!(matches!(engine.read_sector(&mut disc, 5), Err(Error::Read)));
+        assert!(!engine.events().contains(&Event::Skip));
+    }
+
+    #[test]
+    fn unstable_sectors_are_repaired_by_voting() {
+        let mode = ParanoiaMode::FULL ^ ParanoiaMode::NEVERSKIP;
+        let fault = Fault::Unstable {
+            sectors: 30..=31,
+            failures: 3,
+        };
+        for seed in 0..4 {
+            let mut disc = FaultyDisc::new(disc(), seed).with_fault(fault.clone());
+            let (ripped, events) = rip_events(&mut disc, mode, 3).unwrap();
+            assert_eq!(ripped, samples(SECTORS), "{seed}");
+            assert_eq!(sectors_with(&events, Event::Scratch), [30], "{seed}");
+            assert_eq!(sectors_with(&events, Event::Repair), [30], "{seed}");
+            assert!(sectors_with(&events, Event::Skip).is_empty(), "{seed}");
+
+            // without repair, the data is only skipped
+            let mut disc = FaultyDisc::new(disc.into_inner(), seed).with_fault(fault.clone());
+            let (_, events) = rip_events(&mut disc, mode ^ ParanoiaMode::REPAIR, 3).unwrap();
+            assert_eq!(sectors_with(&events, Event::Skip), [30], "{seed}");
+            assert!(sectors_with(&events, Event::Scratch).is_empty(), "{seed}");
+        }
+    }
+}
@@ -4,6 +4,7 @@
 use std::ffi::NulError;
 
 use num_enum::FromPrimitive;
+#[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
 use num_traits::{AsPrimitive, Signed};
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -18,7 +19,28 @@ pub enum Error {
     #[error(transparent)]
     InvalidString(#[from] NulError),
     #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("the encoder exited before all data was written ({status})")]
+    Encoder {
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+    #[error("invalid option: {0}")]
+    InvalidOption(String),
+    #[error(transparent)]
     Paranoia(#[from] ParanoiaError),
+    #[error(transparent)]
+    Span(#[from] crate::span::SpanError),
+    #[error(transparent)]
+    Mmc(#[from] crate::mmc::MmcError),
+    #[error(transparent)]
+    Quirks(#[from] crate::quirks::QuirksError),
+    #[cfg(feature = "signature")]
+    #[error("invalid key of station {station}: {reason}")]
+    InvalidKey {
+        station: String,
+        reason: &'static str,
+    },
 }
 
 /// Error code as returned from libcdio-cdparanoia/cdparanoia-3.
@@ -75,6 +97,7 @@ pub enum ParanoiaError {
     #[num_enum(catch_all)]
     Other(u16),
 }
+#[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
 impl ParanoiaError {
     pub(crate) fn check_result<N>(code: N) -> std::result::Result<N, Self>
     where
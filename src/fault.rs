@@ -0,0 +1,520 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Simulated read errors for testing.
+//!
+//! [`FaultyDisc`] wraps any [`CdBackend`] and distorts its raw reads
+//! according to a list of [`Fault`]s. All randomness is derived from a seed,
+//! so a test run can be reproduced exactly. Every injected fault is
+//! recorded as a [`FaultEvent`], which allows comparing what a ripping
+//! strategy reported with what actually went wrong.
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{
+//!     fault::{Fault, FaultKind, FaultyDisc},
+//!     CdBackend, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+//! };
+//!
+//! let disc = VirtualDisc::new([VirtualTrack::new(vec![1; 20 * SECTOR_WORDS])])?;
+//! let mut disc = FaultyDisc::new(disc, 42)
+//!     .with_fault(Fault::Unstable { sectors: 5..=6, failures: 2 })
+//!     .with_fault(Fault::Unreadable { sectors: 15..=15 });
+//!
+//! let mut buf = vec![0; SECTOR_WORDS];
+//! for _ in 0..2 {
+//!     disc.read_raw(5, &mut buf)?;
+//!     assert_ne!(buf, vec![1; SECTOR_WORDS]);
+//! }
+//! disc.read_raw(5, &mut buf)?;
+//! assert_eq!(buf, vec![1; SECTOR_WORDS]);
+//!
+//! assert!(disc.read_raw(15, &mut buf).is_err());
+//! assert_eq!(disc.events().last().unwrap().kind, FaultKind::ReadError);
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use crate::{CdBackend, Error, Result, SECTOR_BYTES, SECTOR_WORDS};
+
+/// Number of bytes in a stereo sample frame.
+const FRAME_BYTES: i64 = 4;
+
+/// A fault model for [`FaultyDisc`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// Shift every read by a random number of stereo frames
+    /// in the range `-max_frames..=max_frames`.
+    Jitter { max_frames: u32 },
+    /// With the given probability, start a read up to `max_bytes` bytes
+    /// too late, so the beginning of the requested data is missing.
+    ///
+    /// Drives transfer whole samples, so the number of bytes is always even.
+    DroppedBytes { probability: f64, max_bytes: u32 },
+    /// With the given probability, start a read up to `max_bytes` bytes
+    /// too early, so the end of the preceding data is repeated.
+    ///
+    /// Drives transfer whole samples, so the number of bytes is always even.
+    DuplicatedBytes { probability: f64, max_bytes: u32 },
+    /// Return random garbage for these sectors on the first `failures` reads
+    /// that include them, and the correct data afterwards.
+    Unstable {
+        sectors: RangeInclusive<u32>,
+        failures: u32,
+    },
+    /// Fail every read that includes one of these sectors.
+    Unreadable { sectors: RangeInclusive<u32> },
+    /// Return at most `max_sectors` sectors per read, even if more have been
+    /// requested.
+    ShortReads { max_sectors: u32 },
+}
+
+/// A fault that has been injected into a read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultEvent {
+    /// The first sector of the affected read, or the sector that was
+    /// replaced with garbage.
+    pub lsn: u32,
+    pub kind: FaultKind,
+}
+
+/// The kind of a [`FaultEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// The read was shifted by this many stereo frames.
+    Jitter(i32),
+    /// This many bytes were dropped at the start of the read.
+    DroppedBytes(u32),
+    /// This many bytes were duplicated at the start of the read.
+    DuplicatedBytes(u32),
+    /// Only this many sectors were returned.
+    ShortRead(u32),
+    /// The sector was replaced with garbage.
+    Garbage,
+    /// The read failed.
+    ReadError,
+}
+
+/// A [`CdBackend`] that injects reproducible read errors into another backend.
+///
+/// The table of contents is passed through unchanged.
+#[derive(Debug, Clone)]
+pub struct FaultyDisc<B> {
+    inner: B,
+    faults: Vec<Fault>,
+    rng: Rng,
+    attempts: HashMap<u32, u32>,
+    events: Vec<FaultEvent>,
+}
+
+impl<B: CdBackend> FaultyDisc<B> {
+    /// Wrap a backend without any faults, using the given seed
+    /// for all random decisions.
+    pub fn new(inner: B, seed: u64) -> Self {
+        Self {
+            inner,
+            faults: Vec::new(),
+            rng: Rng(seed),
+            attempts: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+    /// Add a fault model.
+    pub fn with_fault(mut self, fault: Fault) -> Self {
+        self.faults.push(fault);
+        self
+    }
+    /// Get a reference to the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+    /// Unwrap the wrapped backend.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+    /// Get all faults that have been injected so far.
+    pub fn events(&self) -> &[FaultEvent] {
+        &self.events
+    }
+    /// Remove and return all faults that have been injected so far.
+    pub fn take_events(&mut self) -> Vec<FaultEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl<B: CdBackend> FaultyDisc<B> {
+    fn shift(&mut self, lsn: u32) -> i64 {
+        let mut shift = 0;
+        for fault in &self.faults {
+            let kind = match *fault {
+                Fault::Jitter { max_frames } => {
+                    let max_frames = i64::from(max_frames);
+                    let frames = self.rng.range(-max_frames, max_frames);
+                    shift += frames * FRAME_BYTES;
+                    (frames != 0).then_some(FaultKind::Jitter(frames as i32))
+                }
+                Fault::DroppedBytes {
+                    probability,
+                    max_bytes,
+                } if max_bytes > 1 && self.rng.chance(probability) => {
+                    let bytes = 2 * self.rng.range(1, (max_bytes / 2).into());
+                    shift += bytes;
+                    Some(FaultKind::DroppedBytes(bytes as u32))
+                }
+                Fault::DuplicatedBytes {
+                    probability,
+                    max_bytes,
+                } if max_bytes > 1 && self.rng.chance(probability) => {
+                    let bytes = 2 * self.rng.range(1, (max_bytes / 2).into());
+                    shift -= bytes;
+                    Some(FaultKind::DuplicatedBytes(bytes as u32))
+                }
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                self.events.push(FaultEvent { lsn, kind });
+            }
+        }
+        shift
+    }
+    fn read_shifted(&mut self, first_lsn: u32, buf: &mut [i16], shift: i64) -> Result<usize> {
+        let margin = shift.unsigned_abs().div_ceil(SECTOR_BYTES as u64) as u32;
+        let start_lsn = first_lsn.saturating_sub(margin);
+        let lead = (first_lsn - start_lsn) as usize;
+
+        let mut raw = vec![0; buf.len() + (lead + margin as usize) * SECTOR_WORDS];
+        let read = self.inner.read_raw(start_lsn, &mut raw)?;
+        if read <= lead {
+            return Err(Error::Read);
+        }
+        let sectors = (read - lead).min(buf.len() / SECTOR_WORDS);
+
+        let bytes: Vec<u8> = raw[..read * SECTOR_WORDS]
+            .iter()
+            .flat_map(|sample| sample.to_ne_bytes())
+            .collect();
+        let base = (lead * SECTOR_BYTES) as i64 + shift;
+        for (i, sample) in buf[..sectors * SECTOR_WORDS].iter_mut().enumerate() {
+            let byte = |offset: i64| {
+                usize::try_from(base + 2 * i as i64 + offset)
+                    .ok()
+                    .and_then(|index| bytes.get(index).copied())
+                    .unwrap_or(0)
+            };
+            *sample = i16::from_ne_bytes([byte(0), byte(1)]);
+        }
+
+        Ok(sectors)
+    }
+}
+
+impl<B: CdBackend> CdBackend for FaultyDisc<B> {
+    fn tracks(&self) -> u8 {
+        self.inner.tracks()
+    }
+    fn track_first_sector(&self, track: u8) -> Result<u32> {
+        self.inner.track_first_sector(track)
+    }
+    fn track_last_sector(&self, track: u8) -> Result<u32> {
+        self.inner.track_last_sector(track)
+    }
+    fn track_channels(&self, track: u8) -> Option<u8> {
+        self.inner.track_channels(track)
+    }
+    fn track_audio(&self, track: u8) -> bool {
+        self.inner.track_audio(track)
+    }
+    fn track_copy_permitted(&self, track: u8) -> bool {
+        self.inner.track_copy_permitted(track)
+    }
+    fn track_linear_preemphasis(&self, track: u8) -> bool {
+        self.inner.track_linear_preemphasis(track)
+    }
+    fn disc_first_sector(&self) -> Result<u32> {
+        self.inner.disc_first_sector()
+    }
+    fn disc_last_sector(&self) -> Result<u32> {
+        self.inner.disc_last_sector()
+    }
+    fn sector_track(&self, lsn: u32) -> Result<u8> {
+        self.inner.sector_track(lsn)
+    }
+    fn disc_mcn(&self) -> Option<String> {
+        self.inner.disc_mcn()
+    }
+    fn track_isrc(&self, track: u8) -> Option<String> {
+        self.inner.track_isrc(track)
+    }
+    fn track_pregap_sector(&self, track: u8) -> Option<u32> {
+        self.inner.track_pregap_sector(track)
+    }
+    fn read_raw(&mut self, first_lsn: u32, buf: &mut [i16]) -> Result<usize> {
+        let max_sectors = self
+            .faults
+            .iter()
+            .filter_map(|fault| match *fault {
+                Fault::ShortReads { max_sectors } => Some(max_sectors.max(1) as usize),
+                _ => None,
+            })
+            .min();
+        let buf = match max_sectors {
+            Some(max_sectors) if buf.len() / SECTOR_WORDS > max_sectors => {
+                self.events.push(FaultEvent {
+                    lsn: first_lsn,
+                    kind: FaultKind::ShortRead(max_sectors as u32),
+                });
+                &mut buf[..max_sectors * SECTOR_WORDS]
+            }
+            _ => buf,
+        };
+
+        // u64, so a read near the end of the address space doesn't overflow
+        let requested =
+            u64::from(first_lsn)..u64::from(first_lsn) + (buf.len() / SECTOR_WORDS) as u64;
+        let unreadable = self.faults.iter().any(|fault| match fault {
+            Fault::Unreadable { sectors } => {
+                u64::from(*sectors.start()) < requested.end
+                    && u64::from(*sectors.end()) >= requested.start
+            }
+            _ => false,
+        });
+        if unreadable {
+            self.events.push(FaultEvent {
+                lsn: first_lsn,
+                kind: FaultKind::ReadError,
+            });
+            return Err(Error::Read);
+        }
+
+        let shift = self.shift(first_lsn);
+        let sectors = if shift == 0 {
+            self.inner.read_raw(first_lsn, buf)?
+        } else {
+            self.read_shifted(first_lsn, buf, shift)?
+        };
+
+        for (i, sector) in buf.chunks_exact_mut(SECTOR_WORDS).take(sectors).enumerate() {
+            // the inner backend has read this sector, so it is addressable
+            let lsn = first_lsn + i as u32;
+            let unstable = self.faults.iter().any(|fault| match fault {
+                Fault::Unstable { sectors, failures } => {
+                    sectors.contains(&lsn) && {
+                        let attempts = self.attempts.entry(lsn).or_default();
+                        *attempts += 1;
+                        *attempts <= *failures
+                    }
+                }
+                _ => false,
+            });
+            if unstable {
+                sector.fill_with(|| self.rng.next_u64() as i16);
+                self.events.push(FaultEvent {
+                    lsn,
+                    kind: FaultKind::Garbage,
+                });
+            }
+        }
+
+        Ok(sectors)
+    }
+}
+
+/// A small deterministic pseudo-random number generator (SplitMix64).
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+    /// Get a number in `low..=high`.
+    pub(crate) fn range(&mut self, low: i64, high: i64) -> i64 {
+        let span = (high - low) as u64 + 1;
+        low + (self.next_u64() % span) as i64
+    }
+    /// Return `true` with the given probability.
+    pub(crate) fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{VirtualDisc, VirtualTrack};
+
+    const SECTORS: usize = 20;
+
+    /// A disc where every sample holds its own index.
+    fn disc() -> VirtualDisc {
+        let samples = (0..SECTORS * SECTOR_WORDS).map(|i| i as i16).collect();
+        VirtualDisc::new([VirtualTrack::new(samples)]).unwrap()
+    }
+
+    /// Read one sector and return the index of its first sample on the disc.
+    fn read(disc: &mut FaultyDisc<VirtualDisc>, lsn: u32) -> i64 {
+        let mut buf = vec![0; SECTOR_WORDS];
+        assert_eq!(disc.read_raw(lsn, &mut buf).unwrap(), 1);
+        let start = i64::from(buf[0]);
+        for (i, &sample) in buf.iter().enumerate() {
+            assert_eq!(i64::from(sample), start + i as i64, "not a contiguous read");
+        }
+        start
+    }
+
+    #[test]
+    fn jitter_shifts_whole_frames() {
+        let mut disc = FaultyDisc::new(disc(), 1).with_fault(Fault::Jitter { max_frames: 3 });
+        let mut shifts = Vec::new();
+        for _ in 0..50 {
+            let start = read(&mut disc, 5);
+            let frames = match disc.take_events()[..] {
+                [] => 0,
+                [FaultEvent {
+                    lsn: 5,
+                    kind: FaultKind::Jitter(frames),
+                }] => frames,
+                ref events => panic!("unexpected events {events:?}"),
+            };
+            assert_eq!(start, 5 * SECTOR_WORDS as i64 + 2 * i64::from(frames));
+            shifts.push(frames);
+        }
+        assert!(shifts.iter().all(|frames| (-3..=3).contains(frames)));
+        assert!(shifts.contains(&-3) && shifts.contains(&0) && shifts.contains(&3));
+    }
+
+    #[test]
+    fn dropped_bytes_skip_samples() {
+        let mut disc = FaultyDisc::new(disc(), 2).with_fault(Fault::DroppedBytes {
+            probability: 1.0,
+            max_bytes: 4,
+        });
+        let mut drops = Vec::new();
+        for _ in 0..20 {
+            let start = read(&mut disc, 5);
+            let [FaultEvent {
+                lsn: 5,
+                kind: FaultKind::DroppedBytes(bytes),
+            }] = disc.take_events()[..]
+            else {
+                panic!("expected a single drop");
+            };
+            assert_eq!(start, 5 * SECTOR_WORDS as i64 + i64::from(bytes / 2));
+            drops.push(bytes);
+        }
+        assert!(drops.contains(&2) && drops.contains(&4));
+        assert!(drops.iter().all(|bytes| [2, 4].contains(bytes)));
+    }
+
+    #[test]
+    fn duplicated_bytes_repeat_samples() {
+        let mut disc = FaultyDisc::new(disc(), 3).with_fault(Fault::DuplicatedBytes {
+            probability: 1.0,
+            max_bytes: 2,
+        });
+        assert_eq!(read(&mut disc, 5), 5 * SECTOR_WORDS as i64 - 1);
+        assert_eq!(
+            disc.take_events(),
+            [FaultEvent {
+                lsn: 5,
+                kind: FaultKind::DuplicatedBytes(2),
+            }]
+        );
+
+        // there is nothing before the first sector of the disc
+        let mut buf = vec![1; SECTOR_WORDS];
+        disc.read_raw(0, &mut buf).unwrap();
+        assert_eq!(buf[..2], [0, 0]);
+        assert_eq!(buf[2], 1);
+    }
+
+    #[test]
+    fn probabilities_are_respected() {
+        let mut disc = FaultyDisc::new(disc(), 4)
+            .with_fault(Fault::DroppedBytes {
+                probability: 0.0,
+                max_bytes: 4,
+            })
+            .with_fault(Fault::DuplicatedBytes {
+                probability: 0.5,
+                max_bytes: 4,
+            })
+            .with_fault(Fault::Jitter { max_frames: 0 });
+        let duplicated = (0..200)
+            .filter(|_| read(&mut disc, 5) < 5 * SECTOR_WORDS as i64)
+            .count();
+        assert!((60..140).contains(&duplicated), "{duplicated}");
+        assert!(disc
+            .events()
+            .iter()
+            .all(|event| matches!(event.kind, FaultKind::DuplicatedBytes(_))));
+    }
+
+    #[test]
+    fn same_seed_same_faults() {
+        let rip = |seed| {
+            let mut disc = FaultyDisc::new(disc(), seed)
+                .with_fault(Fault::Jitter { max_frames: 8 })
+                .with_fault(Fault::Unstable {
+                    sectors: 3..=4,
+                    failures: 1,
+                });
+            let mut buf = vec![0; 4 * SECTOR_WORDS];
+            for lsn in 0..16 {
+                disc.read_raw(lsn, &mut buf).unwrap();
+            }
+            (buf, disc.take_events())
+        };
+        assert_eq!(rip(5), rip(5));
+        assert_ne!(rip(5).1, rip(6).1);
+    }
+
+    #[test]
+    fn short_reads_return_fewer_sectors() {
+        let mut disc = FaultyDisc::new(disc(), 8)
+            .with_fault(Fault::ShortReads { max_sectors: 3 })
+            .with_fault(Fault::Unreadable { sectors: 8..=8 });
+        let mut buf = vec![0; 6 * SECTOR_WORDS];
+        assert_eq!(disc.read_raw(5, &mut buf).unwrap(), 3);
+        assert_eq!(buf[0], 5 * SECTOR_WORDS as i16);
+        assert_eq!(
+            disc.take_events(),
+            [FaultEvent {
+                lsn: 5,
+                kind: FaultKind::ShortRead(3),
+            }]
+        );
+
+        assert_eq!(disc.read_raw(5, &mut buf[..2 * SECTOR_WORDS]).unwrap(), 2);
+        assert!(disc.take_events().is_empty());
+    }
+
+    #[test]
+    fn end_of_address_space() {
+        let disc = VirtualDisc::new([VirtualTrack::new(vec![1; 2 * SECTOR_WORDS])])
+            .unwrap()
+            .with_first_sector(u32::MAX - 1);
+        let mut disc = FaultyDisc::new(disc, 7)
+            .with_fault(Fault::Unreadable { sectors: 0..=0 })
+            .with_fault(Fault::Unstable {
+                sectors: u32::MAX - 1..=u32::MAX,
+                failures: 1,
+            });
+        let mut buf = vec![0; 4 * SECTOR_WORDS];
+        assert_eq!(disc.read_raw(u32::MAX - 1, &mut buf).unwrap(), 2);
+        assert_eq!(disc.read_raw(u32::MAX - 1, &mut buf).unwrap(), 2);
+        assert_eq!(buf[..2 * SECTOR_WORDS], [1; 2 * SECTOR_WORDS]);
+        assert_eq!(disc.read_raw(u32::MAX, &mut buf).unwrap(), 1);
+
+        let mut disc = disc.with_fault(Fault::Unreadable {
+            sectors: u32::MAX..=u32::MAX,
+        });
+        assert!(disc.read_raw(u32::MAX - 1, &mut buf).is_err());
+    }
+}
@@ -0,0 +1,865 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Rip logs in the style of EAC and XLD.
+//!
+//! A [`RipLog`] collects everything that is known about a rip: the drive,
+//! the read offset, the [`ParanoiaMode`], the table of contents and a
+//! [`TrackLog`] per track with its peak level, test and copy CRC, skipped
+//! sectors and the results of AccurateRip and CTDB lookups. Its [`Display`]
+//! implementation prints a human-readable log close to the layout of EAC and
+//! [`RipLog::to_json()`] returns the same data as JSON.
+//!
+//! This crate doesn't talk to AccurateRip or CTDB, the results of the lookups
+//! have to be filled in by the caller, e.g. using the checksums of a
+//! [`TrackChecksum`].
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{
+//!     checksum::TrackChecksum,
+//!     log::{AccurateRipResult, RipLog, Status, TrackLog, Verification},
+//!     toc::Toc,
+//!     Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+//! };
+//!
+//! let disc = VirtualDisc::new([VirtualTrack::new(vec![16384; 750 * SECTOR_WORDS])])?;
+//! let toc = Toc::read(&disc)?;
+//! let mut paranoia = Paranoia::new(disc);
+//!
+//! let mut checksum = TrackChecksum::for_track(&toc, 1).unwrap();
+//! for sector in paranoia.read_track(1)? {
+//!     checksum.update(&sector?);
+//! }
+//! let track = TrackLog::new(1, &checksum)
+//!     .with_file("01.wav")
+//!     .with_test(&checksum)
+//!     .with_accuraterip(AccurateRipResult {
+//!         version: 2,
+//!         checksum: checksum.accuraterip().v2(),
+//!         verification: Verification::Accurate { confidence: 12 },
+//!     });
+//!
+//! let log = RipLog::new(toc, paranoia.mode())
+//!     .with_drive("PLEXTOR DVDR PX-716A 1.11")
+//!     .with_read_offset(30)
+//!     .with_track(track);
+//!
+//! assert_eq!(log.status(), Status::NoErrors);
+//! let text = log.to_string();
+//! assert!(text.contains("Used drive  : PLEXTOR DVDR PX-716A 1.11"));
+//! assert!(text.contains("     Peak level 50.0 %"));
+//! assert!(text.contains("All tracks accurately ripped"));
+//! assert!(log.to_json().contains(r#""status": "no errors""#));
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::{
+    fmt::{self, Display},
+    ops::RangeInclusive,
+    path::PathBuf,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    checksum::TrackChecksum,
+    toc::{self, Toc},
+    ParanoiaMode,
+};
+
+/// The log of a rip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RipLog {
+    /// The name and version of the ripping program.
+    pub program: String,
+    /// When the rip was made, in any format.
+    pub date: Option<String>,
+    /// The drive, e.g. from [`Drive::model()`](crate::Drive::model).
+    pub drive: Option<String>,
+    /// The read offset correction in samples.
+    pub read_offset: i32,
+    pub mode: ParanoiaMode,
+    pub toc: Toc,
+    pub tracks: Vec<TrackLog>,
+}
+
+/// The part of a [`RipLog`] about a single track.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackLog {
+    pub number: u8,
+    /// The file the track has been written to.
+    pub file: Option<PathBuf>,
+    /// The largest absolute sample value, see [`TrackChecksum::peak()`].
+    pub peak: u16,
+    /// The CRC of a test read, if the track was read twice.
+    pub test_crc: Option<u32>,
+    pub copy_crc: u32,
+    pub accuraterip: Option<AccurateRipResult>,
+    pub ctdb: Option<CtdbResult>,
+    /// Sectors that couldn't be read correctly and have been skipped.
+    pub skipped: Vec<RangeInclusive<u32>>,
+}
+
+/// The result of an AccurateRip lookup for a track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AccurateRipResult {
+    /// The version of the checksum, 1 or 2.
+    pub version: u8,
+    /// The checksum of the rip, see [`AccurateRip`](crate::checksum::AccurateRip).
+    pub checksum: u32,
+    pub verification: Verification,
+}
+
+/// The result of a CUETools database (CTDB) lookup for a track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CtdbResult {
+    /// The CRC of the rip as used by CTDB, see [`TrackChecksum::ctdb_crc()`].
+    pub crc: u32,
+    pub verification: Verification,
+}
+
+/// How a checksum compares to a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Verification {
+    /// The database has the same checksum, submitted this many times.
+    Accurate { confidence: u32 },
+    /// The database only has other checksums, the most common one was
+    /// submitted this many times.
+    Mismatch { confidence: u32 },
+    /// The database doesn't know the track.
+    NotPresent,
+}
+
+/// The final status of a [`RipLog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    /// All tracks were read without skipped sectors and the test CRCs
+    /// match the copy CRCs.
+    NoErrors,
+    /// Sectors were skipped or a test CRC differs from its copy CRC.
+    Errors,
+}
+
+impl RipLog {
+    pub fn new(toc: Toc, mode: ParanoiaMode) -> Self {
+        Self {
+            program: concat!("cdparanoia-rs ", env!("CARGO_PKG_VERSION")).to_owned(),
+            date: None,
+            drive: None,
+            read_offset: 0,
+            mode,
+            toc,
+            tracks: Vec::new(),
+        }
+    }
+    /// Set the name and version of the ripping program.
+    pub fn with_program(mut self, program: impl Into<String>) -> Self {
+        self.program = program.into();
+        self
+    }
+    /// Set when the rip was made.
+    pub fn with_date(mut self, date: impl Into<String>) -> Self {
+        self.date = Some(date.into());
+        self
+    }
+    /// Set the name of the drive.
+    pub fn with_drive(mut self, drive: impl Into<String>) -> Self {
+        self.drive = Some(drive.into());
+        self
+    }
+    /// Set the read offset correction in samples.
+    pub fn with_read_offset(mut self, read_offset: i32) -> Self {
+        self.read_offset = read_offset;
+        self
+    }
+    /// Add a track.
+    pub fn with_track(mut self, track: TrackLog) -> Self {
+        self.tracks.push(track);
+        self
+    }
+    /// Get the final status of the rip.
+    pub fn status(&self) -> Status {
+        if self.tracks.iter().all(TrackLog::is_ok) {
+            Status::NoErrors
+        } else {
+            Status::Errors
+        }
+    }
+    /// Get the text of the log with a signature block,
+    /// see [`signature::sign()`](crate::signature::sign).
+    #[cfg(feature = "signature")]
+    pub fn to_signed_string(&self, key: &crate::signature::StationKey) -> crate::Result<String> {
+        crate::signature::sign(&self.to_string(), key)
+    }
+    /// Get the log as a JSON document.
+    ///
+    /// Checksums are formatted as hexadecimal strings like in the text,
+    /// missing values are `null`.
+    pub fn to_json(&self) -> String {
+        let tracks: Vec<Value> = self.tracks.iter().map(TrackLog::to_value).collect();
+        let accuraterip = self.accuraterip_tracks();
+
+        let json = json!({
+            "program": self.program,
+            "date": self.date,
+            "drive": self.drive,
+            "read_offset": self.read_offset,
+            "mode": {
+                "flags": self.mode.bits(),
+                "description": describe_mode(self.mode),
+            },
+            "toc": self.toc.to_value(),
+            "tracks": tracks,
+            "accuraterip": {
+                "tracks": accuraterip.len(),
+                "accurate": accuraterip
+                    .iter()
+                    .filter(|result| result.is_accurate())
+                    .count(),
+            },
+            "status": self.status().as_str(),
+        });
+        format!("{json:#}\n")
+    }
+    fn accuraterip_tracks(&self) -> Vec<Verification> {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.accuraterip)
+            .map(|result| result.verification)
+            .collect()
+    }
+    fn write_toc(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "TOC of the extracted CD")?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "     Track |   Start  |  Length  | Start sector | End sector"
+        )?;
+        writeln!(
+            f,
+            "    ---------------------------------------------------------"
+        )?;
+        for track in &self.toc.tracks {
+            writeln!(
+                f,
+                "{:>9}  | {} | {} | {:>9}    | {:>9}",
+                track.number,
+                toc::msf(track.first_sector),
+                toc::msf(track.sectors()),
+                track.first_sector,
+                track.last_sector,
+            )?;
+        }
+        Ok(())
+    }
+    fn write_accuraterip_summary(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let results = self.accuraterip_tracks();
+        if results.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(f, "AccurateRip summary")?;
+        writeln!(f)?;
+        for track in &self.tracks {
+            if let Some(result) = &track.accuraterip {
+                writeln!(f, "Track {:2}  {result}", track.number)?;
+            }
+        }
+        writeln!(f)?;
+
+        let accurate = results.iter().filter(|result| result.is_accurate()).count();
+        if accurate == self.tracks.len() {
+            writeln!(f, "All tracks accurately ripped")?;
+        } else if results
+            .iter()
+            .all(|result| *result == Verification::NotPresent)
+        {
+            writeln!(
+                f,
+                "None of the tracks are present in the AccurateRip database"
+            )?;
+        } else {
+            writeln!(
+                f,
+                "{accurate} of {} tracks accurately ripped",
+                self.tracks.len()
+            )?;
+        }
+        writeln!(f)
+    }
+}
+
+impl Display for RipLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.date {
+            Some(date) => writeln!(f, "{} extraction logfile from {date}", self.program)?,
+            None => writeln!(f, "{} extraction logfile", self.program)?,
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Used drive  : {}",
+            self.drive.as_deref().unwrap_or("Unknown")
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Read mode               : {}", describe_mode(self.mode))?;
+        writeln!(f, "Read offset correction  : {}", self.read_offset)?;
+        writeln!(f)?;
+        writeln!(f)?;
+        self.write_toc(f)?;
+        writeln!(f)?;
+
+        for track in &self.tracks {
+            writeln!(f)?;
+            write!(f, "{track}")?;
+        }
+        writeln!(f)?;
+
+        writeln!(f, "{}", self.status())?;
+        writeln!(f)?;
+        self.write_accuraterip_summary(f)?;
+        writeln!(f, "End of status report")
+    }
+}
+
+impl TrackLog {
+    /// Create the log of a track from the checksums of the copy.
+    pub fn new(number: u8, copy: &TrackChecksum) -> Self {
+        Self {
+            number,
+            file: None,
+            peak: copy.peak(),
+            test_crc: None,
+            copy_crc: copy.crc(),
+            accuraterip: None,
+            ctdb: None,
+            skipped: Vec::new(),
+        }
+    }
+    /// Set the file the track has been written to.
+    pub fn with_file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+    /// Set the CRC of the test read from its checksums.
+    pub fn with_test(mut self, test: &TrackChecksum) -> Self {
+        self.test_crc = Some(test.crc());
+        self
+    }
+    /// Set the result of the AccurateRip lookup.
+    pub fn with_accuraterip(mut self, result: AccurateRipResult) -> Self {
+        self.accuraterip = Some(result);
+        self
+    }
+    /// Set the result of the CTDB lookup.
+    pub fn with_ctdb(mut self, result: CtdbResult) -> Self {
+        self.ctdb = Some(result);
+        self
+    }
+    /// Record a skipped sector.
+    ///
+    /// Consecutive sectors are merged into a single range.
+    pub fn add_skipped(&mut self, lsn: u32) {
+        match self.skipped.last_mut() {
+            Some(range) if range.contains(&lsn) => {}
+            Some(range) if *range.end() + 1 == lsn => *range = *range.start()..=lsn,
+            _ => self.skipped.push(lsn..=lsn),
+        }
+    }
+    /// Check if no sectors have been skipped and the test CRC (if any)
+    /// matches the copy CRC.
+    pub fn is_ok(&self) -> bool {
+        self.skipped.is_empty() && self.test_crc.is_none_or(|crc| crc == self.copy_crc)
+    }
+    /// Get the peak level in percent of the largest possible sample value.
+    pub fn peak_percent(&self) -> f64 {
+        f64::from(self.peak) * 100.0 / 32768.0
+    }
+    fn to_value(&self) -> Value {
+        let skipped: Vec<Value> = self
+            .skipped
+            .iter()
+            .map(|range| {
+                json!({
+                    "first_sector": range.start(),
+                    "last_sector": range.end(),
+                    "begin": toc::msf(*range.start()),
+                    "end": toc::msf(*range.end()),
+                })
+            })
+            .collect();
+
+        json!({
+            "number": self.number,
+            "file": self.file.as_ref().map(|file| file.display().to_string()),
+            "peak": self.peak,
+            "peak_percent": self.peak_percent(),
+            "test_crc": self.test_crc.map(hex),
+            "copy_crc": hex(self.copy_crc),
+            "accuraterip": self.accuraterip.map(|result| {
+                json!({
+                    "version": result.version,
+                    "checksum": hex(result.checksum),
+                    "status": result.verification.as_str(),
+                    "confidence": result.verification.confidence(),
+                })
+            }),
+            "ctdb": self.ctdb.map(|result| {
+                json!({
+                    "crc": hex(result.crc),
+                    "status": result.verification.as_str(),
+                    "confidence": result.verification.confidence(),
+                })
+            }),
+            "skipped": skipped,
+            "status": if self.is_ok() { "ok" } else { "errors" },
+        })
+    }
+}
+
+impl Display for TrackLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Track {:2}", self.number)?;
+        writeln!(f)?;
+        if let Some(file) = &self.file {
+            writeln!(f, "     Filename {}", file.display())?;
+            writeln!(f)?;
+        }
+        for range in &self.skipped {
+            writeln!(
+                f,
+                "     Skipped sectors {} - {} ({}-{})",
+                toc::msf(*range.start()),
+                toc::msf(*range.end()),
+                range.start(),
+                range.end()
+            )?;
+        }
+        if !self.skipped.is_empty() {
+            writeln!(f)?;
+        }
+        writeln!(f, "     Peak level {:.1} %", self.peak_percent())?;
+        if let Some(test_crc) = self.test_crc {
+            writeln!(f, "     Test CRC {}", hex(test_crc))?;
+        }
+        writeln!(f, "     Copy CRC {}", hex(self.copy_crc))?;
+        if let Some(result) = &self.accuraterip {
+            writeln!(f, "     {result}")?;
+        }
+        if let Some(result) = &self.ctdb {
+            writeln!(f, "     {result}")?;
+        }
+        writeln!(
+            f,
+            "     {}",
+            if self.is_ok() {
+                "Copy OK"
+            } else {
+                "Copy finished"
+            }
+        )
+    }
+}
+
+impl Display for AccurateRipResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let checksum = hex(self.checksum);
+        match self.verification {
+            Verification::Accurate { confidence } => write!(
+                f,
+                "Accurately ripped (confidence {confidence})  [{checksum}]  (AR v{})",
+                self.version
+            ),
+            Verification::Mismatch { confidence } => write!(
+                f,
+                "Cannot be verified as accurate (confidence {confidence})  [{checksum}]  (AR v{})",
+                self.version
+            ),
+            Verification::NotPresent => write!(f, "Track not present in AccurateRip database"),
+        }
+    }
+}
+
+impl Display for CtdbResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let crc = hex(self.crc);
+        match self.verification {
+            Verification::Accurate { confidence } => {
+                write!(
+                    f,
+                    "CTDB [{crc}] Accurately ripped (confidence {confidence})"
+                )
+            }
+            Verification::Mismatch { confidence } => {
+                write!(
+                    f,
+                    "CTDB [{crc}] Differs from database (confidence {confidence})"
+                )
+            }
+            Verification::NotPresent => write!(f, "CTDB: Track not present in database"),
+        }
+    }
+}
+
+impl Verification {
+    /// Check if the checksum has been found in the database.
+    pub fn is_accurate(&self) -> bool {
+        matches!(self, Verification::Accurate { .. })
+    }
+    fn confidence(&self) -> Option<u32> {
+        match self {
+            Verification::Accurate { confidence } | Verification::Mismatch { confidence } => {
+                Some(*confidence)
+            }
+            Verification::NotPresent => None,
+        }
+    }
+    fn as_str(&self) -> &'static str {
+        match self {
+            Verification::Accurate { .. } => "accurate",
+            Verification::Mismatch { .. } => "mismatch",
+            Verification::NotPresent => "not present",
+        }
+    }
+}
+
+impl Status {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Status::NoErrors => "no errors",
+            Status::Errors => "errors",
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::NoErrors => "No errors occurred",
+            Status::Errors => "There were errors",
+        })
+    }
+}
+
+/// Describe the enabled paranoia features.
+fn describe_mode(mode: ParanoiaMode) -> String {
+    const FLAGS: [(ParanoiaMode, &str); 6] = [
+        (ParanoiaMode::VERIFY, "verify"),
+        (ParanoiaMode::FRAGMENT, "fragment"),
+        (ParanoiaMode::OVERLAP, "overlap"),
+        (ParanoiaMode::SCRATCH, "scratch"),
+        (ParanoiaMode::REPAIR, "repair"),
+        (ParanoiaMode::NEVERSKIP, "never skip"),
+    ];
+
+    if mode == ParanoiaMode::FULL {
+        return "Paranoia (full)".to_owned();
+    }
+    let flags: Vec<_> = FLAGS
+        .into_iter()
+        .filter(|(flag, _)| mode.contains(*flag))
+        .map(|(_, name)| name)
+        .collect();
+    if flags.is_empty() {
+        "Paranoia disabled".to_owned()
+    } else {
+        format!("Paranoia ({})", flags.join(", "))
+    }
+}
+
+/// Format a checksum like EAC.
+fn hex(checksum: u32) -> String {
+    format!("{checksum:08X}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toc::TocEntry;
+
+    fn entry(number: u8, first_sector: u32, last_sector: u32) -> TocEntry {
+        TocEntry {
+            number,
+            first_sector,
+            last_sector,
+            audio: true,
+            copy_permitted: false,
+            preemphasis: false,
+            channels: Some(2),
+            isrc: None,
+            pregap_sector: None,
+        }
+    }
+
+    fn track(number: u8, crc: u32) -> TrackLog {
+        TrackLog {
+            number,
+            file: None,
+            peak: 16384,
+            test_crc: None,
+            copy_crc: crc,
+            accuraterip: None,
+            ctdb: None,
+            skipped: Vec::new(),
+        }
+    }
+
+    fn rip_log() -> RipLog {
+        let toc = Toc {
+            mcn: None,
+            tracks: vec![entry(1, 0, 749), entry(2, 750, 17_999)],
+        };
+        let mut second = track(2, 0x0123_ABCD);
+        second.test_crc = Some(0x0123_ABCE);
+        second.accuraterip = Some(AccurateRipResult {
+            version: 1,
+            checksum: 0xDEAD_BEEF,
+            verification: Verification::Mismatch { confidence: 3 },
+        });
+        second.ctdb = Some(CtdbResult {
+            crc: 0x1234_5678,
+            verification: Verification::NotPresent,
+        });
+        for lsn in [800, 801, 802, 900] {
+            second.add_skipped(lsn);
+        }
+
+        RipLog::new(toc, ParanoiaMode::VERIFY | ParanoiaMode::OVERLAP)
+            .with_program("cdparanoia-rs 1.0")
+            .with_date("2023-10-01 12:00")
+            .with_drive("PLEXTOR DVDR PX-716A 1.11")
+            .with_read_offset(30)
+            .with_track(
+                track(1, 0x89AB_CDEF)
+                    .with_file("01.wav")
+                    .with_accuraterip(AccurateRipResult {
+                        version: 2,
+                        checksum: 0x0000_00FF,
+                        verification: Verification::Accurate { confidence: 12 },
+                    })
+                    .with_ctdb(CtdbResult {
+                        crc: 0xCAFE_F00D,
+                        verification: Verification::Accurate { confidence: 7 },
+                    }),
+            )
+            .with_track(second)
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(rip_log().to_string(), TEXT);
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(rip_log().to_json(), JSON);
+    }
+
+    #[test]
+    fn accuraterip_summary() {
+        let mut log = rip_log();
+        log.tracks[1].accuraterip = None;
+        assert!(log
+            .to_string()
+            .contains("\n1 of 2 tracks accurately ripped\n"));
+
+        log.tracks[1].accuraterip = log.tracks[0].accuraterip;
+        assert!(log.to_string().contains("\nAll tracks accurately ripped\n"));
+
+        for track in &mut log.tracks {
+            track.accuraterip.as_mut().unwrap().verification = Verification::NotPresent;
+        }
+        let text = log.to_string();
+        assert!(text.contains("\nTrack  1  Track not present in AccurateRip database\n"));
+        assert!(text.contains("\nNone of the tracks are present in the AccurateRip database\n"));
+
+        for track in &mut log.tracks {
+            track.accuraterip = None;
+        }
+        assert!(!log.to_string().contains("AccurateRip summary"));
+    }
+
+    #[test]
+    fn status() {
+        let mut log = rip_log();
+        assert_eq!(log.status(), Status::Errors);
+        log.tracks[1].skipped.clear();
+        assert_eq!(log.status(), Status::Errors);
+        log.tracks[1].test_crc = Some(log.tracks[1].copy_crc);
+        assert_eq!(log.status(), Status::NoErrors);
+        assert!(log.to_string().contains("\nNo errors occurred\n"));
+        assert!(log.to_json().ends_with("  \"status\": \"no errors\"\n}\n"));
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(describe_mode(ParanoiaMode::FULL), "Paranoia (full)");
+        assert_eq!(describe_mode(ParanoiaMode::DISABLE), "Paranoia disabled");
+        assert_eq!(
+            describe_mode(ParanoiaMode::FRAGMENT | ParanoiaMode::NEVERSKIP),
+            "Paranoia (fragment, never skip)"
+        );
+    }
+
+    const TEXT: &str = r#"cdparanoia-rs 1.0 extraction logfile from 2023-10-01 12:00
+
+Used drive  : PLEXTOR DVDR PX-716A 1.11
+
+Read mode               : Paranoia (verify, overlap)
+Read offset correction  : 30
+
+
+TOC of the extracted CD
+
+     Track |   Start  |  Length  | Start sector | End sector
+    ---------------------------------------------------------
+        1  | 00:00.00 | 00:10.00 |         0    |       749
+        2  | 00:10.00 | 03:50.00 |       750    |     17999
+
+
+Track  1
+
+     Filename 01.wav
+
+     Peak level 50.0 %
+     Copy CRC 89ABCDEF
+     Accurately ripped (confidence 12)  [000000FF]  (AR v2)
+     CTDB [CAFEF00D] Accurately ripped (confidence 7)
+     Copy OK
+
+Track  2
+
+     Skipped sectors 00:10.50 - 00:10.52 (800-802)
+     Skipped sectors 00:12.00 - 00:12.00 (900-900)
+
+     Peak level 50.0 %
+     Test CRC 0123ABCE
+     Copy CRC 0123ABCD
+     Cannot be verified as accurate (confidence 3)  [DEADBEEF]  (AR v1)
+     CTDB: Track not present in database
+     Copy finished
+
+There were errors
+
+AccurateRip summary
+
+Track  1  Accurately ripped (confidence 12)  [000000FF]  (AR v2)
+Track  2  Cannot be verified as accurate (confidence 3)  [DEADBEEF]  (AR v1)
+
+1 of 2 tracks accurately ripped
+
+End of status report
+"#;
+
+    const JSON: &str = r#"{
+  "program": "cdparanoia-rs 1.0",
+  "date": "2023-10-01 12:00",
+  "drive": "PLEXTOR DVDR PX-716A 1.11",
+  "read_offset": 30,
+  "mode": {
+    "flags": 5,
+    "description": "Paranoia (verify, overlap)"
+  },
+  "toc": {
+    "mcn": null,
+    "tracks": [
+      {
+        "number": 1,
+        "audio": true,
+        "first_sector": 0,
+        "last_sector": 749,
+        "sectors": 750,
+        "begin": "00:00.00",
+        "length": "00:10.00",
+        "copy_permitted": false,
+        "preemphasis": false,
+        "channels": 2,
+        "isrc": null,
+        "pregap_sector": null
+      },
+      {
+        "number": 2,
+        "audio": true,
+        "first_sector": 750,
+        "last_sector": 17999,
+        "sectors": 17250,
+        "begin": "00:10.00",
+        "length": "03:50.00",
+        "copy_permitted": false,
+        "preemphasis": false,
+        "channels": 2,
+        "isrc": null,
+        "pregap_sector": null
+      }
+    ],
+    "lead_out_sector": 18000,
+    "audio_sectors": 18000,
+    "audio_length": "04:00.00"
+  },
+  "tracks": [
+    {
+      "number": 1,
+      "file": "01.wav",
+      "peak": 16384,
+      "peak_percent": 50.0,
+      "test_crc": null,
+      "copy_crc": "89ABCDEF",
+      "accuraterip": {
+        "version": 2,
+        "checksum": "000000FF",
+        "status": "accurate",
+        "confidence": 12
+      },
+      "ctdb": {
+        "crc": "CAFEF00D",
+        "status": "accurate",
+        "confidence": 7
+      },
+      "skipped": [],
+      "status": "ok"
+    },
+    {
+      "number": 2,
+      "file": null,
+      "peak": 16384,
+      "peak_percent": 50.0,
+      "test_crc": "0123ABCE",
+      "copy_crc": "0123ABCD",
+      "accuraterip": {
+        "version": 1,
+        "checksum": "DEADBEEF",
+        "status": "mismatch",
+        "confidence": 3
+      },
+      "ctdb": {
+        "crc": "12345678",
+        "status": "not present",
+        "confidence": null
+      },
+      "skipped": [
+        {
+          "first_sector": 800,
+          "last_sector": 802,
+          "begin": "00:10.50",
+          "end": "00:10.52"
+        },
+        {
+          "first_sector": 900,
+          "last_sector": 900,
+          "begin": "00:12.00",
+          "end": "00:12.00"
+        }
+      ],
+      "status": "errors"
+    }
+  ],
+  "accuraterip": {
+    "tracks": 2,
+    "accurate": 1
+  },
+  "status": "errors"
+}
+"#;
+}
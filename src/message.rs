@@ -0,0 +1,178 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Routing of the messages of libcdio-paranoia/cdparanoia-3.
+//!
+//! The C libraries report what they are doing (and what went wrong) as lines
+//! of text. Every [`Drive`](crate::Drive) hands these lines to a
+//! [`MessageSink`], which is set with
+//! [`DriveOptions::message_sink()`](crate::DriveOptions::message_sink).
+//! By default, messages are forwarded to `tracing` if the `tracing` feature
+//! is enabled, to `log` if the `log` feature is enabled and dropped
+//! otherwise. Earlier versions printed them to stderr, which a library
+//! shouldn't do unasked; use [`Stderr`] to get that behavior back.
+//!
+//! The Rust backend doesn't produce any messages.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cdparanoia::{message::Collect, DriveOptions};
+//!
+//! let messages = Collect::new();
+//! let drive = DriveOptions::new()
+//!     .message_sink(messages.clone())
+//!     .open("/dev/cdrom")?;
+//!
+//! for message in messages.take() {
+//!     println!("{:?}: {}", message.level, message.text);
+//! }
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// The severity of a [`Message`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Level {
+    /// A line from the error buffer of the library.
+    Error,
+    /// A line from the message buffer of the library.
+    Info,
+}
+
+/// A single line that was reported by the library.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Message {
+    pub level: Level,
+    /// The device the message is about, if it is known.
+    pub device: Option<PathBuf>,
+    pub text: String,
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(device) = &self.device {
+            write!(f, "{}: ", device.display())?;
+        }
+        f.write_str(&self.text)
+    }
+}
+
+/// Receives the messages of a [`Drive`](crate::Drive).
+///
+/// This is implemented for closures, so `|message: &Message| ...`
+/// can be used as a sink.
+pub trait MessageSink: Send + Sync {
+    fn message(&self, message: &Message);
+}
+
+impl<F: Fn(&Message) + Send + Sync> MessageSink for F {
+    fn message(&self, message: &Message) {
+        self(message)
+    }
+}
+
+/// Drops all messages.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Discard;
+
+impl MessageSink for Discard {
+    fn message(&self, _message: &Message) {}
+}
+
+/// Prints all messages to stderr, like the C libraries do by themselves.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stderr;
+
+impl MessageSink for Stderr {
+    fn message(&self, message: &Message) {
+        eprintln!("{}", message.text);
+    }
+}
+
+/// Collects all messages in memory.
+///
+/// Clones share the same messages, so a clone can be passed to
+/// [`DriveOptions::message_sink()`](crate::DriveOptions::message_sink)
+/// while the original is used to read them.
+#[derive(Debug, Clone, Default)]
+pub struct Collect(Arc<Mutex<Vec<Message>>>);
+
+impl Collect {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Get a copy of all messages that have been collected so far.
+    pub fn messages(&self) -> Vec<Message> {
+        self.lock().clone()
+    }
+    /// Remove and return all messages that have been collected so far.
+    pub fn take(&self) -> Vec<Message> {
+        std::mem::take(&mut *self.lock())
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Message>> {
+        // a panicking sink can't leave the vector in an invalid state
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl MessageSink for Collect {
+    fn message(&self, message: &Message) {
+        self.lock().push(message.clone());
+    }
+}
+
+/// Forwards all messages to the `log` crate, with the target `cdparanoia`.
+#[cfg(feature = "log")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Log;
+
+#[cfg(feature = "log")]
+impl MessageSink for Log {
+    fn message(&self, message: &Message) {
+        let level = match message.level {
+            Level::Error => ::log::Level::Error,
+            Level::Info => ::log::Level::Info,
+        };
+        ::log::log!(target: "cdparanoia", level, "{message}");
+    }
+}
+
+/// Forwards all messages to `tracing` events, with the device as a field.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tracing;
+
+#[cfg(feature = "tracing")]
+impl MessageSink for Tracing {
+    fn message(&self, message: &Message) {
+        let device = message.device.as_ref().map(|device| device.display());
+        match message.level {
+            Level::Error => tracing::error!(
+                device = device.map(tracing::field::display),
+                "{}",
+                message.text
+            ),
+            Level::Info => tracing::info!(
+                device = device.map(tracing::field::display),
+                "{}",
+                message.text
+            ),
+        }
+    }
+}
+
+/// The sink that is used if none is set.
+pub(crate) fn default_sink() -> Arc<dyn MessageSink> {
+    #[cfg(feature = "tracing")]
+    return Arc::new(Tracing);
+    #[cfg(all(feature = "log", not(feature = "tracing")))]
+    return Arc::new(Log);
+    #[cfg(not(any(feature = "log", feature = "tracing")))]
+    Arc::new(Discard)
+}
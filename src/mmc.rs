@@ -0,0 +1,1112 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Raw MMC (SCSI multimedia) commands.
+//!
+//! A [`Transport`] sends a command descriptor block (CDB) to a drive and
+//! transfers the data of the command. [`Drive`](crate::Drive) implements it
+//! through libcdio's MMC layer (with `libcdio-paranoia`) or the SG_IO ioctl
+//! (with `rust-paranoia`), cdparanoia-3 doesn't support raw commands.
+//!
+//! The [`Command`]s of this module build the CDB and decode the response:
+//! [`Inquiry`], [`GetConfiguration`], [`ModeSense2A`], [`ReadToc`],
+//! [`ReadSubChannel`], [`ReadCd`] and [`SetCdSpeed`].
+//!
+//! [`MockTransport`] replays canned responses, so code that uses raw commands
+//! can be tested without a drive. Responses can be recorded from a real drive
+//! with a [`Recorder`] and loaded with [`MockTransport::from_transcript()`].
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::mmc::{Command, Inquiry, MockTransport};
+//!
+//! let mut response = vec![0x05, 0x80, 0x05, 0x32, 0x5b, 0, 0, 0];
+//! response.extend(b"PLEXTOR DVDR   PX-716A  1.11");
+//! let transport = MockTransport::new().with_response(Inquiry.cdb(), response);
+//!
+//! let inquiry = Inquiry.run(&transport)?;
+//! assert_eq!(inquiry.vendor, "PLEXTOR");
+//! assert_eq!(inquiry.product, "DVDR   PX-716A");
+//! assert_eq!(inquiry.revision, "1.11");
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt::{self, Display, Write as _},
+};
+
+use crate::{Error, Result, SECTOR_BYTES};
+
+/// Number of bytes of the C2 error pointers of a sector, one bit for every
+/// byte of audio data.
+pub const C2_BYTES: usize = SECTOR_BYTES / 8;
+
+/// Sends raw MMC commands to a drive.
+pub trait Transport {
+    /// Send a command descriptor block and transfer `data`.
+    ///
+    /// Returns the number of bytes that have been transferred, which may be
+    /// less than the size of the buffer. Fails with
+    /// [`MmcError::CheckCondition`] if the drive rejects the command.
+    fn execute(&self, cdb: &[u8], data: Data<'_>) -> Result<usize>;
+}
+
+impl<T: Transport + ?Sized> Transport for &T {
+    fn execute(&self, cdb: &[u8], data: Data<'_>) -> Result<usize> {
+        (**self).execute(cdb, data)
+    }
+}
+
+/// The data transfer of a command.
+#[derive(Debug)]
+pub enum Data<'a> {
+    /// The command doesn't transfer data.
+    None,
+    /// The drive sends data into this buffer.
+    In(&'a mut [u8]),
+    /// This data is sent to the drive.
+    Out(&'a [u8]),
+}
+
+/// A typed MMC command.
+pub trait Command {
+    /// The decoded response.
+    type Response;
+
+    /// Get the command descriptor block.
+    fn cdb(&self) -> Vec<u8>;
+    /// Get the number of bytes the drive may return.
+    fn response_len(&self) -> usize;
+    /// Decode the data returned by the drive, which may be shorter than
+    /// [`response_len()`](Self::response_len).
+    fn decode(&self, data: &[u8]) -> Result<Self::Response>;
+    /// Send the command through a transport and decode the response.
+    fn run(&self, transport: &(impl Transport + ?Sized)) -> Result<Self::Response>
+    where
+        Self: Sized,
+    {
+        let mut data = vec![0; self.response_len()];
+        let transfer = if data.is_empty() {
+            Data::None
+        } else {
+            Data::In(&mut data)
+        };
+        let len = transport.execute(&self.cdb(), transfer)?;
+        self.decode(&data[..len.min(data.len())])
+    }
+}
+
+/// An error of a raw MMC command.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MmcError {
+    #[error("the drive rejected the command: {0}")]
+    CheckCondition(Sense),
+    #[error("the command failed with error code {0}")]
+    Failed(i32),
+    #[error("raw MMC commands are not supported by this backend")]
+    Unsupported,
+    #[error("unexpected command {0:02x?}")]
+    UnexpectedCommand(Vec<u8>),
+    #[error("the drive returned an invalid response")]
+    InvalidResponse,
+    #[error("invalid transcript in line {0}")]
+    InvalidTranscript(usize),
+}
+
+/// The sense data of a command that the drive rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sense {
+    pub key: u8,
+    /// The additional sense code.
+    pub asc: u8,
+    /// The additional sense code qualifier.
+    pub ascq: u8,
+}
+
+impl Sense {
+    /// Parse sense data in fixed or descriptor format.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        match data.first()? & 0x7f {
+            0x70 | 0x71 if data.len() >= 14 => Some(Self {
+                key: data[2] & 0x0f,
+                asc: data[12],
+                ascq: data[13],
+            }),
+            0x72 | 0x73 if data.len() >= 4 => Some(Self {
+                key: data[1] & 0x0f,
+                asc: data[2],
+                ascq: data[3],
+            }),
+            _ => None,
+        }
+    }
+    /// Encode the sense data in fixed format.
+    pub fn to_bytes(self) -> [u8; 18] {
+        let mut data = [0; 18];
+        data[0] = 0x70;
+        data[2] = self.key;
+        data[7] = 10;
+        data[12] = self.asc;
+        data[13] = self.ascq;
+        data
+    }
+}
+
+impl Display for Sense {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sense key {:X}h, ASC {:02X}h, ASCQ {:02X}h",
+            self.key, self.asc, self.ascq
+        )
+    }
+}
+
+/// A [`Transport`] that replays canned responses in order.
+///
+/// Every command must match the CDB of the next response, otherwise it fails
+/// with [`MmcError::UnexpectedCommand`].
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: RefCell<VecDeque<Exchange>>,
+    commands: RefCell<Vec<Vec<u8>>>,
+}
+
+/// A command and the response to it.
+#[derive(Debug, Clone)]
+struct Exchange {
+    cdb: Vec<u8>,
+    response: std::result::Result<Vec<u8>, Sense>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Load the responses of a transcript, see [`Recorder::transcript()`].
+    ///
+    /// Each exchange starts with a line `> <cdb>`, followed by any number of
+    /// lines `< <data>` or a single line `! <key> <asc> <ascq>` if the
+    /// command was rejected. Everything is written as hexadecimal bytes
+    /// separated by whitespace, lines starting with `#` are ignored.
+    pub fn from_transcript(transcript: &str) -> Result<Self> {
+        let mut responses = VecDeque::new();
+        for (number, line) in transcript.lines().enumerate() {
+            let invalid = || Error::from(MmcError::InvalidTranscript(number + 1));
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (kind, bytes) = line.split_at(line.chars().next().map_or(0, char::len_utf8));
+            let bytes = bytes
+                .split_whitespace()
+                .map(|byte| u8::from_str_radix(byte, 16))
+                .collect::<std::result::Result<Vec<u8>, _>>()
+                .map_err(|_| invalid())?;
+            match (kind, responses.back_mut()) {
+                (">", _) => responses.push_back(Exchange {
+                    cdb: bytes,
+                    response: Ok(Vec::new()),
+                }),
+                (
+                    "<",
+                    Some(Exchange {
+                        response: Ok(data), ..
+                    }),
+                ) => data.extend(bytes),
+                ("!", Some(exchange)) => match bytes[..] {
+                    [key, asc, ascq] => exchange.response = Err(Sense { key, asc, ascq }),
+                    _ => return Err(invalid()),
+                },
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(Self {
+            responses: RefCell::new(responses),
+            commands: RefCell::default(),
+        })
+    }
+    /// Answer the next command, which must be `cdb`, with `data`.
+    ///
+    /// If the command reads less data, the rest is dropped. If it reads
+    /// more, the transfer is short, like with a drive that returns less data
+    /// than requested.
+    pub fn with_response(self, cdb: impl Into<Vec<u8>>, data: impl Into<Vec<u8>>) -> Self {
+        self.responses.borrow_mut().push_back(Exchange {
+            cdb: cdb.into(),
+            response: Ok(data.into()),
+        });
+        self
+    }
+    /// Reject the next command, which must be `cdb`, with sense data.
+    pub fn with_sense(self, cdb: impl Into<Vec<u8>>, sense: Sense) -> Self {
+        self.responses.borrow_mut().push_back(Exchange {
+            cdb: cdb.into(),
+            response: Err(sense),
+        });
+        self
+    }
+    /// Get the CDBs of all commands that have been executed.
+    pub fn commands(&self) -> Vec<Vec<u8>> {
+        self.commands.borrow().clone()
+    }
+    /// Get the number of responses that haven't been used.
+    pub fn remaining(&self) -> usize {
+        self.responses.borrow().len()
+    }
+}
+
+impl Transport for MockTransport {
+    fn execute(&self, cdb: &[u8], data: Data<'_>) -> Result<usize> {
+        self.commands.borrow_mut().push(cdb.to_vec());
+
+        let mut responses = self.responses.borrow_mut();
+        if responses.front().map(|exchange| exchange.cdb.as_slice()) != Some(cdb) {
+            return Err(MmcError::UnexpectedCommand(cdb.to_vec()).into());
+        }
+        let exchange = responses.pop_front().unwrap();
+        let response = exchange.response.map_err(MmcError::CheckCondition)?;
+        Ok(match data {
+            Data::None => 0,
+            Data::In(buf) => {
+                let len = buf.len().min(response.len());
+                buf[..len].copy_from_slice(&response[..len]);
+                len
+            }
+            Data::Out(buf) => buf.len(),
+        })
+    }
+}
+
+/// A [`Transport`] that records the commands sent through it, e.g. to
+/// replay the responses of a real drive with a [`MockTransport`].
+#[derive(Debug)]
+pub struct Recorder<T> {
+    inner: T,
+    exchanges: RefCell<Vec<Exchange>>,
+}
+
+impl<T: Transport> Recorder<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            exchanges: RefCell::default(),
+        }
+    }
+    /// Get the underlying transport.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+    /// Get all successful and rejected commands in the format of
+    /// [`MockTransport::from_transcript()`].
+    pub fn transcript(&self) -> String {
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let mut transcript = String::new();
+        for exchange in self.exchanges.borrow().iter() {
+            // writing to a String can't fail
+            let _ = writeln!(transcript, "> {}", hex(&exchange.cdb));
+            match &exchange.response {
+                Ok(data) => {
+                    for line in data.chunks(16) {
+                        let _ = writeln!(transcript, "< {}", hex(line));
+                    }
+                }
+                Err(sense) => {
+                    let _ = writeln!(
+                        transcript,
+                        "! {:02x} {:02x} {:02x}",
+                        sense.key, sense.asc, sense.ascq
+                    );
+                }
+            }
+        }
+        transcript
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn execute(&self, cdb: &[u8], mut data: Data<'_>) -> Result<usize> {
+        let result = self.inner.execute(cdb, reborrow(&mut data));
+        let response = match (&result, data) {
+            (Ok(len), Data::In(buf)) => Ok(buf[..(*len).min(buf.len())].to_vec()),
+            (Ok(_), _) => Ok(Vec::new()),
+            (Err(Error::Mmc(MmcError::CheckCondition(sense))), _) => Err(*sense),
+            // failures of the transport can't be replayed
+            (Err(_), _) => return result,
+        };
+        self.exchanges.borrow_mut().push(Exchange {
+            cdb: cdb.to_vec(),
+            response,
+        });
+        result
+    }
+}
+
+fn reborrow<'a>(data: &'a mut Data<'_>) -> Data<'a> {
+    match data {
+        Data::None => Data::None,
+        Data::In(buf) => Data::In(buf),
+        Data::Out(buf) => Data::Out(buf),
+    }
+}
+
+/// Check that a response has at least `len` bytes.
+fn require(data: &[u8], len: usize) -> Result<()> {
+    if data.len() < len {
+        return Err(MmcError::InvalidResponse.into());
+    }
+    Ok(())
+}
+
+fn u16_at(data: &[u8], index: usize) -> u16 {
+    u16::from_be_bytes([data[index], data[index + 1]])
+}
+
+/// Get the printable text of a fixed-length ASCII field.
+fn ascii(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+        .to_owned()
+}
+
+/// Convert minutes, seconds and frames to a logical block address.
+///
+/// Addresses from minute 90 on are in the lead-in, which is before LBA 0.
+fn msf_to_lba(minutes: u8, seconds: u8, frames: u8) -> i32 {
+    let lba = (i32::from(minutes) * 60 + i32::from(seconds)) * 75 + i32::from(frames) - 150;
+    if minutes >= 90 {
+        lba - 450_000
+    } else {
+        lba
+    }
+}
+
+/// INQUIRY (12h): the vendor, product and firmware revision of the drive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Inquiry;
+
+/// The response to [`Inquiry`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InquiryData {
+    /// The peripheral device type, 5 for CD/DVD drives.
+    pub device_type: u8,
+    pub vendor: String,
+    pub product: String,
+    pub revision: String,
+}
+
+impl Command for Inquiry {
+    type Response = InquiryData;
+
+    fn cdb(&self) -> Vec<u8> {
+        vec![0x12, 0, 0, 0, self.response_len() as u8, 0]
+    }
+    fn response_len(&self) -> usize {
+        96
+    }
+    fn decode(&self, data: &[u8]) -> Result<InquiryData> {
+        require(data, 36)?;
+        Ok(InquiryData {
+            device_type: data[0] & 0x1f,
+            vendor: ascii(&data[8..16]),
+            product: ascii(&data[16..32]),
+            revision: ascii(&data[32..36]),
+        })
+    }
+}
+
+/// GET CONFIGURATION (46h): the features of the drive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct GetConfiguration {
+    current_only: bool,
+    starting_feature: u16,
+}
+
+/// The response to [`GetConfiguration`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Configuration {
+    /// The profile of the current medium, e.g. `0x08` for CD-ROM.
+    pub current_profile: u16,
+    pub features: Vec<Feature>,
+}
+
+/// A feature descriptor of a [`Configuration`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Feature {
+    pub code: u16,
+    pub version: u8,
+    pub persistent: bool,
+    /// Whether the feature is usable with the current medium.
+    pub current: bool,
+    /// The feature-dependent data.
+    pub data: Vec<u8>,
+}
+
+impl GetConfiguration {
+    /// The feature code of CD Read.
+    pub const CD_READ: u16 = 0x001e;
+    /// The feature code of CD External Audio Play.
+    pub const CD_AUDIO_PLAY: u16 = 0x0103;
+    /// The feature code of Real Time Streaming.
+    pub const REAL_TIME_STREAMING: u16 = 0x0107;
+
+    /// Request all features.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Only request the features that are current.
+    pub fn current_only(mut self) -> Self {
+        self.current_only = true;
+        self
+    }
+    /// Start at this feature code.
+    pub fn with_starting_feature(mut self, code: u16) -> Self {
+        self.starting_feature = code;
+        self
+    }
+}
+
+impl Command for GetConfiguration {
+    type Response = Configuration;
+
+    fn cdb(&self) -> Vec<u8> {
+        let [feature_msb, feature_lsb] = self.starting_feature.to_be_bytes();
+        let [len_msb, len_lsb] = (self.response_len() as u16).to_be_bytes();
+        vec![
+            0x46,
+            u8::from(self.current_only),
+            feature_msb,
+            feature_lsb,
+            0,
+            0,
+            0,
+            len_msb,
+            len_lsb,
+            0,
+        ]
+    }
+    fn response_len(&self) -> usize {
+        4096
+    }
+    fn decode(&self, data: &[u8]) -> Result<Configuration> {
+        require(data, 8)?;
+        let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let data = &data[..(4 + len).clamp(8, data.len())];
+
+        let mut features = Vec::new();
+        let mut descriptors = &data[8..];
+        while descriptors.len() >= 4 {
+            let end = 4 + usize::from(descriptors[3]);
+            if descriptors.len() < end {
+                // the response was cut off in the middle of a descriptor
+                break;
+            }
+            features.push(Feature {
+                code: u16_at(descriptors, 0),
+                version: (descriptors[2] >> 2) & 0x0f,
+                persistent: descriptors[2] & 0x02 != 0,
+                current: descriptors[2] & 0x01 != 0,
+                data: descriptors[4..end].to_vec(),
+            });
+            descriptors = &descriptors[end..];
+        }
+
+        Ok(Configuration {
+            current_profile: u16_at(data, 6),
+            features,
+        })
+    }
+}
+
+impl Configuration {
+    /// Get a feature by its code.
+    pub fn feature(&self, code: u16) -> Option<&Feature> {
+        self.features.iter().find(|feature| feature.code == code)
+    }
+}
+
+/// MODE SENSE (10) (5Ah) of the CD/DVD capabilities and mechanical status
+/// page (2Ah).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ModeSense2A;
+
+/// The response to [`ModeSense2A`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CapabilitiesPage {
+    pub read_cd_r: bool,
+    pub read_cd_rw: bool,
+    pub audio_play: bool,
+    /// Whether audio can be read with READ CD.
+    pub cdda: bool,
+    /// Whether the drive can continue an audio read at the exact position
+    /// after it was interrupted.
+    pub accurate_stream: bool,
+    /// Whether R-W subchannel data can be read.
+    pub rw_subchannel: bool,
+    /// Whether R-W subchannel data is returned deinterleaved and corrected.
+    pub rw_deinterleaved: bool,
+    /// Whether C2 error pointers can be read.
+    pub c2_pointers: bool,
+    pub isrc: bool,
+    pub upc: bool,
+    /// The maximum read speed in kB/s (obsolete since MMC-3, but still
+    /// reported by most drives).
+    pub max_read_speed: u16,
+    /// The current read speed in kB/s.
+    pub current_read_speed: u16,
+    /// The size of the buffer in KiB.
+    pub buffer_size: u16,
+}
+
+impl Command for ModeSense2A {
+    type Response = CapabilitiesPage;
+
+    fn cdb(&self) -> Vec<u8> {
+        let [len_msb, len_lsb] = (self.response_len() as u16).to_be_bytes();
+        // no block descriptors, current values
+        vec![0x5a, 0x08, 0x2a, 0, 0, 0, 0, len_msb, len_lsb, 0]
+    }
+    fn response_len(&self) -> usize {
+        256
+    }
+    fn decode(&self, data: &[u8]) -> Result<CapabilitiesPage> {
+        require(data, 8)?;
+        let start = 8 + usize::from(u16_at(data, 6));
+        require(data, start + 8)?;
+        let page = &data[start..];
+        if page[0] & 0x3f != 0x2a {
+            return Err(MmcError::InvalidResponse.into());
+        }
+
+        let end = (2 + usize::from(page[1])).min(page.len());
+        let byte = |index: usize| if index < end { page[index] } else { 0 };
+        let word = |index: usize| u16::from_be_bytes([byte(index), byte(index + 1)]);
+        let bit = |index: usize, bit: u8| byte(index) & (1 << bit) != 0;
+        Ok(CapabilitiesPage {
+            read_cd_r: bit(2, 0),
+            read_cd_rw: bit(2, 1),
+            audio_play: bit(4, 0),
+            cdda: bit(5, 0),
+            accurate_stream: bit(5, 1),
+            rw_subchannel: bit(5, 2),
+            rw_deinterleaved: bit(5, 3),
+            c2_pointers: bit(5, 4),
+            isrc: bit(5, 5),
+            upc: bit(5, 6),
+            max_read_speed: word(8),
+            current_read_speed: word(14),
+            buffer_size: word(12),
+        })
+    }
+}
+
+/// READ TOC/PMA/ATIP (43h).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReadToc {
+    format: TocFormat,
+    track: u8,
+}
+
+/// What [`ReadToc`] reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TocFormat {
+    /// The formatted table of contents, see [`TocResponse::tracks()`].
+    Toc = 0,
+    SessionInfo = 1,
+    /// All Q subchannel entries of the lead-in.
+    FullToc = 2,
+    /// The program memory area of recordable discs.
+    Pma = 3,
+    /// The absolute time in pregroove of recordable discs,
+    /// see [`TocResponse::atip()`].
+    Atip = 4,
+    /// The CD-TEXT packs of the lead-in, see [`TocResponse::cd_text_packs()`].
+    CdText = 5,
+}
+
+/// The response to [`ReadToc`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TocResponse {
+    pub format: TocFormat,
+    /// The first track or session, depending on the format.
+    pub first: u8,
+    /// The last track or session, depending on the format.
+    pub last: u8,
+    /// The descriptors after the header.
+    pub descriptors: Vec<u8>,
+}
+
+/// A track descriptor of [`TocFormat::Toc`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TocTrack {
+    /// The track number, `0xaa` for the lead-out.
+    pub number: u8,
+    pub adr: u8,
+    pub control: u8,
+    /// The logical block address of the start of the track.
+    pub address: i32,
+}
+
+/// The ATIP of a recordable disc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Atip {
+    pub rewritable: bool,
+    /// The logical block address of the start of the lead-in.
+    pub lead_in_start: i32,
+    /// The logical block address of the last possible start of the lead-out.
+    pub last_lead_out_start: i32,
+}
+
+impl ReadToc {
+    pub fn new(format: TocFormat) -> Self {
+        Self { format, track: 0 }
+    }
+    /// Start at this track or session, depending on the format.
+    pub fn with_track(mut self, track: u8) -> Self {
+        self.track = track;
+        self
+    }
+}
+
+impl Command for ReadToc {
+    type Response = TocResponse;
+
+    fn cdb(&self) -> Vec<u8> {
+        let [len_msb, len_lsb] = (self.response_len() as u16).to_be_bytes();
+        // addresses as LBA
+        vec![
+            0x43,
+            0,
+            self.format as u8,
+            0,
+            0,
+            0,
+            self.track,
+            len_msb,
+            len_lsb,
+            0,
+        ]
+    }
+    fn response_len(&self) -> usize {
+        match self.format {
+            TocFormat::CdText => 0x8000,
+            _ => 4096,
+        }
+    }
+    fn decode(&self, data: &[u8]) -> Result<TocResponse> {
+        require(data, 4)?;
+        let len = usize::from(u16_at(data, 0));
+        let data = &data[..(2 + len).clamp(4, data.len())];
+        Ok(TocResponse {
+            format: self.format,
+            first: data[2],
+            last: data[3],
+            descriptors: data[4..].to_vec(),
+        })
+    }
+}
+
+impl TocResponse {
+    /// Get the track descriptors of [`TocFormat::Toc`].
+    pub fn tracks(&self) -> Vec<TocTrack> {
+        if self.format != TocFormat::Toc {
+            return Vec::new();
+        }
+        self.descriptors
+            .chunks_exact(8)
+            .map(|descriptor| TocTrack {
+                number: descriptor[2],
+                adr: descriptor[1] >> 4,
+                control: descriptor[1] & 0x0f,
+                address: i32::from_be_bytes([
+                    descriptor[4],
+                    descriptor[5],
+                    descriptor[6],
+                    descriptor[7],
+                ]),
+            })
+            .collect()
+    }
+    /// Get the ATIP of [`TocFormat::Atip`].
+    pub fn atip(&self) -> Option<Atip> {
+        let descriptor = self.descriptors.get(..11)?;
+        (self.format == TocFormat::Atip).then(|| Atip {
+            rewritable: descriptor[2] & 0x40 != 0,
+            lead_in_start: msf_to_lba(descriptor[4], descriptor[5], descriptor[6]),
+            last_lead_out_start: msf_to_lba(descriptor[8], descriptor[9], descriptor[10]),
+        })
+    }
+    /// Get the 18-byte packs of [`TocFormat::CdText`].
+    pub fn cd_text_packs(&self) -> Vec<&[u8]> {
+        if self.format != TocFormat::CdText {
+            return Vec::new();
+        }
+        self.descriptors.chunks_exact(18).collect()
+    }
+}
+
+/// READ SUB-CHANNEL (42h) of the Q subchannel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReadSubChannel {
+    /// The current position of the optical head.
+    CurrentPosition,
+    /// The media catalog number of the disc.
+    Mcn,
+    /// The International Standard Recording Code of a track.
+    Isrc { track: u8 },
+}
+
+/// The response to [`ReadSubChannel`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SubChannel {
+    Position {
+        track: u8,
+        index: u8,
+        adr: u8,
+        control: u8,
+        /// The logical block address on the disc.
+        absolute: i32,
+        /// The address relative to the start of the track.
+        relative: i32,
+    },
+    /// The media catalog number, if the disc has one.
+    Mcn(Option<String>),
+    /// The ISRC, if the track has one.
+    Isrc(Option<String>),
+}
+
+impl Command for ReadSubChannel {
+    type Response = SubChannel;
+
+    fn cdb(&self) -> Vec<u8> {
+        let (format, track) = match self {
+            ReadSubChannel::CurrentPosition => (1, 0),
+            ReadSubChannel::Mcn => (2, 0),
+            ReadSubChannel::Isrc { track } => (3, *track),
+        };
+        let [len_msb, len_lsb] = (self.response_len() as u16).to_be_bytes();
+        // addresses as LBA, Q subchannel data
+        vec![0x42, 0, 0x40, format, 0, 0, track, len_msb, len_lsb, 0]
+    }
+    fn response_len(&self) -> usize {
+        48
+    }
+    fn decode(&self, data: &[u8]) -> Result<SubChannel> {
+        let address = |index: usize| {
+            i32::from_be_bytes([
+                data[index],
+                data[index + 1],
+                data[index + 2],
+                data[index + 3],
+            ])
+        };
+        Ok(match self {
+            ReadSubChannel::CurrentPosition => {
+                require(data, 16)?;
+                SubChannel::Position {
+                    track: data[6],
+                    index: data[7],
+                    adr: data[5] >> 4,
+                    control: data[5] & 0x0f,
+                    absolute: address(8),
+                    relative: address(12),
+                }
+            }
+            ReadSubChannel::Mcn => {
+                require(data, 22)?;
+                SubChannel::Mcn(
+                    (data[8] & 0x80 != 0)
+                        .then(|| ascii(&data[9..22]))
+                        .filter(|mcn| !mcn.is_empty()),
+                )
+            }
+            ReadSubChannel::Isrc { .. } => {
+                require(data, 21)?;
+                SubChannel::Isrc(
+                    (data[8] & 0x80 != 0)
+                        .then(|| ascii(&data[9..21]))
+                        .filter(|isrc| !isrc.is_empty()),
+                )
+            }
+        })
+    }
+}
+
+/// READ CD (BEh) of audio sectors.
+///
+/// Each sector is returned as [`SECTOR_BYTES`] bytes of audio data,
+/// followed by [`C2_BYTES`] bytes of C2 error pointers if requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReadCd {
+    lsn: u32,
+    sectors: u32,
+    c2_pointers: bool,
+}
+
+impl ReadCd {
+    pub fn new(lsn: u32, sectors: u32) -> Self {
+        Self {
+            lsn,
+            sectors,
+            c2_pointers: false,
+        }
+    }
+    /// Request the C2 error pointers of every sector.
+    pub fn with_c2_pointers(mut self) -> Self {
+        self.c2_pointers = true;
+        self
+    }
+    /// Get the number of bytes returned for each sector.
+    pub fn sector_len(&self) -> usize {
+        if self.c2_pointers {
+            SECTOR_BYTES + C2_BYTES
+        } else {
+            SECTOR_BYTES
+        }
+    }
+}
+
+impl Command for ReadCd {
+    type Response = Vec<u8>;
+
+    fn cdb(&self) -> Vec<u8> {
+        let mut cdb = vec![0; 12];
+        cdb[0] = 0xbe;
+        // expected sector type: CD-DA
+        cdb[1] = 1 << 2;
+        cdb[2..6].copy_from_slice(&self.lsn.to_be_bytes());
+        cdb[6..9].copy_from_slice(&self.sectors.to_be_bytes()[1..]);
+        // user data, and C2 error pointers without block error bits
+        cdb[9] = if self.c2_pointers { 0x12 } else { 0x10 };
+        cdb
+    }
+    fn response_len(&self) -> usize {
+        self.sectors as usize * self.sector_len()
+    }
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        require(data, self.response_len())?;
+        Ok(data[..self.response_len()].to_vec())
+    }
+}
+
+/// SET CD SPEED (BBh).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SetCdSpeed {
+    read_speed: u16,
+}
+
+impl SetCdSpeed {
+    /// Set the read speed in kB/s, e.g. `1764` for 10x.
+    pub fn new(read_speed: u16) -> Self {
+        Self { read_speed }
+    }
+    /// Set the maximum read speed.
+    pub fn max() -> Self {
+        Self::new(u16::MAX)
+    }
+}
+
+impl Command for SetCdSpeed {
+    type Response = ();
+
+    fn cdb(&self) -> Vec<u8> {
+        let [read_msb, read_lsb] = self.read_speed.to_be_bytes();
+        // the write speed is left at the maximum
+        vec![0xbb, 0, read_msb, read_lsb, 0xff, 0xff, 0, 0, 0, 0, 0, 0]
+    }
+    fn response_len(&self) -> usize {
+        0
+    }
+    fn decode(&self, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOT_READY: Sense = Sense {
+        key: 0x02,
+        asc: 0x3a,
+        ascq: 0x00,
+    };
+
+    #[test]
+    fn check_condition() {
+        let transport = MockTransport::new()
+            .with_sense(Inquiry.cdb(), NOT_READY)
+            .with_response(Inquiry.cdb(), [0; 36]);
+
+        let err = Inquiry.run(&transport).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Mmc(MmcError::CheckCondition(NOT_READY))
+        ));
+        assert_eq!(
+            err.to_string(),
+            "the drive rejected the command: sense key 2h, ASC 3Ah, ASCQ 00h"
+        );
+        assert_eq!(transport.remaining(), 1);
+        assert!(Inquiry.run(&transport).is_ok());
+        assert_eq!(transport.commands(), [Inquiry.cdb(), Inquiry.cdb()]);
+    }
+
+    #[test]
+    fn unexpected_command() {
+        let transport = MockTransport::new().with_response(ModeSense2A.cdb(), []);
+        assert!(matches!(
+            Inquiry.run(&transport),
+            Err(Error::Mmc(MmcError::UnexpectedCommand(cdb))) if cdb == Inquiry.cdb()
+        ));
+        // the response is still there for the right command
+        assert_eq!(transport.remaining(), 1);
+        assert!(matches!(
+            Inquiry.run(&MockTransport::new()),
+            Err(Error::Mmc(MmcError::UnexpectedCommand(_)))
+        ));
+    }
+
+    #[test]
+    fn short_transfers() {
+        let transport = MockTransport::new()
+            .with_response(Inquiry.cdb(), [0; 40])
+            .with_response(ReadCd::new(0, 1).cdb(), [0; SECTOR_BYTES]);
+
+        let mut buf = [0xff; 96];
+        assert_eq!(
+            transport
+                .execute(&Inquiry.cdb(), Data::In(&mut buf))
+                .unwrap(),
+            40
+        );
+        assert!(buf[..40].iter().all(|&byte| byte == 0));
+        assert!(buf[40..].iter().all(|&byte| byte == 0xff));
+
+        let mut buf = [0; 16];
+        assert_eq!(
+            transport
+                .execute(&ReadCd::new(0, 1).cdb(), Data::In(&mut buf))
+                .unwrap(),
+            16
+        );
+    }
+
+    #[test]
+    fn read_cd_requires_all_sectors() {
+        let read = ReadCd::new(100, 2).with_c2_pointers();
+        let transport = MockTransport::new()
+            .with_response(read.cdb(), vec![1; 2 * read.sector_len() - 1])
+            .with_response(read.cdb(), vec![1; 2 * read.sector_len() + 1]);
+
+        assert!(matches!(
+            read.run(&transport),
+            Err(Error::Mmc(MmcError::InvalidResponse))
+        ));
+        assert_eq!(
+            read.run(&transport).unwrap(),
+            vec![1; 2 * read.sector_len()]
+        );
+    }
+
+    #[test]
+    fn sense_formats() {
+        assert_eq!(Sense::parse(&NOT_READY.to_bytes()), Some(NOT_READY));
+        assert_eq!(Sense::parse(&[0x72, 0x02, 0x3a, 0x00]), Some(NOT_READY));
+        assert_eq!(Sense::parse(&NOT_READY.to_bytes()[..13]), None);
+        assert_eq!(Sense::parse(&[]), None);
+    }
+
+    #[test]
+    fn truncated_feature_descriptor() {
+        let mut response = vec![0, 0, 0, 0x14, 0, 0, 0, 0x08];
+        // CD Read, complete
+        response.extend([0x00, 0x1e, 0x09, 0x04, 0x03, 0, 0, 0]);
+        // Real Time Streaming, cut off after 2 of 4 bytes
+        response.extend([0x01, 0x07, 0x05, 0x04, 0x1f, 0]);
+        let transport = MockTransport::new().with_response(GetConfiguration::new().cdb(), response);
+
+        let configuration = GetConfiguration::new().run(&transport).unwrap();
+        assert_eq!(configuration.current_profile, 0x08);
+        assert_eq!(
+            configuration.features,
+            [Feature {
+                code: GetConfiguration::CD_READ,
+                version: 2,
+                persistent: false,
+                current: true,
+                data: vec![3, 0, 0, 0],
+            }]
+        );
+    }
+
+    #[test]
+    fn recorded_transcript_replays() {
+        let mut inquiry = vec![0x05, 0x80, 0x05, 0x32, 0x5b, 0, 0, 0];
+        inquiry.extend(b"PLEXTOR DVDR   PX-716A  1.11");
+        // trailing zeros are part of the response
+        inquiry.extend([0; 4]);
+        let mut toc = vec![0, 0x12, 1, 1];
+        toc.extend([0, 0x10, 1, 0, 0, 0, 0, 0]);
+        toc.extend([0, 0x10, 0xaa, 0, 0, 0, 0x10, 0]);
+        let drive = MockTransport::new()
+            .with_response(Inquiry.cdb(), inquiry.clone())
+            .with_sense(ModeSense2A.cdb(), NOT_READY)
+            .with_response(ReadToc::new(TocFormat::Toc).cdb(), toc)
+            .with_response(SetCdSpeed::max().cdb(), []);
+
+        let recorder = Recorder::new(drive);
+        let inquiry_data = Inquiry.run(&recorder).unwrap();
+        assert!(ModeSense2A.run(&recorder).is_err());
+        let toc_data = ReadToc::new(TocFormat::Toc).run(&recorder).unwrap();
+        SetCdSpeed::max().run(&recorder).unwrap();
+        let transcript = recorder.transcript();
+        assert_eq!(recorder.into_inner().remaining(), 0);
+
+        let replay = MockTransport::from_transcript(&transcript).unwrap();
+        assert_eq!(Inquiry.run(&replay).unwrap(), inquiry_data);
+        assert!(matches!(
+            ModeSense2A.run(&replay),
+            Err(Error::Mmc(MmcError::CheckCondition(NOT_READY)))
+        ));
+        assert_eq!(ReadToc::new(TocFormat::Toc).run(&replay).unwrap(), toc_data);
+        SetCdSpeed::max().run(&replay).unwrap();
+        assert_eq!(replay.remaining(), 0);
+
+        let mut buf = [0; 96];
+        let replay = MockTransport::from_transcript(&transcript).unwrap();
+        assert_eq!(
+            replay.execute(&Inquiry.cdb(), Data::In(&mut buf)).unwrap(),
+            inquiry.len()
+        );
+    }
+
+    #[test]
+    fn invalid_transcripts() {
+        for (transcript, line) in [
+            ("< 00", 1),
+            ("> 12 00\n< 0g", 2),
+            ("> 12 00\n! 02 3a", 2),
+            ("> 12 00\n! 02 3a 00\n< 00", 3),
+            ("# comment\n\n? 00", 3),
+        ] {
+            assert!(
+                matches!(
+                    MockTransport::from_transcript(transcript),
+                    Err(Error::Mmc(MmcError::InvalidTranscript(l))) if l == line
+                ),
+                "{transcript:?}"
+            );
+        }
+    }
+}
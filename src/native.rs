@@ -0,0 +1,570 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Access to CD-ROM drives through the ioctl interface of the Linux kernel's
+//! CD-ROM driver, used when no C library is enabled.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    device, options::Interface, quirks::Quirks, sink::Endianness, DriveInfo, DriveOptions, Error,
+    Paranoia, ParanoiaError, Result, SECTOR_WORDS,
+};
+
+const CDROMREADTOCHDR: libc::c_ulong = 0x5305;
+const CDROMREADTOCENTRY: libc::c_ulong = 0x5306;
+const CDROMREADAUDIO: libc::c_ulong = 0x530e;
+const CDROM_GET_MCN: libc::c_ulong = 0x5311;
+const CDROM_SELECT_SPEED: libc::c_ulong = 0x5322;
+const CDROM_DRIVE_STATUS: libc::c_ulong = 0x5326;
+const SG_IO: libc::c_ulong = 0x2285;
+const SG_DXFER_NONE: libc::c_int = -1;
+const SG_DXFER_TO_DEV: libc::c_int = -2;
+const SG_DXFER_FROM_DEV: libc::c_int = -3;
+const SG_INFO_OK_MASK: libc::c_uint = 0x1;
+/// Timeout of raw MMC commands in milliseconds.
+const MMC_TIMEOUT_MS: libc::c_uint = 10_000;
+const CDSL_CURRENT: libc::c_int = libc::c_int::MAX;
+const CDS_DISC_OK: libc::c_int = 4;
+const CDROM_LBA: u8 = 0x01;
+const CDROM_LEADOUT: u8 = 0xaa;
+/// Default number of sectors per `CDROMREADAUDIO` call, the kernel's maximum.
+const CD_FRAMES: usize = 75;
+
+/// Devices that are tried by [`Drive::find()`], in this order.
+const SEARCH_PATHS: &[&str] = &[
+    "/dev/cdrom",
+    "/dev/sr0",
+    "/dev/sr1",
+    "/dev/sr2",
+    "/dev/sr3",
+    "/dev/scd0",
+    "/dev/scd1",
+    "/dev/scd2",
+    "/dev/scd3",
+];
+
+/// `struct cdrom_tochdr`
+#[repr(C)]
+#[derive(Default)]
+struct TocHeader {
+    first_track: u8,
+    last_track: u8,
+}
+
+/// `struct cdrom_tocentry` with `union cdrom_addr` in LBA format
+#[repr(C)]
+struct RawTocEntry {
+    track: u8,
+    adr_ctrl: u8,
+    format: u8,
+    addr: libc::c_int,
+    datamode: u8,
+}
+
+/// `struct cdrom_read_audio` with `union cdrom_addr` in LBA format
+#[repr(C)]
+struct ReadAudio {
+    addr: libc::c_int,
+    addr_format: u8,
+    nframes: libc::c_int,
+    buf: *mut u8,
+}
+
+/// `struct cdrom_mcn`
+#[repr(C)]
+#[derive(Default)]
+struct Mcn {
+    medium_catalog_number: [u8; 14],
+}
+
+/// `struct sg_io_hdr`
+#[repr(C)]
+struct SgIoHeader {
+    interface_id: libc::c_int,
+    dxfer_direction: libc::c_int,
+    cmd_len: u8,
+    mx_sb_len: u8,
+    iovec_count: libc::c_ushort,
+    dxfer_len: libc::c_uint,
+    dxferp: *mut libc::c_void,
+    cmdp: *const u8,
+    sbp: *mut u8,
+    timeout: libc::c_uint,
+    flags: libc::c_uint,
+    pack_id: libc::c_int,
+    usr_ptr: *mut libc::c_void,
+    status: u8,
+    masked_status: u8,
+    msg_status: u8,
+    sb_len_wr: u8,
+    host_status: libc::c_ushort,
+    driver_status: libc::c_ushort,
+    resid: libc::c_int,
+    duration: libc::c_uint,
+    info: libc::c_uint,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TocEntry {
+    ctrl: u8,
+    lsn: u32,
+}
+
+/// Represents a physical CD-ROM drive.
+///
+/// Use [`Drive::find()`] to get a default drive or [`Drive::open()`]
+/// to get a specific drive with a CD-DA in it. [`DriveOptions`] opens
+/// drives with non-default options.
+///
+/// For reading audio data, get a [`Paranoia`] instance using the [`paranoia()`](Drive::paranoia) method.
+#[derive(Debug)]
+pub struct Drive {
+    file: File,
+    path: PathBuf,
+    /// All tracks followed by the lead-out.
+    toc: Vec<TocEntry>,
+    sectors_per_read: usize,
+    endianness: Endianness,
+    quirks: Quirks,
+}
+
+impl Drive {
+    /// Open a default CD-ROM drive with a CD-DA in it.
+    pub fn find() -> Result<Self> {
+        DriveOptions::new().find()
+    }
+    /// Open a specific CD-ROM drive with a CD-DA in it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        DriveOptions::new().open(path)
+    }
+    pub(crate) fn find_with(options: &DriveOptions) -> Result<Self> {
+        SEARCH_PATHS
+            .iter()
+            .find_map(|path| Self::open_with(Path::new(path), options).ok())
+            .ok_or(Error::CantOpenDrive)
+    }
+    /// The kernel's CD-ROM driver doesn't produce any messages, so the
+    /// message sink and verbosity of the options are never used.
+    pub(crate) fn open_with(path: &Path, options: &DriveOptions) -> Result<Self> {
+        options.validate()?;
+        if options.interface != Interface::Auto {
+            return Err(ParanoiaError::InterfaceNotSupported.into());
+        }
+        let file = open_device(path)?;
+
+        let mut header = TocHeader::default();
+        if unsafe { libc::ioctl(file.as_raw_fd(), CDROMREADTOCHDR as _, &mut header) } < 0 {
+            return Err(ParanoiaError::ReadTocHeader.into());
+        }
+        if header.first_track == 0 || header.last_track < header.first_track {
+            return Err(ParanoiaError::IllegalNumberOfTracks.into());
+        }
+
+        let mut toc = (header.first_track..=header.last_track)
+            .chain([CDROM_LEADOUT])
+            .map(|track| {
+                let mut entry = RawTocEntry {
+                    track,
+                    adr_ctrl: 0,
+                    format: CDROM_LBA,
+                    addr: 0,
+                    datamode: 0,
+                };
+                if unsafe { libc::ioctl(file.as_raw_fd(), CDROMREADTOCENTRY as _, &mut entry) } < 0
+                {
+                    return Err(ParanoiaError::ReadTocEntry.into());
+                }
+                #[cfg(target_endian = "little")]
+                let ctrl = entry.adr_ctrl >> 4;
+                #[cfg(target_endian = "big")]
+                let ctrl = entry.adr_ctrl & 0x0f;
+                let lsn = entry
+                    .addr
+                    .try_into()
+                    .map_err(|_| ParanoiaError::IllegalToc)?;
+                Ok(TocEntry { ctrl, lsn })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        check_toc(&toc)?;
+        if options.toc_bias {
+            let offset = toc[0].lsn;
+            for entry in &mut toc {
+                entry.lsn -= offset;
+            }
+        }
+
+        let [vendor, model, revision] = sysfs_info(path);
+        let quirks = options.quirks.lookup_hardware((vendor, model, revision));
+        let drive = Drive {
+            file,
+            path: path.to_owned(),
+            toc,
+            // the kernel rejects larger reads
+            sectors_per_read: options
+                .sectors_per_read
+                .or(quirks.sectors_per_read)
+                .map_or(CD_FRAMES, |sectors| (sectors as usize).min(CD_FRAMES)),
+            endianness: options.endianness.unwrap_or_default(),
+            quirks,
+        };
+        if let Some(speed) = options.speed {
+            drive.set_speed(speed)?;
+        }
+        Ok(drive)
+    }
+    /// List all CD-ROM drives, whether they contain a disc or not.
+    ///
+    /// Drives are found through sysfs, with the paths that are tried by
+    /// [`Drive::find()`] as a fallback.
+    pub fn list() -> Vec<DriveInfo> {
+        let mut names: Vec<_> = fs::read_dir("/sys/class/block")
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.starts_with("sr"))
+            .collect();
+        names.sort();
+        let paths: Vec<_> = if names.is_empty() {
+            SEARCH_PATHS
+                .iter()
+                .map(PathBuf::from)
+                .filter(|path| path.exists())
+                .collect()
+        } else {
+            names
+                .iter()
+                .map(|name| Path::new("/dev").join(name))
+                .collect()
+        };
+
+        device::dedup_devices(paths)
+            .into_iter()
+            .filter_map(|path| {
+                let file = open_device(&path).ok()?;
+                let status =
+                    unsafe { libc::ioctl(file.as_raw_fd(), CDROM_DRIVE_STATUS as _, CDSL_CURRENT) };
+                if status < 0 {
+                    // not a CD-ROM drive
+                    return None;
+                }
+                let disc_present = status == CDS_DISC_OK;
+                let audio = disc_present
+                    && Self::open(&path).is_ok_and(|drive| {
+                        (1..=drive.tracks()).any(|track| drive.track_audio(track))
+                    });
+                let [vendor, model, revision] = sysfs_info(&path);
+                Some(DriveInfo {
+                    path,
+                    vendor,
+                    model,
+                    revision,
+                    disc_present,
+                    audio,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Check that the tracks and the lead-out start at increasing sectors, so
+/// every track has at least one sector.
+fn check_toc(toc: &[TocEntry]) -> Result<()> {
+    if toc
+        .windows(2)
+        .all(|entries| entries[0].lsn < entries[1].lsn)
+    {
+        Ok(())
+    } else {
+        Err(ParanoiaError::IllegalToc.into())
+    }
+}
+
+fn open_device(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .map_err(|_| Error::CantOpenDrive)
+}
+
+/// Read vendor, model and revision of a SCSI device from sysfs.
+fn sysfs_info(path: &Path) -> [Option<String>; 3] {
+    let Some(name) = path
+        .canonicalize()
+        .ok()
+        .and_then(|path| path.file_name().map(ToOwned::to_owned))
+    else {
+        return [None, None, None];
+    };
+    let device = Path::new("/sys/class/block").join(name).join("device");
+    ["vendor", "model", "rev"].map(|attribute| {
+        fs::read_to_string(device.join(attribute))
+            .ok()
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty())
+    })
+}
+
+impl Drive {
+    /// Get a [`Paranoia`] instance for reading audio data.
+    pub fn paranoia(self) -> Paranoia {
+        Paranoia::new(self)
+    }
+}
+
+impl Drive {
+    fn toc_entry(&self, track: u8) -> Result<&TocEntry> {
+        if track == 0 || track > self.tracks() {
+            return Err(ParanoiaError::InvalidTrackNumber.into());
+        }
+        Ok(&self.toc[usize::from(track - 1)])
+    }
+    /// Get the logical sector number for the start of a track.
+    pub fn track_first_sector(&self, track: u8) -> Result<u32> {
+        Ok(self.toc_entry(track)?.lsn)
+    }
+    /// Get the last logical sector number of a track.
+    /// This is generally one less than the start of the next track.
+    pub fn track_last_sector(&self, track: u8) -> Result<u32> {
+        self.toc_entry(track)?;
+        // can't underflow, see `check_toc()`
+        Ok(self.toc[usize::from(track)].lsn - 1)
+    }
+    /// Get the number of tracks on the CD.
+    pub fn tracks(&self) -> u8 {
+        (self.toc.len() - 1).try_into().unwrap()
+    }
+    /// Get the track containing the given logical sector number.
+    ///
+    /// If the LSN is before the first track (in the pregap), 0 is returned.
+    pub fn sector_track(&self, lsn: u32) -> Result<u8> {
+        if lsn >= self.toc[self.toc.len() - 1].lsn {
+            return Err(ParanoiaError::InvalidTrackNumber.into());
+        }
+        Ok(self.toc.iter().filter(|entry| entry.lsn <= lsn).count() as u8)
+    }
+    /// Get the number of channels in a track.
+    ///
+    /// Returns `Some(2)` or `Some(4)` on success or
+    /// `None` if the value could not be retrieved.
+    pub fn track_channels(&self, track: u8) -> Option<u8> {
+        let entry = self.toc_entry(track).ok()?;
+        Some(if entry.ctrl & 0x08 != 0 { 4 } else { 2 })
+    }
+    /// Check if a track is an audio track.
+    pub fn track_audio(&self, track: u8) -> bool {
+        self.toc_entry(track)
+            .is_ok_and(|entry| entry.ctrl & 0x04 == 0)
+    }
+    /// Check if a track has copy permit set.
+    pub fn track_copy_permitted(&self, track: u8) -> bool {
+        self.toc_entry(track)
+            .is_ok_and(|entry| entry.ctrl & 0x02 != 0)
+    }
+    /// Check if a track has linear preemphasis set.
+    ///
+    /// Only makes sense for audio tracks.
+    pub fn track_linear_preemphasis(&self, track: u8) -> bool {
+        self.toc_entry(track)
+            .is_ok_and(|entry| entry.ctrl & 0x01 != 0)
+    }
+    /// Get the first logical sector number of the first audio track.
+    pub fn disc_first_sector(&self) -> Result<u32> {
+        let track = (1..=self.tracks())
+            .find(|&track| self.track_audio(track))
+            .ok_or(ParanoiaError::NoAudioTracks)?;
+        self.track_first_sector(track)
+    }
+    /// Get the last logical sector number of the last audio track.
+    pub fn disc_last_sector(&self) -> Result<u32> {
+        let track = (1..=self.tracks())
+            .rev()
+            .find(|&track| self.track_audio(track))
+            .ok_or(ParanoiaError::NoAudioTracks)?;
+        self.track_last_sector(track)
+    }
+    /// Get the media catalog number (UPC/EAN) of the CD, if available.
+    pub fn disc_mcn(&self) -> Option<String> {
+        let mut mcn = Mcn::default();
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), CDROM_GET_MCN as _, &mut mcn) } < 0 {
+            return None;
+        }
+        let mcn = &mcn.medium_catalog_number[..13];
+        (mcn.iter().all(u8::is_ascii_digit) && mcn.iter().any(|&digit| digit != b'0'))
+            .then(|| String::from_utf8_lossy(mcn).into_owned())
+    }
+    /// Get the International Standard Recording Code of a track, if available.
+    ///
+    /// The kernel's CD-ROM driver doesn't provide ISRCs,
+    /// so this always returns `None`.
+    pub fn track_isrc(&self, _track: u8) -> Option<String> {
+        None
+    }
+    /// Get the first logical sector number of a track's pregap (index 0),
+    /// if it is known.
+    ///
+    /// The kernel's CD-ROM driver doesn't provide pregaps,
+    /// so this always returns `None`.
+    pub fn track_pregap_sector(&self, _track: u8) -> Option<u32> {
+        None
+    }
+}
+
+impl Drive {
+    /// Get the vendor, model and revision of the drive as reported by
+    /// sysfs, e.g. `"PLEXTOR DVDR PX-716A 1.11"`.
+    pub fn model(&self) -> Option<String> {
+        let name = sysfs_info(&self.path)
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some(name).filter(|name| !name.is_empty())
+    }
+    /// Get the quirks of the drive model, see [`quirks`](crate::quirks).
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+    /// Get the path of the device that is used for reading audio.
+    pub fn device_name(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+}
+
+impl Drive {
+    /// Set the read speed as a multiple of 176.4 kB/s, or `-1` for the
+    /// maximum speed.
+    ///
+    /// Not all drives support this.
+    pub fn set_speed(&self, speed: i32) -> Result<()> {
+        let speed: libc::c_int = speed.max(0);
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), CDROM_SELECT_SPEED as _, speed) } < 0 {
+            return Err(ParanoiaError::NotSupported.into());
+        }
+        Ok(())
+    }
+}
+
+impl Drive {
+    /// Read raw audio sectors without any verification or error correction.
+    ///
+    /// Reads as many whole sectors starting at `first_lsn` as fit into `buf`
+    /// and returns the number of sectors read.
+    pub fn read_raw(&self, first_lsn: u32, buf: &mut [i16]) -> Result<usize> {
+        let mut sectors = 0;
+        let chunk_words = self.sectors_per_read * SECTOR_WORDS;
+        for chunk in buf.chunks_exact_mut(chunk_words) {
+            sectors += self.read_audio(first_lsn + sectors as u32, chunk)?;
+        }
+        let rest = buf.chunks_exact_mut(chunk_words).into_remainder();
+        if rest.len() >= SECTOR_WORDS {
+            sectors += self.read_audio(first_lsn + sectors as u32, rest)?;
+        }
+        Ok(sectors)
+    }
+    fn read_audio(&self, lsn: u32, buf: &mut [i16]) -> Result<usize> {
+        let sectors = buf.len() / SECTOR_WORDS;
+        let mut request = ReadAudio {
+            addr: lsn
+                .try_into()
+                .map_err(|_| ParanoiaError::UnaddressableSector)?,
+            addr_format: CDROM_LBA,
+            nframes: sectors as libc::c_int,
+            buf: buf.as_mut_ptr().cast(),
+        };
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), CDROMREADAUDIO as _, &mut request) } < 0 {
+            return Err(Error::Read);
+        }
+        // drives return little endian samples unless forced otherwise
+        for sample in &mut buf[..sectors * SECTOR_WORDS] {
+            *sample = match self.endianness {
+                Endianness::Little => i16::from_le(*sample),
+                Endianness::Big => i16::from_be(*sample),
+            };
+        }
+        Ok(sectors)
+    }
+}
+
+impl crate::mmc::Transport for Drive {
+    /// Send a raw command through the SG_IO ioctl.
+    fn execute(&self, cdb: &[u8], data: crate::mmc::Data<'_>) -> Result<usize> {
+        use crate::mmc::{Data, MmcError, Sense};
+
+        let (direction, len, buf) = match data {
+            Data::None => (SG_DXFER_NONE, 0, std::ptr::null_mut()),
+            Data::In(buf) => (SG_DXFER_FROM_DEV, buf.len(), buf.as_mut_ptr()),
+            Data::Out(buf) => (SG_DXFER_TO_DEV, buf.len(), buf.as_ptr().cast_mut()),
+        };
+        let mut sense = [0u8; 32];
+        let mut header = SgIoHeader {
+            interface_id: b'S'.into(),
+            dxfer_direction: direction,
+            cmd_len: cdb.len() as u8,
+            mx_sb_len: sense.len() as u8,
+            iovec_count: 0,
+            dxfer_len: len as libc::c_uint,
+            dxferp: buf.cast(),
+            cmdp: cdb.as_ptr(),
+            sbp: sense.as_mut_ptr(),
+            timeout: MMC_TIMEOUT_MS,
+            flags: 0,
+            pack_id: 0,
+            usr_ptr: std::ptr::null_mut(),
+            status: 0,
+            masked_status: 0,
+            msg_status: 0,
+            sb_len_wr: 0,
+            host_status: 0,
+            driver_status: 0,
+            resid: 0,
+            duration: 0,
+            info: 0,
+        };
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), SG_IO as _, &mut header) } < 0 {
+            let err = std::io::Error::last_os_error();
+            return Err(match err.raw_os_error() {
+                Some(libc::ENOTTY | libc::EINVAL) => MmcError::Unsupported.into(),
+                _ => err.into(),
+            });
+        }
+        if header.info & SG_INFO_OK_MASK == 0 {
+            // the residual count is the part of the buffer that wasn't
+            // transferred
+            let resid = usize::try_from(header.resid).unwrap_or(0);
+            return Ok(len.saturating_sub(resid));
+        }
+        Err(
+            match Sense::parse(&sense[..usize::from(header.sb_len_wr).min(sense.len())]) {
+                Some(sense) => MmcError::CheckCondition(sense),
+                None => MmcError::Failed(header.status.into()),
+            }
+            .into(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toc(lsns: &[u32]) -> Vec<TocEntry> {
+        lsns.iter().map(|&lsn| TocEntry { ctrl: 0, lsn }).collect()
+    }
+
+    #[test]
+    fn tocs_must_increase() {
+        assert!(check_toc(&toc(&[0, 150, 300])).is_ok());
+        assert!(check_toc(&toc(&[150, 0])).is_err());
+        assert!(check_toc(&toc(&[0, 150, 150])).is_err());
+        assert!(matches!(
+            check_toc(&toc(&[150, 300, 0])),
+            Err(Error::Paranoia(ParanoiaError::IllegalToc))
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Options for opening a [`Drive`], see [`DriveOptions`].
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    message::{self, MessageSink},
+    quirks::QuirkDatabase,
+    sink::Endianness,
+    Drive, Error, Result,
+};
+
+/// The largest number of sectors per read that can be forced,
+/// like `cdparanoia -n`.
+pub const MAX_SECTORS_PER_READ: u32 = 100;
+
+/// Which messages of the library are passed to the message sink.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Drop all messages.
+    Quiet,
+    /// Only pass on errors.
+    Errors,
+    /// Pass on errors and informational messages.
+    #[default]
+    Verbose,
+}
+
+/// The interface that is used to talk to a drive.
+///
+/// Only cdparanoia-3 can force an interface, opening a drive with anything
+/// but [`Interface::Auto`] fails with
+/// [`ParanoiaError::InterfaceNotSupported`](crate::ParanoiaError::InterfaceNotSupported)
+/// on the other backends.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Interface {
+    /// Let the library choose.
+    #[default]
+    Auto,
+    /// Use the cooked ioctl interface of the kernel, like `cdparanoia -k`.
+    Cooked,
+    /// Use the generic SCSI interface through this device, e.g. `/dev/sg0`,
+    /// like `cdparanoia -g`.
+    GenericScsi(PathBuf),
+}
+
+/// Options for opening a [`Drive`].
+///
+/// [`Drive::find()`] and [`Drive::open()`] use the default options.
+/// Everything except the speed and the TOC bias is applied before the
+/// table of contents is read.
+///
+/// ```no_run
+/// use cdparanoia::{message::Discard, options::Interface, DriveOptions};
+///
+/// let drive = DriveOptions::new()
+///     .message_sink(Discard)
+///     .interface(Interface::GenericScsi("/dev/sg1".into()))
+///     .sectors_per_read(8)
+///     .open("/dev/sr0")?;
+/// # Ok::<(), cdparanoia::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct DriveOptions {
+    pub(crate) message_sink: Arc<dyn MessageSink>,
+    pub(crate) verbosity: Verbosity,
+    pub(crate) speed: Option<i32>,
+    pub(crate) sectors_per_read: Option<u32>,
+    pub(crate) interface: Interface,
+    pub(crate) endianness: Option<Endianness>,
+    pub(crate) toc_bias: bool,
+    pub(crate) quirks: Arc<QuirkDatabase>,
+}
+
+impl DriveOptions {
+    pub fn new() -> Self {
+        Self {
+            message_sink: message::default_sink(),
+            verbosity: Verbosity::default(),
+            speed: None,
+            sectors_per_read: None,
+            interface: Interface::default(),
+            endianness: None,
+            toc_bias: false,
+            quirks: Arc::new(QuirkDatabase::builtin()),
+        }
+    }
+    /// Set where the messages of the library go, see [`message`].
+    ///
+    /// By default, they go to `tracing` or `log` if the respective feature is
+    /// enabled and are dropped otherwise.
+    pub fn message_sink(mut self, sink: impl MessageSink + 'static) -> Self {
+        self.message_sink = Arc::new(sink);
+        self
+    }
+    /// Set which messages of the library are passed to the message sink.
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+    /// Set the read speed after opening the drive,
+    /// see [`Drive::set_speed()`].
+    ///
+    /// Opening fails if the drive doesn't support setting the speed.
+    pub fn speed(mut self, speed: i32) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+    /// Force the number of sectors per read, like `cdparanoia -n`.
+    ///
+    /// Must be in the range `1..=MAX_SECTORS_PER_READ`.
+    pub fn sectors_per_read(mut self, sectors: u32) -> Self {
+        self.sectors_per_read = Some(sectors);
+        self
+    }
+    /// Force the interface that is used to talk to the drive.
+    pub fn interface(mut self, interface: Interface) -> Self {
+        self.interface = interface;
+        self
+    }
+    /// Force the byte order of the samples that the drive returns, like
+    /// `cdparanoia -c`/`-C`, instead of detecting it.
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = Some(endianness);
+        self
+    }
+    /// Treat the start of track 1 as sector 0, like `cdparanoia -T`.
+    ///
+    /// Some drives report the start of track 1 as the 150 sectors of
+    /// the first pregap but address it as sector 0.
+    pub fn toc_bias(mut self, toc_bias: bool) -> Self {
+        self.toc_bias = toc_bias;
+        self
+    }
+    /// Set the database that the quirks of the drive are looked up in,
+    /// default [`QuirkDatabase::builtin()`].
+    ///
+    /// Options that are set explicitly take precedence over the quirks.
+    /// Pass [`QuirkDatabase::new()`] to ignore all quirks.
+    pub fn quirks(mut self, quirks: QuirkDatabase) -> Self {
+        self.quirks = Arc::new(quirks);
+        self
+    }
+    /// Open a default CD-ROM drive with a CD-DA in it.
+    pub fn find(&self) -> Result<Drive> {
+        Drive::find_with(self)
+    }
+    /// Open a specific CD-ROM drive with a CD-DA in it.
+    pub fn open(&self, path: impl AsRef<Path>) -> Result<Drive> {
+        Drive::open_with(path.as_ref(), self)
+    }
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(sectors) = self.sectors_per_read {
+            if !(1..=MAX_SECTORS_PER_READ).contains(&sectors) {
+                return Err(Error::InvalidOption(format!(
+                    "the number of sectors per read must be between 1 and {MAX_SECTORS_PER_READ}, not {sectors}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for DriveOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for DriveOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DriveOptions")
+            .field("verbosity", &self.verbosity)
+            .field("speed", &self.speed)
+            .field("sectors_per_read", &self.sectors_per_read)
+            .field("interface", &self.interface)
+            .field("endianness", &self.endianness)
+            .field("toc_bias", &self.toc_bias)
+            .finish_non_exhaustive()
+    }
+}
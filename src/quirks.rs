@@ -0,0 +1,441 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Known properties of drive models, like their read offset.
+//!
+//! A [`QuirkDatabase`] maps drives, identified by vendor, model and
+//! optionally firmware revision, to their [`Quirks`]. When a
+//! [`Drive`](crate::Drive) is opened, the quirks of its model are looked up
+//! in the database of the [`DriveOptions`](crate::DriveOptions) and applied:
+//!
+//! - [`Quirks::sectors_per_read`] is used unless
+//!   [`DriveOptions::sectors_per_read()`](crate::DriveOptions::sectors_per_read)
+//!   is set.
+//! - [`Paranoia`](crate::Paranoia) corrects the [`Quirks::read_offset`].
+//! - Everything is available through
+//!   [`Drive::quirks()`](crate::Drive::quirks), e.g. to choose a ripping
+//!   strategy.
+//!
+//! By default, the database that ships with this crate
+//! ([`QuirkDatabase::builtin()`]) is used. It contains the read offsets of
+//! the [AccurateRip](http://www.accuraterip.com/driveoffsets.htm) drive
+//! database for common drives.
+//!
+//! # Format
+//!
+//! Databases are JSON documents with a list of drives. Only `vendor` and
+//! `model` are required, `firmware` restricts an entry to one firmware
+//! revision. Vendor, model and firmware are compared case-insensitively,
+//! with any whitespace treated as a single space.
+//!
+//! ```json
+//! {
+//!   "drives": [
+//!     {
+//!       "vendor": "PLEXTOR",
+//!       "model": "DVDR PX-716A",
+//!       "firmware": "1.11",
+//!       "read_offset": 30,
+//!       "overread": true,
+//!       "cache_defeat": true,
+//!       "interface": "generic-scsi",
+//!       "sectors_per_read": 26
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! If several entries match a drive, later entries override the fields of
+//! earlier ones. [`QuirkDatabase::merge()`] appends the entries of another
+//! database, so user overrides take precedence over the built-in entries.
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{quirks::QuirkDatabase, DriveOptions};
+//!
+//! let overrides: QuirkDatabase = r#"{
+//!     "drives": [
+//!         { "vendor": "PLEXTOR", "model": "DVDR PX-716A", "sectors_per_read": 8 },
+//!         { "vendor": "ACME", "model": "CD-ROM 1X", "read_offset": -12 }
+//!     ]
+//! }"#
+//! .parse()?;
+//! let database = QuirkDatabase::builtin().merge(overrides);
+//!
+//! let quirks = database.lookup("PLEXTOR", "DVDR   PX-716A", Some("1.11"));
+//! assert_eq!(quirks.read_offset, Some(30));
+//! assert_eq!(quirks.sectors_per_read, Some(8));
+//! assert_eq!(database.lookup("acme", "cd-rom 1x", None).read_offset, Some(-12));
+//!
+//! let options = DriveOptions::new().quirks(database);
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::{fs, path::Path, str::FromStr, sync::OnceLock};
+
+use serde_json::{Number, Value};
+
+use crate::{options::MAX_SECTORS_PER_READ, Result};
+
+/// The database that ships with this crate.
+const BUILTIN: &str = include_str!("../data/quirks.json");
+
+/// Properties of a drive model that affect ripping.
+///
+/// Properties that aren't known are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Quirks {
+    /// The number of samples (per channel) the drive reads too early, which
+    /// has to be added to the position of every sample.
+    pub read_offset: Option<i32>,
+    /// Whether the drive can read into the lead-in and lead-out, which is
+    /// required to correct the read offset at the edges of the disc without
+    /// losing samples.
+    pub overread: Option<bool>,
+    /// Whether the drive caches audio data, so that re-reads have to evict
+    /// the cache to actually read the disc again.
+    pub cache_defeat: Option<bool>,
+    /// The interface that works best with the drive.
+    ///
+    /// This is only informational, the interface has to be chosen before the
+    /// drive can be identified. See
+    /// [`DriveOptions::interface()`](crate::DriveOptions::interface).
+    pub interface: Option<PreferredInterface>,
+    /// The number of sectors per read that works best with the drive.
+    pub sectors_per_read: Option<u32>,
+}
+
+/// The interface that works best with a drive, see [`Quirks::interface`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PreferredInterface {
+    /// The cooked ioctl interface of the kernel (`"cooked"`).
+    Cooked,
+    /// The generic SCSI interface (`"generic-scsi"`).
+    GenericScsi,
+}
+
+/// An error in a [`QuirkDatabase`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QuirksError {
+    #[error("invalid quirk database at line {line}, column {column}: {message}")]
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("invalid drive {index} in the quirk database: {message}")]
+    InvalidDrive { index: usize, message: String },
+    #[error("the quirk database must be an object with a list of drives")]
+    MissingDrives,
+}
+
+/// A list of drive models and their [`Quirks`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuirkDatabase {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    vendor: String,
+    model: String,
+    firmware: Option<String>,
+    quirks: Quirks,
+}
+
+impl QuirkDatabase {
+    /// Create an empty database.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Get the database that ships with this crate.
+    pub fn builtin() -> Self {
+        static DATABASE: OnceLock<QuirkDatabase> = OnceLock::new();
+        DATABASE
+            .get_or_init(|| {
+                BUILTIN
+                    .parse()
+                    .expect("the built-in database should be valid")
+            })
+            .clone()
+    }
+    /// Load a database from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(fs::read_to_string(path)?.parse()?)
+    }
+    /// Append the entries of another database, which override the entries
+    /// of this one.
+    pub fn merge(mut self, overrides: QuirkDatabase) -> Self {
+        self.entries.extend(overrides.entries);
+        self
+    }
+    /// Get the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// Check if the database has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Get the quirks of a drive.
+    ///
+    /// Returns [`Quirks::default()`] if the drive isn't in the database.
+    pub fn lookup(&self, vendor: &str, model: &str, firmware: Option<&str>) -> Quirks {
+        let mut quirks = Quirks::default();
+        for entry in &self.entries {
+            let firmware_matches = match (&entry.firmware, firmware) {
+                (None, _) => true,
+                (Some(expected), Some(firmware)) => same_name(expected, firmware),
+                (Some(_), None) => false,
+            };
+            if same_name(&entry.vendor, vendor)
+                && same_name(&entry.model, model)
+                && firmware_matches
+            {
+                quirks.apply(&entry.quirks);
+            }
+        }
+        quirks
+    }
+    /// Get the quirks of a drive from its vendor, model and revision, if
+    /// they are known.
+    pub(crate) fn lookup_hardware(
+        &self,
+        (vendor, model, revision): (Option<String>, Option<String>, Option<String>),
+    ) -> Quirks {
+        match (vendor, model) {
+            (Some(vendor), Some(model)) => self.lookup(&vendor, &model, revision.as_deref()),
+            _ => Quirks::default(),
+        }
+    }
+}
+
+impl FromStr for QuirkDatabase {
+    type Err = QuirksError;
+
+    fn from_str(text: &str) -> std::result::Result<Self, QuirksError> {
+        let document: Value = serde_json::from_str(text).map_err(|err| {
+            let message = err.to_string();
+            let location = format!(" at line {} column {}", err.line(), err.column());
+            QuirksError::Syntax {
+                line: err.line(),
+                column: err.column(),
+                message: message
+                    .strip_suffix(&location)
+                    .unwrap_or(&message)
+                    .to_owned(),
+            }
+        })?;
+
+        let Value::Object(mut fields) = document else {
+            return Err(QuirksError::MissingDrives);
+        };
+        let Some(Value::Array(drives)) = fields.remove("drives") else {
+            return Err(QuirksError::MissingDrives);
+        };
+        let entries = drives
+            .into_iter()
+            .enumerate()
+            .map(|(index, drive)| {
+                Entry::parse(drive).map_err(|message| QuirksError::InvalidDrive { index, message })
+            })
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self { entries })
+    }
+}
+
+impl Entry {
+    fn parse(drive: Value) -> std::result::Result<Self, String> {
+        let Value::Object(fields) = drive else {
+            return Err(format!("expected an object, found {}", kind(&drive)));
+        };
+
+        let mut vendor = None;
+        let mut model = None;
+        let mut firmware = None;
+        let mut quirks = Quirks::default();
+        for (key, value) in fields {
+            let invalid =
+                |expected: &str| format!("expected {expected} for {key:?}, found {}", kind(&value));
+            match (key.as_str(), &value) {
+                ("vendor", Value::String(string)) => vendor = Some(string.clone()),
+                ("model", Value::String(string)) => model = Some(string.clone()),
+                ("firmware", Value::String(string)) => firmware = Some(string.clone()),
+                ("read_offset", Value::Number(number)) => {
+                    quirks.read_offset = Some(integer(number).ok_or_else(|| {
+                        format!("\"read_offset\" must be an integer, not {number}")
+                    })?)
+                }
+                ("overread", &Value::Bool(value)) => quirks.overread = Some(value),
+                ("cache_defeat", &Value::Bool(value)) => quirks.cache_defeat = Some(value),
+                ("interface", Value::String(string)) => {
+                    quirks.interface = Some(match string.as_str() {
+                        "cooked" => PreferredInterface::Cooked,
+                        "generic-scsi" => PreferredInterface::GenericScsi,
+                        _ => return Err(format!("unknown interface {string:?}")),
+                    })
+                }
+                ("sectors_per_read", Value::Number(number)) => {
+                    let range = 1..=MAX_SECTORS_PER_READ;
+                    match integer(number).and_then(|sectors| u32::try_from(sectors).ok()) {
+                        Some(sectors) if range.contains(&sectors) => {
+                            quirks.sectors_per_read = Some(sectors)
+                        }
+                        _ => {
+                            return Err(format!(
+                                "\"sectors_per_read\" must be between 1 and {MAX_SECTORS_PER_READ}, not {number}"
+                            ))
+                        }
+                    }
+                }
+                ("vendor" | "model" | "firmware" | "interface", _) => {
+                    return Err(invalid("a string"))
+                }
+                ("read_offset" | "sectors_per_read", _) => return Err(invalid("a number")),
+                ("overread" | "cache_defeat", _) => return Err(invalid("a boolean")),
+                _ => return Err(format!("unknown field {key:?}")),
+            }
+        }
+
+        Ok(Self {
+            vendor: vendor.ok_or("missing \"vendor\"")?,
+            model: model.ok_or("missing \"model\"")?,
+            firmware,
+            quirks,
+        })
+    }
+}
+
+impl Quirks {
+    /// Override the known properties with those of `other`.
+    fn apply(&mut self, other: &Quirks) {
+        self.read_offset = other.read_offset.or(self.read_offset);
+        self.overread = other.overread.or(self.overread);
+        self.cache_defeat = other.cache_defeat.or(self.cache_defeat);
+        self.interface = other.interface.or(self.interface);
+        self.sectors_per_read = other.sectors_per_read.or(self.sectors_per_read);
+    }
+}
+
+/// Compare names case-insensitively, treating any whitespace as a single
+/// space.
+fn same_name(a: &str, b: &str) -> bool {
+    a.split_whitespace()
+        .map(str::to_ascii_lowercase)
+        .eq(b.split_whitespace().map(str::to_ascii_lowercase))
+}
+
+fn integer(number: &Number) -> Option<i32> {
+    let number = number.as_f64()?;
+    (number.fract() == 0.0 && (f64::from(i32::MIN)..=f64::from(i32::MAX)).contains(&number))
+        .then_some(number as i32)
+}
+
+/// Get a short description of the type of a value for error messages.
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> QuirksError {
+        text.parse::<QuirkDatabase>().unwrap_err()
+    }
+
+    fn drive_error(drive: &str) -> String {
+        match error(&format!(r#"{{"drives": [{drive}]}}"#)) {
+            QuirksError::InvalidDrive { index: 0, message } => message,
+            err => panic!("unexpected error {err:?}"),
+        }
+    }
+
+    #[test]
+    fn builtin() {
+        let database = QuirkDatabase::builtin();
+        assert!(!database.is_empty());
+        assert_eq!(
+            database.lookup("PLEXTOR", "DVDR PX-716A", None).read_offset,
+            Some(30)
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(
+            error("{\n  \"drives\": [\n    {\"vendor\": \"A\",}\n  ]\n}"),
+            QuirksError::Syntax {
+                line: 3,
+                column: 20,
+                message: "trailing comma".to_owned(),
+            }
+        );
+        assert!(matches!(
+            error(r#"{"drives": ["\u00"]}"#),
+            QuirksError::Syntax { line: 1, .. }
+        ));
+        assert!(matches!(
+            error(r#"{"drives": []} []"#),
+            QuirksError::Syntax { line: 1, .. }
+        ));
+
+        // deeply nested documents are rejected instead of overflowing the stack
+        let nested = format!(
+            r#"{{"drives": {}{}}}"#,
+            "[".repeat(10_000),
+            "]".repeat(10_000)
+        );
+        assert!(matches!(error(&nested), QuirksError::Syntax { .. }));
+    }
+
+    #[test]
+    fn missing_drives() {
+        assert_eq!(error("[]"), QuirksError::MissingDrives);
+        assert_eq!(error("{}"), QuirksError::MissingDrives);
+        assert_eq!(error(r#"{"drives": {}}"#), QuirksError::MissingDrives);
+    }
+
+    #[test]
+    fn invalid_drives() {
+        assert_eq!(drive_error("1"), "expected an object, found a number");
+        assert_eq!(drive_error(r#"{"vendor": "A"}"#), "missing \"model\"");
+        assert_eq!(
+            drive_error(r#"{"vendor": "A", "model": "B", "overread": 1}"#),
+            "expected a boolean for \"overread\", found a number"
+        );
+        assert_eq!(
+            drive_error(r#"{"vendor": "A", "model": "B", "read_offset": 1.5}"#),
+            "\"read_offset\" must be an integer, not 1.5"
+        );
+        assert_eq!(
+            drive_error(r#"{"vendor": "A", "model": "B", "sectors_per_read": 0}"#),
+            format!("\"sectors_per_read\" must be between 1 and {MAX_SECTORS_PER_READ}, not 0")
+        );
+        assert_eq!(
+            drive_error(r#"{"vendor": "A", "model": "B", "interface": "atapi"}"#),
+            "unknown interface \"atapi\""
+        );
+        assert_eq!(
+            drive_error(r#"{"vendor": "A", "model": "B", "speed": 8}"#),
+            "unknown field \"speed\""
+        );
+    }
+
+    #[test]
+    fn integral_numbers() {
+        let database: QuirkDatabase =
+            r#"{"drives": [{"vendor": "A", "model": "B", "read_offset": -6.0}]}"#
+                .parse()
+                .unwrap();
+        assert_eq!(database.lookup("a", "b", None).read_offset, Some(-6));
+    }
+}
@@ -1,29 +1,150 @@
 // Copyright (c) 2023 d-k-bo
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use crate::{Drive, Error, Result};
+use std::{
+    collections::BTreeMap,
+    ops::{BitOr, BitXor, RangeInclusive},
+};
+
+#[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+use crate::Error;
+use crate::{
+    checksum::Crc32, damage::Event, engine::Engine, span::Span, CdBackend, Drive, Result,
+    SECTOR_WORDS,
+};
+
+#[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+thread_local! {
+    /// Events reported by the C library during the current read, with the
+    /// sector they refer to.
+    ///
+    /// The callback doesn't receive a user pointer, but it is only called
+    /// from within `paranoia_read_limited()` on the reading thread.
+    static CALLBACK_EVENTS: std::cell::RefCell<Vec<(std::ffi::c_long, Event)>> = const {
+        std::cell::RefCell::new(Vec::new())
+    };
+}
+
+#[cfg(feature = "libcdio-paranoia")]
+type CallbackFunction = crate::ffi::paranoia_cb_mode_t::Type;
+#[cfg(all(feature = "cdparanoia-3", not(feature = "libcdio-paranoia")))]
+type CallbackFunction = std::ffi::c_int;
+
+#[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+unsafe extern "C" fn callback(position: std::ffi::c_long, function: CallbackFunction) {
+    // the position is an index of the interleaved samples of the disc
+    let lsn = position.div_euclid(SECTOR_WORDS as std::ffi::c_long);
+    if let Some(event) = Event::from_code(function as i64) {
+        CALLBACK_EVENTS.with_borrow_mut(|events| events.push((lsn, event)));
+    }
+}
 
 /// Allows reading audio data from a CD.
+///
+/// If the backend is a [`Drive`] and the `libcdio-paranoia` or `cdparanoia-3`
+/// feature is enabled, the data is verified by the respective C library.
+/// Otherwise, a verification engine written in Rust is used that works on top
+/// of [`CdBackend::read_raw()`].
+///
+/// # Example
+///
+/// ```
+/// use cdparanoia::{
+///     fault::{Fault, FaultyDisc},
+///     Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+/// };
+///
+/// let samples: Vec<i16> = (0..20 * SECTOR_WORDS as i32)
+///     .map(|i| (i * 7919 % 65521) as i16)
+///     .collect();
+/// let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())])?;
+/// let disc = FaultyDisc::new(disc, 42).with_fault(Fault::Unstable {
+///     sectors: 5..=8,
+///     failures: 2,
+/// });
+///
+/// let mut paranoia = Paranoia::new(disc);
+/// let mut ripped = Vec::new();
+/// for sector in paranoia.read_track(1)? {
+///     ripped.extend(sector?);
+/// }
+/// assert_eq!(ripped, samples);
+/// # Ok::<(), cdparanoia::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Paranoia<B: CdBackend = Drive> {
+    verifier: Verifier,
+    mode: ParanoiaMode,
+    read_offset: i32,
+    backend: B,
+    /// The events the C library reported for the last sector it returned.
+    #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+    events: Vec<Event>,
+    /// Events the C library reported for sectors it hasn't returned yet.
+    #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+    pending_events: Vec<(std::ffi::c_long, Event)>,
+    /// The next sector the C library returns.
+    #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+    next_lsn: std::ffi::c_long,
+}
+
 #[derive(Debug)]
-pub struct Paranoia {
-    ptr: *mut crate::ffi::cdrom_paranoia,
-    drive: Drive,
+enum Verifier {
+    #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+    Cdda(*mut crate::ffi::cdrom_paranoia),
+    Engine(Engine),
 }
 
-impl Drop for Paranoia {
+impl<B: CdBackend> Drop for Paranoia<B> {
     fn drop(&mut self) {
-        unsafe { crate::ffi::paranoia_free(self.ptr) };
+        #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+        if let Verifier::Cdda(ptr) = self.verifier {
+            unsafe { crate::ffi::paranoia_free(ptr) };
+        }
     }
 }
 
-impl Paranoia {
-    pub(crate) fn new(drive: Drive) -> Self {
-        let ptr = unsafe { crate::ffi::paranoia_init(drive.as_ptr()) };
+impl<B: CdBackend> Paranoia<B> {
+    /// Create a [`Paranoia`] instance for reading audio data from a backend.
+    ///
+    /// The read offset of a [`Drive`] is corrected if it is known, see
+    /// [`Drive::quirks()`].
+    pub fn new(backend: B) -> Self {
+        let read_offset = backend
+            .as_drive()
+            .and_then(|drive| drive.quirks().read_offset)
+            .unwrap_or(0);
+
+        #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+        if let Some(drive) = backend.as_drive() {
+            let ptr = unsafe { crate::ffi::paranoia_init(drive.as_ptr()) };
 
-        drive.check_messages();
+            drive.check_messages();
+
+            assert!(!ptr.is_null(), "paranoia_init should be infallible");
+            return Self {
+                verifier: Verifier::Cdda(ptr),
+                mode: ParanoiaMode::FULL,
+                read_offset,
+                backend,
+                events: Vec::new(),
+                pending_events: Vec::new(),
+                next_lsn: 0,
+            };
+        }
 
-        assert!(!ptr.is_null(), "paranoia_init should be infallible");
-        Self { ptr, drive }
+        Self {
+            verifier: Verifier::Engine(Engine::new()),
+            mode: ParanoiaMode::FULL,
+            read_offset,
+            backend,
+            #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+            events: Vec::new(),
+            #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+            pending_events: Vec::new(),
+            #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+            next_lsn: 0,
+        }
     }
 }
 
@@ -33,102 +154,362 @@ impl From<Drive> for Paranoia {
     }
 }
 
-impl Paranoia {
-    /// Get a reference to the underlying [`Drive`].
-    pub fn drive(&self) -> &Drive {
-        &self.drive
+impl<B: CdBackend> Paranoia<B> {
+    /// Get a reference to the underlying [`Drive`] or other backend.
+    pub fn drive(&self) -> &B {
+        &self.backend
+    }
+    /// Get the current verification mode.
+    pub fn mode(&self) -> ParanoiaMode {
+        self.mode
+    }
+    /// Set the verification mode.
+    ///
+    /// The default is [`ParanoiaMode::FULL`].
+    pub fn set_mode(&mut self, mode: ParanoiaMode) {
+        self.mode = mode;
+        match &mut self.verifier {
+            #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+            Verifier::Cdda(ptr) => unsafe {
+                crate::ffi::paranoia_modeset(*ptr, mode.bits().into())
+            },
+            Verifier::Engine(engine) => engine.set_mode(mode),
+        }
+    }
+    /// Get the read offset that is corrected, in samples per channel.
+    pub fn read_offset(&self) -> i32 {
+        self.read_offset
+    }
+    /// Set the read offset of the drive in samples per channel, which is
+    /// added to the position of every sample that is read.
+    ///
+    /// Samples that would have to be read from before the first sector or
+    /// after the last audio sector of the disc are returned as silence.
+    /// The default is the read offset in the [`Drive::quirks()`] of a
+    /// [`Drive`] or 0.
+    pub fn set_read_offset(&mut self, read_offset: i32) {
+        self.read_offset = read_offset;
+    }
+    /// Read the sector at the position of the verifier.
+    fn read_next(&mut self, max_retries: i32) -> Result<&[i16]> {
+        match &mut self.verifier {
+            #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+            Verifier::Cdda(ptr) => unsafe {
+                let ptr = crate::ffi::paranoia_read_limited(*ptr, Some(callback), max_retries);
+                let lsn = self.next_lsn;
+                self.next_lsn += 1;
+                // events of sectors that have already been returned are dropped
+                self.pending_events.extend(CALLBACK_EVENTS.take());
+                self.events = (self.pending_events.iter())
+                    .filter(|&&(event_lsn, _)| event_lsn == lsn)
+                    .map(|&(_, event)| event)
+                    .collect();
+                self.pending_events
+                    .retain(|&(event_lsn, _)| event_lsn > lsn);
+
+                self.backend.as_drive().unwrap().check_messages();
+
+                if ptr.is_null() {
+                    return Err(Error::Read);
+                }
+
+                Ok(std::slice::from_raw_parts(ptr, SECTOR_WORDS))
+            },
+            Verifier::Engine(engine) => engine.read_sector(&mut self.backend, max_retries),
+        }
     }
 }
 
-impl Paranoia {
+impl<B: CdBackend> Paranoia<B> {
     /// Read audio data from a track.
-    pub fn read_track(&mut self, track: u8) -> Result<DiscReader<'_>> {
+    pub fn read_track(&mut self, track: u8) -> Result<DiscReader<'_, B>> {
         self.read_track_limited(track, 20)
     }
     /// Read audio data from a track with a custom retry count.
-    pub fn read_track_limited(&mut self, track: u8, max_retries: i32) -> Result<DiscReader<'_>> {
-        let first_lsn = self.drive.track_first_sector(track)?;
-        let last_lsn = self.drive.track_last_sector(track)?;
+    pub fn read_track_limited(&mut self, track: u8, max_retries: i32) -> Result<DiscReader<'_, B>> {
+        let first_lsn = self.backend.track_first_sector(track)?;
+        let last_lsn = self.backend.track_last_sector(track)?;
 
         Ok(self.read_sectors_limited(first_lsn, last_lsn, max_retries))
     }
+    /// Read audio data from all audio tracks of the disc.
+    pub fn read_disc(&mut self) -> Result<DiscReader<'_, B>> {
+        self.read_disc_limited(20)
+    }
+    /// Read audio data from all audio tracks of the disc with a custom retry count.
+    pub fn read_disc_limited(&mut self, max_retries: i32) -> Result<DiscReader<'_, B>> {
+        let first_lsn = self.backend.disc_first_sector()?;
+        let last_lsn = self.backend.disc_last_sector()?;
+
+        Ok(self.read_sectors_limited(first_lsn, last_lsn, max_retries))
+    }
+    /// Read the sectors selected by a cdparanoia span, e.g. `1[0:30.12]-3`.
+    ///
+    /// See the [`span`](crate::span) module for the syntax.
+    pub fn read_span(&mut self, span: &str) -> Result<DiscReader<'_, B>> {
+        self.read_span_limited(span, 20)
+    }
+    /// Read the sectors selected by a cdparanoia span with a custom retry count.
+    pub fn read_span_limited(&mut self, span: &str, max_retries: i32) -> Result<DiscReader<'_, B>> {
+        let sectors = span.parse::<Span>()?.resolve(&self.backend)?;
+
+        Ok(self.read_sectors_limited(*sectors.start(), *sectors.end(), max_retries))
+    }
     /// Read a range of sectors.
-    pub fn read_sectors(&mut self, first_lsn: u32, last_lsn: u32) -> DiscReader<'_> {
+    ///
+    /// Both `first_lsn` and `last_lsn` are inclusive. Reading starts at
+    /// `first_lsn`, no matter where an earlier [`DiscReader`] stopped.
+    pub fn read_sectors(&mut self, first_lsn: u32, last_lsn: u32) -> DiscReader<'_, B> {
         self.read_sectors_limited(first_lsn, last_lsn, 20)
     }
     /// Read a range of sectors with a custom retry count.
+    ///
+    /// Both `first_lsn` and `last_lsn` are inclusive.
     pub fn read_sectors_limited(
         &mut self,
         first_lsn: u32,
         last_lsn: u32,
         max_retries: i32,
-    ) -> DiscReader<'_> {
+    ) -> DiscReader<'_, B> {
         DiscReader::new(self, first_lsn, last_lsn, max_retries)
     }
 }
 
+#[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
 impl Paranoia {
     pub fn as_ptr(&self) -> *mut crate::ffi::cdrom_paranoia {
-        self.ptr
+        match self.verifier {
+            Verifier::Cdda(ptr) => ptr,
+            Verifier::Engine(_) => unreachable!("drives are always verified by the C library"),
+        }
+    }
+}
+
+/// Selects which verification and correction steps are performed.
+///
+/// The flags can be combined using `|` and removed using `^`, e.g.
+/// `ParanoiaMode::FULL ^ ParanoiaMode::NEVERSKIP`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParanoiaMode(u8);
+
+impl ParanoiaMode {
+    /// Return the data as it is read from the drive.
+    pub const DISABLE: Self = Self(0);
+    /// Only accept data that has been read identically at least twice.
+    pub const VERIFY: Self = Self(1);
+    /// Match verified fragments that have been read at different offsets.
+    pub const FRAGMENT: Self = Self(2);
+    /// Use overlapping reads to correct jitter between consecutive reads.
+    pub const OVERLAP: Self = Self(4);
+    /// Detect regions that cannot be read consistently.
+    pub const SCRATCH: Self = Self(8);
+    /// Reconstruct scratched regions from all available reads.
+    pub const REPAIR: Self = Self(16);
+    /// Never skip unverifiable data.
+    ///
+    /// libcdio-paranoia/cdparanoia-3 keep retrying indefinitely in this mode,
+    /// the Rust engine fails with [`Error::Read`](crate::Error::Read)
+    /// once the retry count is exceeded.
+    pub const NEVERSKIP: Self = Self(32);
+    /// Enable all of the above.
+    pub const FULL: Self = Self(0xff);
+
+    /// Get the raw flags as used by libcdio-paranoia/cdparanoia-3.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+    /// Check if all flags of `other` are set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for ParanoiaMode {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+impl BitOr for ParanoiaMode {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitXor for ParanoiaMode {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self {
+        Self(self.0 ^ rhs.0)
     }
 }
 
 /// Performs the actual reading of audio data.
 ///
+/// A reader returns the sectors from its first to its last LSN, both
+/// inclusive, e.g. every sector of a track for
+/// [`Paranoia::read_track()`].
+///
 /// This type implements
-/// [`Iterator<Item = cdparanoia::Result<Vec<i16>>>`](#impl-Iterator-for-DiscReader<'drive,+'paranoia>)
+/// [`Iterator<Item = cdparanoia::Result<Vec<i16>>>`](#impl-Iterator-for-DiscReader<'paranoia,+B>)
 /// which will clone the audio buffers. If you prefer to read the data
 /// without cloning, you can use the [`next_sector()`](DiscReader::next_sector) method.
 #[derive(Debug)]
-pub struct DiscReader<'paranoia> {
-    paranoia: &'paranoia mut Paranoia,
+pub struct DiscReader<'paranoia, B: CdBackend = Drive> {
+    paranoia: &'paranoia mut Paranoia<B>,
     last_lsn: u32,
     current_lsn: u32,
+    /// Whether the sector at `u32::MAX` has been read, so `current_lsn`
+    /// can't move past `last_lsn`.
+    finished: bool,
     max_retries: i32,
+    shift: Option<Shift>,
+}
+
+/// Corrects a read offset by shifting the verified samples.
+#[derive(Debug)]
+struct Shift {
+    /// The read offset in words.
+    words: i64,
+    /// The next sector to read from the verifier.
+    next_lsn: i64,
+    /// The last sector that can be read, later sectors are silence.
+    last_readable: i64,
+    /// Samples that haven't been returned, starting at the word `begin` of
+    /// the disc.
+    buffer: Vec<i16>,
+    begin: i64,
+    /// The sector that has been returned last.
+    sector: Vec<i16>,
 }
 
-impl<'paranoia> DiscReader<'paranoia> {
+impl<'paranoia, B: CdBackend> DiscReader<'paranoia, B> {
     pub(crate) fn new(
-        paranoia: &'paranoia mut Paranoia,
+        paranoia: &'paranoia mut Paranoia<B>,
         first_lsn: u32,
         last_lsn: u32,
         max_retries: i32,
     ) -> Self {
+        let shift = (paranoia.read_offset != 0).then(|| {
+            let words = 2 * i64::from(paranoia.read_offset);
+            let next_lsn = i64::from(first_lsn) + words.div_euclid(SECTOR_WORDS as i64);
+            Shift {
+                words,
+                next_lsn,
+                last_readable: paranoia
+                    .backend
+                    .disc_last_sector()
+                    .map_or(i64::MAX, i64::from),
+                buffer: Vec::new(),
+                begin: next_lsn * SECTOR_WORDS as i64,
+                sector: Vec::new(),
+            }
+        });
+        let seek_lsn = shift.as_ref().map_or(first_lsn, |shift| {
+            shift.next_lsn.clamp(0, u32::MAX.into()) as u32
+        });
+
+        match &mut paranoia.verifier {
+            #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+            Verifier::Cdda(ptr) => {
+                paranoia.next_lsn = seek_lsn as std::ffi::c_long;
+                #[cfg(feature = "libcdio-paranoia")]
+                let seek_lsn = seek_lsn.try_into().unwrap();
+                #[cfg(not(feature = "libcdio-paranoia"))]
+                let seek_lsn = seek_lsn.into();
+
+                // 0 = SEEK_SET
+                unsafe { crate::ffi::paranoia_seek(*ptr, seek_lsn, 0) };
+                paranoia.backend.as_drive().unwrap().check_messages();
+                paranoia.pending_events.clear();
+            }
+            Verifier::Engine(engine) => engine.seek(seek_lsn),
+        }
+
         Self {
             paranoia,
             last_lsn,
             current_lsn: first_lsn,
+            finished: false,
             max_retries,
+            shift,
         }
     }
 }
 
-impl<'paranoia> DiscReader<'paranoia> {
+impl<'paranoia, B: CdBackend> DiscReader<'paranoia, B> {
+    /// Get a reference to the underlying [`Drive`] or other backend.
+    pub fn drive(&self) -> &B {
+        self.paranoia.drive()
+    }
+    /// Get the logical sector number of the next sector that will be read.
+    pub fn current_lsn(&self) -> u32 {
+        self.current_lsn
+    }
+    /// Get the logical sector number of the last sector that will be read.
+    pub fn last_lsn(&self) -> u32 {
+        self.last_lsn
+    }
+    /// Get what happened while reading the last sector, e.g. jitter that has
+    /// been corrected or data that has been skipped.
+    ///
+    /// This is the input of a [`DamageMap`](crate::damage::DamageMap).
+    /// If a read offset is corrected, these are the events of the last
+    /// sector that has been read from the disc.
+    pub fn events(&self) -> &[Event] {
+        match &self.paranoia.verifier {
+            #[cfg(any(feature = "libcdio-paranoia", feature = "cdparanoia-3"))]
+            Verifier::Cdda(_) => &self.paranoia.events,
+            Verifier::Engine(engine) => engine.events(),
+        }
+    }
     /// Read the next sector of audio data without cloning.
     pub fn next_sector(&mut self) -> Option<Result<&[i16]>> {
-        if self.current_lsn == self.last_lsn {
+        if self.finished || self.current_lsn > self.last_lsn {
             return None;
         }
 
-        let data = unsafe {
-            let ptr =
-                crate::ffi::paranoia_read_limited(self.paranoia.as_ptr(), None, self.max_retries);
-
-            self.paranoia.drive.check_messages();
+        let data = match &mut self.shift {
+            None => match self.paranoia.read_next(self.max_retries) {
+                Ok(data) => data,
+                Err(err) => return Some(Err(err)),
+            },
+            Some(shift) => {
+                let begin = i64::from(self.current_lsn) * SECTOR_WORDS as i64 + shift.words;
+                let end = begin + SECTOR_WORDS as i64;
+                while shift.begin + (shift.buffer.len() as i64) < end {
+                    if (0..=shift.last_readable).contains(&shift.next_lsn) {
+                        match self.paranoia.read_next(self.max_retries) {
+                            Ok(data) => shift.buffer.extend_from_slice(data),
+                            Err(err) => return Some(Err(err)),
+                        }
+                    } else {
+                        shift.buffer.extend([0; SECTOR_WORDS]);
+                    }
+                    shift.next_lsn += 1;
+                }
 
-            if ptr.is_null() {
-                return Some(Err(Error::Read));
+                let start = (begin - shift.begin) as usize;
+                shift.sector.clear();
+                shift
+                    .sector
+                    .extend(shift.buffer.drain(..start + SECTOR_WORDS).skip(start));
+                shift.begin = end;
+                &shift.sector
             }
-
-            std::slice::from_raw_parts(ptr, crate::ffi::CD_FRAMEWORDS as usize)
         };
-        self.current_lsn += 1;
+        match self.current_lsn.checked_add(1) {
+            Some(lsn) => self.current_lsn = lsn,
+            None => self.finished = true,
+        }
 
         Some(Ok(data))
     }
 }
 
-impl<'paranoia> Iterator for DiscReader<'paranoia> {
+impl<'paranoia, B: CdBackend> Iterator for DiscReader<'paranoia, B> {
     type Item = Result<Vec<i16>>;
 
     /// Read the next sector of audio data.
@@ -138,3 +519,427 @@ impl<'paranoia> Iterator for DiscReader<'paranoia> {
         self.next_sector().map(|res| res.map(<[i16]>::to_vec))
     }
 }
+
+/// Reads sectors twice and re-reads the sectors that differ, like the secure
+/// mode of EAC.
+///
+/// The test pass and the copy pass read all sectors through [`Paranoia`] and
+/// compare the CRC-32 of every sector. Sectors whose test and copy differ (or
+/// couldn't be read) are read again until the same data has been read
+/// [`consistent_reads`](TestAndCopy::with_consistent_reads) times, counting
+/// the test and copy pass. Sectors that don't become consistent within
+/// [`max_rereads`](TestAndCopy::with_max_rereads) re-reads are reported in
+/// [`SecureRip::inconsistent`].
+///
+/// # Example
+///
+/// ```
+/// use cdparanoia::{
+///     fault::{Fault, FaultyDisc},
+///     Paranoia, ParanoiaMode, TestAndCopy, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+/// };
+///
+/// let samples: Vec<i16> = (0..20 * SECTOR_WORDS as i32)
+///     .map(|i| (i * 7919 % 65521) as i16)
+///     .collect();
+/// let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())])?;
+/// let disc = FaultyDisc::new(disc, 42)
+///     .with_fault(Fault::Unstable {
+///         sectors: 5..=8,
+///         failures: 1,
+///     })
+///     .with_fault(Fault::Unstable {
+///         sectors: 12..=12,
+///         failures: 100,
+///     });
+///
+/// let mut paranoia = Paranoia::new(disc);
+/// paranoia.set_mode(ParanoiaMode::DISABLE);
+/// let rip = TestAndCopy::new().read_track(&mut paranoia, 1)?;
+///
+/// assert_ne!(rip.test_crc, rip.copy_crc);
+/// assert_eq!(rip.inconsistent, [12..=12]);
+/// let sector_12 = 12 * SECTOR_WORDS..13 * SECTOR_WORDS;
+/// assert_eq!(rip.samples[..sector_12.start], samples[..sector_12.start]);
+/// assert_eq!(rip.samples[sector_12.end..], samples[sector_12.end..]);
+/// # Ok::<(), cdparanoia::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TestAndCopy {
+    consistent_reads: u32,
+    max_rereads: u32,
+    max_retries: i32,
+}
+
+/// The result of a [`TestAndCopy`] rip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecureRip {
+    /// The first sector that was read.
+    pub first_lsn: u32,
+    /// The audio data of all sectors, after replacing the sectors that
+    /// differed between the passes with consistent re-reads.
+    pub samples: Vec<i16>,
+    /// The CRC-32 of the test pass, see [`Crc32`].
+    pub test_crc: u32,
+    /// The CRC-32 of the copy pass.
+    pub copy_crc: u32,
+    /// The number of sectors that have been re-read.
+    pub rereads: u32,
+    /// Sectors that never became consistent.
+    ///
+    /// They contain the version that has been read most often,
+    /// or silence if they couldn't be read at all.
+    pub inconsistent: Vec<RangeInclusive<u32>>,
+}
+
+/// A version of a sector that has been read during a [`TestAndCopy`] rip.
+#[derive(Debug)]
+struct Candidate {
+    crc: u32,
+    reads: u32,
+    /// The data, unless it has only been read in the test pass.
+    data: Option<Vec<i16>>,
+}
+
+impl TestAndCopy {
+    /// Create a new rip that requires 2 consistent reads of every sector that
+    /// differs between the passes, re-reads it at most 16 times and lets
+    /// paranoia retry every read 20 times.
+    pub fn new() -> Self {
+        Self {
+            consistent_reads: 2,
+            max_rereads: 16,
+            max_retries: 20,
+        }
+    }
+    /// Set how often a sector whose test and copy differ must be read
+    /// identically. Values below 2 are raised to 2.
+    pub fn with_consistent_reads(mut self, reads: u32) -> Self {
+        self.consistent_reads = reads.max(2);
+        self
+    }
+    /// Set how often each sector may be re-read after the copy pass.
+    pub fn with_max_rereads(mut self, rereads: u32) -> Self {
+        self.max_rereads = rereads;
+        self
+    }
+    /// Set the retry count of paranoia for every read,
+    /// see [`Paranoia::read_sectors_limited()`].
+    pub fn with_max_retries(mut self, max_retries: i32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+    /// Rip a track.
+    pub fn read_track<B: CdBackend>(
+        &self,
+        paranoia: &mut Paranoia<B>,
+        track: u8,
+    ) -> Result<SecureRip> {
+        let first_lsn = paranoia.drive().track_first_sector(track)?;
+        let last_lsn = paranoia.drive().track_last_sector(track)?;
+
+        Ok(self.read_sectors(paranoia, first_lsn, last_lsn))
+    }
+    /// Rip a range of sectors.
+    ///
+    /// Both `first_lsn` and `last_lsn` are inclusive.
+    pub fn read_sectors<B: CdBackend>(
+        &self,
+        paranoia: &mut Paranoia<B>,
+        first_lsn: u32,
+        last_lsn: u32,
+    ) -> SecureRip {
+        let sectors = (u64::from(last_lsn) + 1).saturating_sub(first_lsn.into()) as usize;
+        let index = |lsn: u32| (lsn - first_lsn) as usize;
+
+        let mut test_crc = Crc32::new();
+        let mut test = vec![None; sectors];
+        self.read_pass(paranoia, first_lsn, last_lsn, |lsn, sector| {
+            test_crc.update(sector.unwrap_or(&[0; SECTOR_WORDS]));
+            test[index(lsn)] = sector.map(sector_crc);
+        });
+
+        let mut copy_crc = Crc32::new();
+        let mut samples = vec![0; sectors * SECTOR_WORDS];
+        let mut pending = BTreeMap::new();
+        self.read_pass(paranoia, first_lsn, last_lsn, |lsn, sector| {
+            copy_crc.update(sector.unwrap_or(&[0; SECTOR_WORDS]));
+            let test = test[index(lsn)];
+            let copy = sector.map(sector_crc);
+            if let Some(sector) = sector {
+                samples[index(lsn) * SECTOR_WORDS..][..SECTOR_WORDS].copy_from_slice(sector);
+            }
+            if copy.is_none() || test != copy {
+                let mut candidates = Vec::new();
+                if let Some(crc) = test {
+                    candidates.push(Candidate {
+                        crc,
+                        reads: 1,
+                        data: None,
+                    });
+                }
+                if let (Some(crc), Some(sector)) = (copy, sector) {
+                    candidates.push(Candidate {
+                        crc,
+                        reads: 1,
+                        data: Some(sector.to_vec()),
+                    });
+                }
+                pending.insert(lsn, candidates);
+            }
+        });
+
+        let mut rereads = 0;
+        for _ in 0..self.max_rereads {
+            if pending.is_empty() {
+                break;
+            }
+            for range in ranges(pending.keys().copied()) {
+                self.read_pass(paranoia, *range.start(), *range.end(), |lsn, sector| {
+                    rereads += 1;
+                    let (Some(candidates), Some(sector)) = (pending.get_mut(&lsn), sector) else {
+                        return;
+                    };
+                    let crc = sector_crc(sector);
+                    match candidates.iter_mut().find(|candidate| candidate.crc == crc) {
+                        Some(candidate) => {
+                            candidate.reads += 1;
+                            candidate.data.get_or_insert_with(|| sector.to_vec());
+                        }
+                        None => candidates.push(Candidate {
+                            crc,
+                            reads: 1,
+                            data: Some(sector.to_vec()),
+                        }),
+                    }
+                });
+            }
+            pending.retain(|&lsn, candidates| {
+                let consistent = candidates
+                    .iter()
+                    .find(|candidate| candidate.reads >= self.consistent_reads);
+                match consistent.and_then(|candidate| candidate.data.as_ref()) {
+                    Some(data) => {
+                        samples[index(lsn) * SECTOR_WORDS..][..SECTOR_WORDS].copy_from_slice(data);
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+
+        for (&lsn, candidates) in &pending {
+            let best = candidates
+                .iter()
+                .filter_map(|candidate| Some((candidate.reads, candidate.data.as_ref()?)))
+                .max_by_key(|(reads, _)| *reads);
+            let sector = &mut samples[index(lsn) * SECTOR_WORDS..][..SECTOR_WORDS];
+            match best {
+                Some((_, data)) => sector.copy_from_slice(data),
+                None => sector.fill(0),
+            }
+        }
+
+        SecureRip {
+            first_lsn,
+            samples,
+            test_crc: test_crc.finish(),
+            copy_crc: copy_crc.finish(),
+            rereads,
+            inconsistent: ranges(pending.into_keys()),
+        }
+    }
+    /// Read `first_lsn..=last_lsn`, passing `None` for sectors that can't be
+    /// read.
+    fn read_pass<B: CdBackend>(
+        &self,
+        paranoia: &mut Paranoia<B>,
+        first_lsn: u32,
+        last_lsn: u32,
+        mut f: impl FnMut(u32, Option<&[i16]>),
+    ) {
+        // u64, so the loop ends behind a last sector of u32::MAX
+        let mut lsn = u64::from(first_lsn);
+        while lsn <= u64::from(last_lsn) {
+            let mut reader = paranoia.read_sectors_limited(lsn as u32, last_lsn, self.max_retries);
+            while let Some(sector) = reader.next_sector() {
+                let sector = sector.ok();
+                f(lsn as u32, sector);
+                lsn += 1;
+                if sector.is_none() {
+                    // start over behind the unreadable sector
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Default for TestAndCopy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecureRip {
+    /// Check if all sectors have been read consistently.
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistent.is_empty()
+    }
+}
+
+fn sector_crc(sector: &[i16]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(sector);
+    crc.finish()
+}
+
+/// Merge ascending sector numbers into ranges of consecutive sectors.
+pub(crate) fn ranges(lsns: impl IntoIterator<Item = u32>) -> Vec<RangeInclusive<u32>> {
+    let mut ranges: Vec<RangeInclusive<u32>> = Vec::new();
+    for lsn in lsns {
+        match ranges.last_mut() {
+            Some(range) if *range.end() + 1 == lsn => *range = *range.start()..=lsn,
+            _ => ranges.push(lsn..=lsn),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fault::{Fault, FaultyDisc},
+        testing::samples,
+        ParanoiaMode, VirtualDisc, VirtualTrack,
+    };
+
+    fn paranoia(samples: &[i16], fault: Fault) -> Paranoia<FaultyDisc<VirtualDisc>> {
+        let disc = VirtualDisc::new([VirtualTrack::new(samples.to_vec())]).unwrap();
+        let mut paranoia = Paranoia::new(FaultyDisc::new(disc, 7).with_fault(fault));
+        paranoia.set_mode(ParanoiaMode::DISABLE);
+        paranoia
+    }
+
+    #[test]
+    fn defaults() {
+        let rip = TestAndCopy::new();
+        assert_eq!(rip, TestAndCopy::default());
+        assert_eq!(rip.consistent_reads, 2);
+        assert_eq!(rip.max_rereads, 16);
+        assert_eq!(rip.max_retries, 20);
+        assert_eq!(rip.with_consistent_reads(0).consistent_reads, 2);
+    }
+
+    #[test]
+    fn unreadable_sectors_are_silent() {
+        let samples = samples(10);
+        let mut paranoia = paranoia(&samples, Fault::Unreadable { sectors: 4..=5 });
+        let rip = TestAndCopy::new()
+            .with_max_rereads(3)
+            .with_max_retries(1)
+            .read_track(&mut paranoia, 1)
+            .unwrap();
+
+        assert_eq!(rip.inconsistent, [4..=5]);
+        assert_eq!(rip.rereads, 6);
+        assert_eq!(rip.test_crc, rip.copy_crc);
+        assert!(rip.samples[4 * SECTOR_WORDS..6 * SECTOR_WORDS]
+            .iter()
+            .all(|&sample| sample == 0));
+        assert_eq!(rip.samples[..4 * SECTOR_WORDS], samples[..4 * SECTOR_WORDS]);
+        assert_eq!(rip.samples[6 * SECTOR_WORDS..], samples[6 * SECTOR_WORDS..]);
+    }
+
+    #[test]
+    fn more_consistent_reads_need_more_rereads() {
+        let samples = samples(10);
+        let fault = Fault::Unstable {
+            sectors: 3..=3,
+            failures: 1,
+        };
+
+        let mut disc = paranoia(&samples, fault.clone());
+        let rip = TestAndCopy::new().read_track(&mut disc, 1).unwrap();
+        assert!(rip.is_consistent());
+        assert_eq!(rip.rereads, 1);
+        assert_eq!(rip.samples, samples);
+
+        let mut disc = paranoia(&samples, fault);
+        let rip = TestAndCopy::new()
+            .with_consistent_reads(4)
+            .read_track(&mut disc, 1)
+            .unwrap();
+        assert!(rip.is_consistent());
+        assert_eq!(rip.rereads, 3);
+        assert_eq!(rip.samples, samples);
+    }
+
+    #[test]
+    fn readers_start_at_the_first_sector_and_include_the_last() {
+        let samples = samples(10);
+        let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())]).unwrap();
+        let mut paranoia = Paranoia::new(disc);
+
+        let sectors = |range: RangeInclusive<usize>| {
+            samples[range.start() * SECTOR_WORDS..(range.end() + 1) * SECTOR_WORDS].to_vec()
+        };
+        for (first_lsn, last_lsn) in [(6, 8), (2, 4), (5, 5)] {
+            let ripped: Vec<i16> = paranoia
+                .read_sectors(first_lsn, last_lsn)
+                .flat_map(Result::unwrap)
+                .collect();
+            assert_eq!(ripped, sectors(first_lsn as usize..=last_lsn as usize));
+        }
+        let track: Vec<i16> = paranoia
+            .read_track(1)
+            .unwrap()
+            .flat_map(Result::unwrap)
+            .collect();
+        assert_eq!(track, samples);
+    }
+
+    #[test]
+    fn read_the_last_addressable_sector() {
+        let samples = samples(40);
+        let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())])
+            .unwrap()
+            .with_first_sector(u32::MAX - 39);
+        assert_eq!(disc.track_last_sector(1).unwrap(), u32::MAX);
+
+        for mode in [ParanoiaMode::DISABLE, ParanoiaMode::FULL] {
+            let mut paranoia = Paranoia::new(disc.clone());
+            paranoia.set_mode(mode);
+            let mut reader = paranoia.read_track(1).unwrap();
+            let mut ripped = Vec::new();
+            while let Some(sector) = reader.next_sector() {
+                ripped.extend_from_slice(sector.unwrap());
+            }
+            assert_eq!(ripped, samples, "{mode:?}");
+            assert!(reader.next_sector().is_none());
+            assert_eq!(reader.current_lsn(), u32::MAX);
+        }
+    }
+
+    #[test]
+    fn end_of_address_space() {
+        let samples = samples(2);
+        let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())])
+            .unwrap()
+            .with_first_sector(u32::MAX - 2);
+        let mut paranoia = Paranoia::new(disc);
+        paranoia.set_mode(ParanoiaMode::DISABLE);
+
+        // the virtual disc ends before the last addressable sector
+        let rip = TestAndCopy::new()
+            .with_max_rereads(1)
+            .with_max_retries(1)
+            .read_sectors(&mut paranoia, u32::MAX - 2, u32::MAX);
+        assert_eq!(rip.inconsistent, [u32::MAX..=u32::MAX]);
+        assert_eq!(rip.samples[..2 * SECTOR_WORDS], samples);
+        assert!(rip.samples[2 * SECTOR_WORDS..]
+            .iter()
+            .all(|&sample| sample == 0));
+    }
+}
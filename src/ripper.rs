@@ -0,0 +1,348 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Fast ripping of discs that are known to AccurateRip or CTDB.
+//!
+//! Verifying every sector with paranoia is slow, but unnecessary for tracks
+//! whose checksums are known: if an unverified read produces a checksum that
+//! is in the database, the rip is correct. A [`Ripper`] first reads a track
+//! with [`ParanoiaMode::DISABLE`] and compares it with the
+//! [`KnownChecksum`]s that the caller has looked up. Only tracks that don't
+//! match (or aren't in the database) are read again with the mode of the
+//! [`Paranoia`] instance.
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{
+//!     checksum::TrackChecksum,
+//!     fault::{Fault, FaultyDisc},
+//!     ripper::{ChecksumKind, KnownChecksum, RipMethod, Ripper},
+//!     toc::Toc,
+//!     Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+//! };
+//!
+//! let samples: Vec<i16> = (0..20 * SECTOR_WORDS as i32)
+//!     .map(|i| (i * 7919 % 65521) as i16)
+//!     .collect();
+//! let disc = VirtualDisc::new([
+//!     VirtualTrack::new(samples.clone()),
+//!     VirtualTrack::new(samples.clone()),
+//! ])?;
+//!
+//! // the checksums a database lookup would return
+//! let toc = Toc::read(&disc)?;
+//! let mut ripper = Ripper::new();
+//! for track in 1..=2 {
+//!     let mut checksum = TrackChecksum::for_track(&toc, track).unwrap();
+//!     checksum.update(disc.track_samples(track).unwrap());
+//!     ripper = ripper.with_known_checksum(
+//!         track,
+//!         KnownChecksum {
+//!             kind: ChecksumKind::AccurateRipV2,
+//!             checksum: checksum.accuraterip().v2(),
+//!             confidence: 10,
+//!         },
+//!     );
+//! }
+//!
+//! // track 2 is damaged, so it can't be verified without paranoia
+//! let disc = FaultyDisc::new(disc, 42).with_fault(Fault::Unstable {
+//!     sectors: 25..=26,
+//!     failures: 1,
+//! });
+//! let mut paranoia = Paranoia::new(disc);
+//!
+//! let rip = ripper.rip_track(&mut paranoia, 1)?;
+//! assert_eq!(rip.method, RipMethod::Burst);
+//! assert_eq!(rip.samples, samples);
+//!
+//! let rip = ripper.rip_track(&mut paranoia, 2)?;
+//! assert_eq!(rip.method, RipMethod::Secure);
+//! assert!(rip.verification.is_accurate());
+//! assert_eq!(rip.samples, samples);
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::collections::HashMap;
+
+use crate::{
+    checksum::TrackChecksum, log::Verification, toc::Toc, CdBackend, Paranoia, ParanoiaError,
+    ParanoiaMode, Result,
+};
+
+/// Rips tracks with unverified reads first and only falls back to paranoia
+/// if the result doesn't match a known checksum.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ripper {
+    known: HashMap<u8, Vec<KnownChecksum>>,
+    max_retries: Option<i32>,
+    min_confidence: u32,
+}
+
+/// A checksum of a track from a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KnownChecksum {
+    pub kind: ChecksumKind,
+    pub checksum: u32,
+    /// How many rips with this checksum the database has seen.
+    pub confidence: u32,
+}
+
+/// Which checksum of a [`TrackChecksum`] a [`KnownChecksum`] is compared with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChecksumKind {
+    /// [`AccurateRip::v1()`](crate::checksum::AccurateRip::v1).
+    AccurateRipV1,
+    /// [`AccurateRip::v2()`](crate::checksum::AccurateRip::v2).
+    AccurateRipV2,
+    /// [`TrackChecksum::ctdb_crc()`].
+    Ctdb,
+}
+
+/// How a track has been read by a [`Ripper`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RipMethod {
+    /// A single pass of unverified reads.
+    Burst,
+    /// Paranoia, after the burst read didn't match a known checksum.
+    Secure,
+}
+
+/// The result of [`Ripper::rip_track()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackRip {
+    pub track: u8,
+    pub samples: Vec<i16>,
+    pub checksum: TrackChecksum,
+    pub method: RipMethod,
+    /// How the final result compares to the known checksums.
+    pub verification: Verification,
+    /// The known checksum that matched, if any.
+    pub matched: Option<KnownChecksum>,
+}
+
+impl Ripper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a checksum that a database knows for a track.
+    pub fn with_known_checksum(mut self, track: u8, checksum: KnownChecksum) -> Self {
+        self.known.entry(track).or_default().push(checksum);
+        self
+    }
+    /// Only accept matches with at least this confidence, default 0.
+    pub fn with_min_confidence(mut self, confidence: u32) -> Self {
+        self.min_confidence = confidence;
+        self
+    }
+    /// Set the retry count of paranoia for the secure read,
+    /// see [`Paranoia::read_track_limited()`].
+    pub fn with_max_retries(mut self, max_retries: i32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+    /// Rip a track, falling back to the mode of `paranoia` if the burst read
+    /// can't be verified.
+    ///
+    /// The mode of `paranoia` is the same afterwards.
+    pub fn rip_track<B: CdBackend>(
+        &self,
+        paranoia: &mut Paranoia<B>,
+        track: u8,
+    ) -> Result<TrackRip> {
+        let toc = Toc::read(paranoia.drive())?;
+        let Some(empty) = TrackChecksum::for_track(&toc, track) else {
+            return Err(ParanoiaError::TrackNotAudioData.into());
+        };
+
+        let mode = paranoia.mode();
+        if mode != ParanoiaMode::DISABLE && self.known(track).next().is_some() {
+            paranoia.set_mode(ParanoiaMode::DISABLE);
+            let burst = self.read(paranoia, track, empty);
+            paranoia.set_mode(mode);
+
+            if let Ok(rip) = burst {
+                if rip.verification.is_accurate() {
+                    return Ok(rip);
+                }
+            }
+        }
+
+        let mut rip = self.read(paranoia, track, empty)?;
+        rip.method = if mode == ParanoiaMode::DISABLE {
+            RipMethod::Burst
+        } else {
+            RipMethod::Secure
+        };
+        Ok(rip)
+    }
+    fn read<B: CdBackend>(
+        &self,
+        paranoia: &mut Paranoia<B>,
+        track: u8,
+        mut checksum: TrackChecksum,
+    ) -> Result<TrackRip> {
+        let reader = match self.max_retries {
+            Some(max_retries) => paranoia.read_track_limited(track, max_retries)?,
+            None => paranoia.read_track(track)?,
+        };
+        let mut samples = Vec::new();
+        for sector in reader {
+            let sector = sector?;
+            checksum.update(&sector);
+            samples.extend(sector);
+        }
+
+        let (verification, matched) = self.verify(track, &checksum);
+        Ok(TrackRip {
+            track,
+            samples,
+            checksum,
+            method: RipMethod::Burst,
+            verification,
+            matched,
+        })
+    }
+    /// Compare the checksums of a track with the known ones.
+    pub fn verify(
+        &self,
+        track: u8,
+        checksum: &TrackChecksum,
+    ) -> (Verification, Option<KnownChecksum>) {
+        let known: Vec<_> = self.known(track).collect();
+
+        let matched = known
+            .iter()
+            .filter(|known| {
+                known.checksum
+                    == match known.kind {
+                        ChecksumKind::AccurateRipV1 => checksum.accuraterip().v1(),
+                        ChecksumKind::AccurateRipV2 => checksum.accuraterip().v2(),
+                        ChecksumKind::Ctdb => checksum.ctdb_crc(),
+                    }
+            })
+            .max_by_key(|known| known.confidence);
+        match (matched, known.iter().map(|known| known.confidence).max()) {
+            (Some(matched), _) => (
+                Verification::Accurate {
+                    confidence: matched.confidence,
+                },
+                Some(**matched),
+            ),
+            (None, Some(confidence)) => (Verification::Mismatch { confidence }, None),
+            (None, None) => (Verification::NotPresent, None),
+        }
+    }
+    /// The known checksums of a track with at least the minimum confidence.
+    fn known(&self, track: u8) -> impl Iterator<Item = &KnownChecksum> {
+        self.known
+            .get(&track)
+            .into_iter()
+            .flatten()
+            .filter(|known| known.confidence >= self.min_confidence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checksum::Crc32,
+        testing::{samples, CountingDisc},
+        VirtualDisc, VirtualTrack, SECTOR_WORDS,
+    };
+
+    fn setup(confidence: u32) -> (Ripper, Paranoia<CountingDisc>) {
+        let disc = VirtualDisc::new([VirtualTrack::new(samples(10))]).unwrap();
+        let toc = Toc::read(&disc).unwrap();
+        let mut checksum = TrackChecksum::for_track(&toc, 1).unwrap();
+        checksum.update(disc.track_samples(1).unwrap());
+
+        let ripper = Ripper::new().with_known_checksum(
+            1,
+            KnownChecksum {
+                kind: ChecksumKind::AccurateRipV2,
+                checksum: checksum.accuraterip().v2(),
+                confidence,
+            },
+        );
+        (ripper, Paranoia::new(CountingDisc::new(disc)))
+    }
+
+    #[test]
+    fn burst_read_matches_confident_checksum() {
+        let (ripper, mut paranoia) = setup(5);
+        let rip = ripper
+            .with_min_confidence(5)
+            .rip_track(&mut paranoia, 1)
+            .unwrap();
+        assert_eq!(rip.method, RipMethod::Burst);
+        assert_eq!(rip.verification, Verification::Accurate { confidence: 5 });
+        assert_eq!(paranoia.drive().sectors, 10);
+    }
+
+    #[test]
+    fn no_burst_read_without_confident_checksum() {
+        let (ripper, mut paranoia) = setup(4);
+        let rip = ripper
+            .with_min_confidence(5)
+            .rip_track(&mut paranoia, 1)
+            .unwrap();
+        assert_eq!(rip.method, RipMethod::Secure);
+        assert_eq!(rip.verification, Verification::NotPresent);
+        assert!(rip.matched.is_none());
+
+        // the same sectors as a rip without any known checksums
+        let (_, mut secure) = setup(4);
+        Ripper::new().rip_track(&mut secure, 1).unwrap();
+        assert_eq!(paranoia.drive().sectors, secure.drive().sectors);
+    }
+
+    #[test]
+    fn ctdb_leaves_out_the_edges_of_the_disc() {
+        let samples = samples(12);
+        let disc = VirtualDisc::new([
+            VirtualTrack::new(samples.clone()),
+            VirtualTrack::new(samples.clone()),
+            VirtualTrack::new(samples.clone()),
+        ])
+        .unwrap();
+        let toc = Toc::read(&disc).unwrap();
+        let crc = |samples: &[i16]| {
+            let mut crc = Crc32::new();
+            crc.update(samples);
+            crc.finish()
+        };
+
+        let edge = 5 * SECTOR_WORDS;
+        let expected = [
+            crc(&samples[edge..]),
+            crc(&samples),
+            crc(&samples[..samples.len() - edge]),
+        ];
+        let mut ripper = Ripper::new();
+        for (track, expected) in (1..=3).zip(expected) {
+            let mut checksum = TrackChecksum::for_track(&toc, track).unwrap();
+            for sector in samples.chunks(SECTOR_WORDS) {
+                checksum.update(sector);
+            }
+            assert_eq!(checksum.ctdb_crc(), expected);
+            ripper = ripper.with_known_checksum(
+                track,
+                KnownChecksum {
+                    kind: ChecksumKind::Ctdb,
+                    checksum: expected,
+                    confidence: 1,
+                },
+            );
+        }
+
+        let mut paranoia = Paranoia::new(disc);
+        for track in 1..=3 {
+            let rip = ripper.rip_track(&mut paranoia, track).unwrap();
+            assert_eq!(rip.method, RipMethod::Burst);
+            assert_eq!(rip.checksum.crc(), crc(&samples));
+        }
+    }
+}
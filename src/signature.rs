@@ -0,0 +1,417 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Signatures that make rip logs tamper-evident.
+//!
+//! [`sign()`] appends a signature block to a log, using the key of the
+//! station that made the rip. [`verify()`] checks a log against the keys of
+//! all known stations and reports whether the log has been edited after it
+//! was signed. The block looks like this:
+//!
+//! ```text
+//! ==== Log signature ====
+//! Algorithm : HMAC-SHA256
+//! Station   : station-1
+//! Signature : 5f1c...
+//! ==== End of log signature ====
+//! ```
+//!
+//! Every byte before the block is signed, so even converting line endings
+//! invalidates the signature.
+//!
+//! Stations either share a secret key with whoever verifies the logs
+//! ([`StationKey::new()`], HMAC-SHA256) or sign with an Ed25519 key
+//! ([`StationKey::ed25519()`]), so only their public key has to be
+//! distributed ([`StationKey::ed25519_public()`]).
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::signature::{self, SignatureStatus, StationKey};
+//!
+//! let keys = [StationKey::new("station-1", b"secret key of station 1".to_vec())];
+//! let log = signature::sign("Copy CRC 7DC734FF\n", &keys[0])?;
+//!
+//! assert_eq!(
+//!     signature::verify(&log, &keys),
+//!     SignatureStatus::Valid { station: "station-1".to_owned() }
+//! );
+//!
+//! let edited = log.replace("7DC734FF", "59C18970");
+//! assert_eq!(
+//!     signature::verify(&edited, &keys),
+//!     SignatureStatus::Edited { station: "station-1".to_owned() }
+//! );
+//!
+//! // the archive only needs the public key of an Ed25519 station
+//! let station = StationKey::ed25519("station-2", [42; 32]);
+//! let public = StationKey::ed25519_public("station-2", station.public_key().unwrap())?;
+//! let log = signature::sign("Copy CRC 7DC734FF\n", &station)?;
+//! assert!(signature::verify(&log, &[public]).is_valid());
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::fmt::{self, Display, Write};
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{Error, Result};
+
+const BEGIN: &str = "==== Log signature ====\n";
+const END: &str = "==== End of log signature ====";
+const HMAC_SHA256: &str = "HMAC-SHA256";
+const ED25519: &str = "Ed25519";
+
+/// The key of a ripping station.
+#[derive(Clone, PartialEq, Eq)]
+pub struct StationKey {
+    station: String,
+    key: Key,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum Key {
+    Hmac(Vec<u8>),
+    Ed25519(SigningKey),
+    Ed25519Public(VerifyingKey),
+}
+
+/// The result of [`verify()`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SignatureStatus {
+    /// The log hasn't been changed since it was signed by this station.
+    Valid { station: String },
+    /// The log has been changed after it was signed by this station.
+    Edited { station: String },
+    /// The log has been signed by a station whose key isn't known.
+    UnknownStation { station: String },
+    /// The log has no signature block.
+    Unsigned,
+    /// The signature block can't be parsed or uses an unknown algorithm.
+    Malformed,
+}
+
+impl StationKey {
+    /// Create the secret key of a station for HMAC-SHA256 signatures.
+    ///
+    /// Line breaks in the name of the station are replaced with spaces,
+    /// because it is written on a single line.
+    pub fn new(station: impl Into<String>, key: impl Into<Vec<u8>>) -> Self {
+        Self::with_key(station, Key::Hmac(key.into()))
+    }
+    /// Create the secret key of a station for Ed25519 signatures.
+    pub fn ed25519(station: impl Into<String>, secret_key: [u8; 32]) -> Self {
+        Self::with_key(station, Key::Ed25519(SigningKey::from_bytes(&secret_key)))
+    }
+    /// Create the public key of a station that signs with Ed25519.
+    ///
+    /// It can only verify logs, not sign them. Fails if the bytes aren't a
+    /// valid public key.
+    pub fn ed25519_public(station: impl Into<String>, public_key: [u8; 32]) -> Result<Self> {
+        let station = station.into();
+        let key = VerifyingKey::from_bytes(&public_key).map_err(|_| Error::InvalidKey {
+            station: station.clone(),
+            reason: "not an Ed25519 public key",
+        })?;
+        Ok(Self::with_key(station, Key::Ed25519Public(key)))
+    }
+    fn with_key(station: impl Into<String>, key: Key) -> Self {
+        Self {
+            station: station.into().replace(['\r', '\n'], " "),
+            key,
+        }
+    }
+    /// Get the name of the station.
+    pub fn station(&self) -> &str {
+        &self.station
+    }
+    /// Get the public key of an Ed25519 key, which can be given to whoever
+    /// verifies the logs.
+    pub fn public_key(&self) -> Option<[u8; 32]> {
+        match &self.key {
+            Key::Hmac(_) => None,
+            Key::Ed25519(key) => Some(key.verifying_key().to_bytes()),
+            Key::Ed25519Public(key) => Some(key.to_bytes()),
+        }
+    }
+    fn algorithm(&self) -> &'static str {
+        match self.key {
+            Key::Hmac(_) => HMAC_SHA256,
+            Key::Ed25519(_) | Key::Ed25519Public(_) => ED25519,
+        }
+    }
+    fn sign(&self, log: &str) -> Result<Vec<u8>> {
+        match &self.key {
+            Key::Hmac(key) => Ok(hmac(key, log).finalize().into_bytes().to_vec()),
+            Key::Ed25519(key) => Ok(key.sign(log.as_bytes()).to_bytes().to_vec()),
+            Key::Ed25519Public(_) => Err(Error::InvalidKey {
+                station: self.station.clone(),
+                reason: "a public key can't sign logs",
+            }),
+        }
+    }
+    fn verify(&self, log: &str, signature: &[u8]) -> bool {
+        match &self.key {
+            // compares in constant time
+            Key::Hmac(key) => hmac(key, log).verify_slice(signature).is_ok(),
+            Key::Ed25519(key) => verify_ed25519(&key.verifying_key(), log, signature),
+            Key::Ed25519Public(key) => verify_ed25519(key, log, signature),
+        }
+    }
+}
+
+impl fmt::Debug for StationKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StationKey")
+            .field("station", &self.station)
+            .field("algorithm", &self.algorithm())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SignatureStatus {
+    /// Check if the log is authentic.
+    pub fn is_valid(&self) -> bool {
+        matches!(self, SignatureStatus::Valid { .. })
+    }
+}
+
+impl Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureStatus::Valid { station } => write!(f, "valid, signed by {station}"),
+            SignatureStatus::Edited { station } => {
+                write!(f, "edited after it was signed by {station}")
+            }
+            SignatureStatus::UnknownStation { station } => {
+                write!(f, "signed by unknown station {station}")
+            }
+            SignatureStatus::Unsigned => write!(f, "not signed"),
+            SignatureStatus::Malformed => write!(f, "malformed signature"),
+        }
+    }
+}
+
+/// Append a signature block to a log.
+///
+/// A line break is added to the log first if it doesn't end with one.
+/// Fails if the key is only a public key.
+pub fn sign(log: &str, key: &StationKey) -> Result<String> {
+    let mut signed = log.to_owned();
+    if !signed.is_empty() && !signed.ends_with('\n') {
+        signed.push('\n');
+    }
+    let signature = key.sign(&signed)?;
+
+    signed.push_str(BEGIN);
+    // writing to a String can't fail
+    let _ = writeln!(signed, "Algorithm : {}", key.algorithm());
+    let _ = writeln!(signed, "Station   : {}", key.station);
+    let _ = writeln!(signed, "Signature : {}", hex(&signature));
+    let _ = writeln!(signed, "{END}");
+    Ok(signed)
+}
+
+/// Check the signature block at the end of a log.
+///
+/// Anything but whitespace after the block counts as an edit, and so does a
+/// signature that was made with a different kind of key than the one that
+/// is known for the station.
+pub fn verify(log: &str, keys: &[StationKey]) -> SignatureStatus {
+    let start = match log.rfind(BEGIN) {
+        Some(0) => 0,
+        Some(start) if log[..start].ends_with('\n') => start,
+        _ => return SignatureStatus::Unsigned,
+    };
+    let (content, block) = log.split_at(start);
+
+    let mut lines = block[BEGIN.len()..].lines();
+    let mut field = |name: &str| {
+        lines
+            .next()
+            .and_then(|line| line.split_once(':'))
+            .filter(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().to_owned())
+    };
+    let (Some(algorithm), Some(station), Some(signature)) =
+        (field("Algorithm"), field("Station"), field("Signature"))
+    else {
+        return SignatureStatus::Malformed;
+    };
+    if ![HMAC_SHA256, ED25519].contains(&algorithm.as_str()) || lines.next() != Some(END) {
+        return SignatureStatus::Malformed;
+    }
+    let trailing = lines.any(|line| !line.trim().is_empty());
+
+    let Some(key) = keys.iter().find(|key| key.station == station) else {
+        return SignatureStatus::UnknownStation { station };
+    };
+    let authentic = !trailing
+        && key.algorithm() == algorithm
+        && unhex(&signature).is_some_and(|signature| key.verify(content, &signature));
+    if authentic {
+        SignatureStatus::Valid { station }
+    } else {
+        SignatureStatus::Edited { station }
+    }
+}
+
+fn hmac(key: &[u8], log: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key).expect("HMAC should accept keys of any length");
+    mac.update(log.as_bytes());
+    mac
+}
+
+fn verify_ed25519(key: &VerifyingKey, log: &str, signature: &[u8]) -> bool {
+    signature
+        .try_into()
+        .is_ok_and(|signature| key.verify_strict(log.as_bytes(), &signature).is_ok())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    let digits = hex
+        .chars()
+        .map(|digit| digit.to_digit(16).map(|digit| digit as u8))
+        .collect::<Option<Vec<_>>>()?;
+    (digits.len() % 2 == 0).then(|| {
+        digits
+            .chunks_exact(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "Copy CRC 7DC734FF\n";
+
+    fn status(station: &str) -> (SignatureStatus, SignatureStatus) {
+        let station = station.to_owned();
+        (
+            SignatureStatus::Valid {
+                station: station.clone(),
+            },
+            SignatureStatus::Edited { station },
+        )
+    }
+
+    #[test]
+    fn hmac_test_vectors() {
+        // RFC 4231, test cases 1, 2 and 6
+        for (key, data, expected) in [
+            (
+                vec![0x0b; 20],
+                "Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe".to_vec(),
+                "what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                vec![0xaa; 131],
+                "Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+        ] {
+            assert_eq!(hex(&hmac(&key, data).finalize().into_bytes()), expected);
+        }
+    }
+
+    #[test]
+    fn ed25519_test_vector() {
+        // RFC 8032, section 7.1, test 1
+        let secret = unhex("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let key = StationKey::ed25519("station", secret);
+        assert_eq!(
+            hex(&key.public_key().unwrap()),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+
+        let signed = sign("", &key).unwrap();
+        assert!(signed.contains(
+            "Signature : e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b\n"
+        ));
+    }
+
+    #[test]
+    fn edits_invalidate_the_signature() {
+        let hmac = StationKey::new("station-1", b"secret".to_vec());
+        let ed25519 = StationKey::ed25519("station-2", [7; 32]);
+        let public =
+            StationKey::ed25519_public("station-2", ed25519.public_key().unwrap()).unwrap();
+
+        for (key, verifier) in [(&hmac, &hmac), (&ed25519, &ed25519), (&ed25519, &public)] {
+            let (valid, edited) = status(key.station());
+            let signed = sign(LOG, key).unwrap();
+            let keys = [verifier.clone()];
+
+            assert_eq!(verify(&signed, &keys), valid);
+            assert_eq!(verify(&format!("{signed}\n  \n"), &keys), valid);
+            assert_eq!(verify(&signed.replace("7DC7", "7DC8"), &keys), edited);
+            assert_eq!(verify(&signed.replacen('\n', "\r\n", 1), &keys), edited);
+            assert_eq!(verify(&format!("{signed}Copy OK\n"), &keys), edited);
+            assert_eq!(verify(&format!("{LOG}{signed}"), &keys), edited);
+        }
+    }
+
+    #[test]
+    fn broken_signature_blocks() {
+        let keys = [StationKey::new("station-1", b"secret".to_vec())];
+        let signed = sign(LOG, &keys[0]).unwrap();
+        let (_, edited) = status("station-1");
+
+        assert_eq!(verify(LOG, &keys), SignatureStatus::Unsigned);
+        assert_eq!(
+            verify(&signed, &[]),
+            SignatureStatus::UnknownStation {
+                station: "station-1".to_owned()
+            }
+        );
+        assert_eq!(
+            verify(&signed.replace("HMAC-SHA256", "HMAC-MD5"), &keys),
+            SignatureStatus::Malformed
+        );
+        assert_eq!(
+            verify(&signed.replace("Station   :", "Station"), &keys),
+            SignatureStatus::Malformed
+        );
+        // a signature of the wrong kind or one that isn't hexadecimal
+        assert_eq!(
+            verify(&signed.replace("HMAC-SHA256", "Ed25519"), &keys),
+            edited
+        );
+        let signature = hex(&hmac(b"secret", LOG).finalize().into_bytes());
+        let plus = format!("+{}", &signature[1..]);
+        assert_eq!(verify(&signed.replace(&signature, &plus), &keys), edited);
+    }
+
+    #[test]
+    fn public_keys_cant_sign() {
+        // y = 2 isn't on the curve
+        let mut invalid = [0; 32];
+        invalid[0] = 2;
+        assert!(matches!(
+            StationKey::ed25519_public("station", invalid),
+            Err(Error::InvalidKey { .. })
+        ));
+        let key = StationKey::ed25519("station", [7; 32]);
+        let public = StationKey::ed25519_public("station", key.public_key().unwrap()).unwrap();
+        assert!(matches!(sign(LOG, &public), Err(Error::InvalidKey { .. })));
+        assert_eq!(StationKey::new("station", Vec::new()).public_key(), None);
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Writers that store the audio data of a [`DiscReader`] in a file.
+//!
+//! All of them implement [`Sink`], so a rip can target any format.
+//! [`Wav`], [`Aiff`], [`Aifc`] and [`Raw`] are always available,
+//! [`Flac`] requires the `flac` feature. [`Pipe`] feeds an external encoder.
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{
+//!     sink::{Sink, Wav},
+//!     Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+//! };
+//!
+//! let disc = VirtualDisc::new([VirtualTrack::new(vec![1; 10 * SECTOR_WORDS])])?;
+//! let mut paranoia = Paranoia::new(disc);
+//!
+//! let wav = Wav::new(std::io::Cursor::new(Vec::new()))
+//!     .with_info(*b"INAM", "Track 1")
+//!     .write_all(paranoia.read_track(1)?)?
+//!     .into_inner();
+//!
+//! assert_eq!(&wav[..4], b"RIFF");
+//! assert_eq!(&wav[8..12], b"WAVE");
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::{CdBackend, DiscReader, Result};
+
+#[cfg(feature = "flac")]
+pub use self::flac::Flac;
+pub use self::{
+    aiff::{Aifc, Aiff},
+    pipe::{EncoderOutput, Pipe},
+    raw::Raw,
+    wav::Wav,
+};
+
+mod aiff;
+#[cfg(feature = "flac")]
+mod flac;
+mod pipe;
+mod raw;
+mod wav;
+
+/// Sample rate of CD-DA.
+const SAMPLE_RATE: u32 = 44100;
+/// Chunk size used in place of the real size if the length is not known.
+const UNKNOWN_LENGTH: u32 = u32::MAX;
+
+/// A destination for audio data.
+pub trait Sink: Sized {
+    /// The value returned by [`finish()`](Sink::finish), usually the writer.
+    type Output;
+
+    /// Prepare for writing the sectors `first_lsn..=last_lsn` of a disc.
+    ///
+    /// This is called by [`write_all()`](Sink::write_all) before the first
+    /// sector, so sinks can take the channel count, pre-emphasis or the
+    /// length of the data from the table of contents.
+    fn begin<B: CdBackend + ?Sized>(
+        &mut self,
+        _backend: &B,
+        _first_lsn: u32,
+        _last_lsn: u32,
+    ) -> Result<()> {
+        Ok(())
+    }
+    /// Write a sector of audio data.
+    fn write_sector(&mut self, sector: &[i16]) -> Result<()>;
+    /// Complete the file and return the output.
+    fn finish(self) -> Result<Self::Output>;
+    /// Write all remaining sectors of a [`DiscReader`] and finish the file.
+    fn write_all<B: CdBackend>(mut self, mut reader: DiscReader<'_, B>) -> Result<Self::Output> {
+        self.begin(reader.drive(), reader.current_lsn(), reader.last_lsn())?;
+        while let Some(sector) = reader.next_sector() {
+            self.write_sector(sector?)?;
+        }
+        self.finish()
+    }
+}
+
+/// Byte order of 16-bit samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endianness {
+    fn encode(self, samples: &[i16]) -> Vec<u8> {
+        match self {
+            Endianness::Little => samples.iter().flat_map(|s| s.to_le_bytes()).collect(),
+            Endianness::Big => samples.iter().flat_map(|s| s.to_be_bytes()).collect(),
+        }
+    }
+}
+
+/// Overwrites already written bytes at the given position.
+type Patch<W> = fn(&mut W, u64, u64, &[u8]) -> io::Result<()>;
+
+/// A writer that keeps track of its length and can overwrite parts of the
+/// header later if the underlying writer is seekable.
+#[derive(Debug)]
+struct Output<W> {
+    writer: W,
+    patch: Option<Patch<W>>,
+    written: u64,
+}
+
+impl<W: Write + Seek> Output<W> {
+    fn seekable(writer: W) -> Self {
+        Self {
+            patch: Some(patch_at::<W>),
+            ..Self::streaming(writer)
+        }
+    }
+}
+
+impl<W: Write> Output<W> {
+    fn streaming(writer: W) -> Self {
+        Self {
+            writer,
+            patch: None,
+            written: 0,
+        }
+    }
+    fn is_seekable(&self) -> bool {
+        self.patch.is_some()
+    }
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+    /// Overwrite bytes at `position`, if the writer is seekable.
+    fn patch(&mut self, position: u64, bytes: &[u8]) -> io::Result<()> {
+        match self.patch {
+            Some(patch) => patch(&mut self.writer, self.written, position, bytes),
+            None => Ok(()),
+        }
+    }
+    fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn patch_at<W: Write + Seek>(
+    writer: &mut W,
+    written: u64,
+    position: u64,
+    bytes: &[u8],
+) -> io::Result<()> {
+    let end = writer.stream_position()?;
+    let start = end - written;
+    writer.seek(SeekFrom::Start(start + position))?;
+    writer.write_all(bytes)?;
+    writer.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Get the channel count of the track containing `lsn`.
+fn track_channels<B: CdBackend + ?Sized>(backend: &B, lsn: u32) -> Option<u8> {
+    backend
+        .sector_track(lsn)
+        .ok()
+        .and_then(|track| backend.track_channels(track))
+}
@@ -0,0 +1,464 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::Path,
+};
+
+use super::{track_channels, Endianness, Output, Sink, UNKNOWN_LENGTH};
+use crate::{CdBackend, Error, Result, SECTOR_BYTES, SECTOR_WORDS};
+
+/// 44100 as an 80-bit IEEE 754 extended precision number.
+const SAMPLE_RATE_EXTENDED: [u8; 10] = [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0];
+/// Version of the AIFC specification.
+const AIFC_VERSION_1: u32 = 0xa2805140;
+/// First byte of the AES3 channel status: professional use,
+/// 50/15 µs emphasis, 44.1 kHz.
+const AES_CHANNEL_STATUS_PREEMPHASIS: u8 = 0x4d;
+
+/// Writes audio data as a big-endian AIFF file.
+///
+/// If the length of the data is known (see [`Sink::begin()`]), the header is
+/// complete even for writers that aren't seekable. Otherwise, the sizes are
+/// set to `0xffffffff` unless they can be filled in by
+/// [`finish()`](Sink::finish).
+///
+/// Tracks with pre-emphasis get an `AESD` chunk whose channel status
+/// indicates 50/15 µs emphasis.
+#[derive(Debug)]
+pub struct Aiff<W: Write>(Writer<W>);
+
+impl Aiff<BufWriter<File>> {
+    /// Create an AIFF file at the given path.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write + Seek> Aiff<W> {
+    /// Write to a seekable writer, e.g. a [`File`].
+    pub fn new(writer: W) -> Self {
+        Self(Writer::new(Output::seekable(writer), None))
+    }
+}
+
+impl<W: Write> Aiff<W> {
+    /// Write to a writer that isn't seekable, e.g. [`std::io::Stdout`].
+    pub fn streaming(writer: W) -> Self {
+        Self(Writer::new(Output::streaming(writer), None))
+    }
+    /// Set the number of channels.
+    ///
+    /// By default, [`write_all()`](Sink::write_all) uses the channel count of
+    /// the track that is being read and [`write_sector()`](Sink::write_sector)
+    /// assumes two channels. Counts that don't fill a sector with whole
+    /// samples are rejected when writing begins.
+    pub fn with_channels(mut self, channels: u8) -> Self {
+        self.0.channels = Some(channels.into());
+        self
+    }
+    /// Set whether the audio data has pre-emphasis.
+    ///
+    /// By default, [`write_all()`](Sink::write_all) uses the flag of the
+    /// track that is being read.
+    pub fn with_preemphasis(mut self, preemphasis: bool) -> Self {
+        self.0.preemphasis = Some(preemphasis);
+        self
+    }
+}
+
+impl<W: Write> Sink for Aiff<W> {
+    type Output = W;
+
+    fn begin<B: CdBackend + ?Sized>(
+        &mut self,
+        backend: &B,
+        first_lsn: u32,
+        last_lsn: u32,
+    ) -> Result<()> {
+        self.0.begin(backend, first_lsn, last_lsn)
+    }
+    fn write_sector(&mut self, sector: &[i16]) -> Result<()> {
+        self.0.write_sector(sector)
+    }
+    fn finish(self) -> Result<W> {
+        self.0.finish()
+    }
+}
+
+/// Writes audio data as an AIFF-C file.
+///
+/// The samples are uncompressed (`NONE`) and big-endian by default,
+/// little-endian samples are marked as `sowt`. See [`Aiff`] for details on
+/// the header.
+#[derive(Debug)]
+pub struct Aifc<W: Write>(Writer<W>);
+
+impl Aifc<BufWriter<File>> {
+    /// Create an AIFF-C file at the given path.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write + Seek> Aifc<W> {
+    /// Write to a seekable writer, e.g. a [`File`].
+    pub fn new(writer: W) -> Self {
+        Self(Writer::new(Output::seekable(writer), Some(Endianness::Big)))
+    }
+}
+
+impl<W: Write> Aifc<W> {
+    /// Write to a writer that isn't seekable, e.g. [`std::io::Stdout`].
+    pub fn streaming(writer: W) -> Self {
+        Self(Writer::new(
+            Output::streaming(writer),
+            Some(Endianness::Big),
+        ))
+    }
+    /// Set the number of channels.
+    ///
+    /// By default, [`write_all()`](Sink::write_all) uses the channel count of
+    /// the track that is being read and [`write_sector()`](Sink::write_sector)
+    /// assumes two channels. Counts that don't fill a sector with whole
+    /// samples are rejected when writing begins.
+    pub fn with_channels(mut self, channels: u8) -> Self {
+        self.0.channels = Some(channels.into());
+        self
+    }
+    /// Set whether the audio data has pre-emphasis.
+    ///
+    /// By default, [`write_all()`](Sink::write_all) uses the flag of the
+    /// track that is being read.
+    pub fn with_preemphasis(mut self, preemphasis: bool) -> Self {
+        self.0.preemphasis = Some(preemphasis);
+        self
+    }
+    /// Set the byte order of the samples.
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.0.aifc = Some(endianness);
+        self
+    }
+}
+
+impl<W: Write> Sink for Aifc<W> {
+    type Output = W;
+
+    fn begin<B: CdBackend + ?Sized>(
+        &mut self,
+        backend: &B,
+        first_lsn: u32,
+        last_lsn: u32,
+    ) -> Result<()> {
+        self.0.begin(backend, first_lsn, last_lsn)
+    }
+    fn write_sector(&mut self, sector: &[i16]) -> Result<()> {
+        self.0.write_sector(sector)
+    }
+    fn finish(self) -> Result<W> {
+        self.0.finish()
+    }
+}
+
+/// Positions of the header fields that depend on the length of the data.
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    len: u64,
+    frames: u64,
+}
+
+#[derive(Debug)]
+struct Writer<W: Write> {
+    output: Output<W>,
+    /// The byte order for AIFF-C, `None` for AIFF.
+    aifc: Option<Endianness>,
+    channels: Option<u16>,
+    preemphasis: Option<bool>,
+    expected_sectors: Option<u64>,
+    header: Option<Header>,
+}
+
+impl<W: Write> Writer<W> {
+    fn new(output: Output<W>, aifc: Option<Endianness>) -> Self {
+        Self {
+            output,
+            aifc,
+            channels: None,
+            preemphasis: None,
+            expected_sectors: None,
+            header: None,
+        }
+    }
+    fn begin<B: CdBackend + ?Sized>(
+        &mut self,
+        backend: &B,
+        first_lsn: u32,
+        last_lsn: u32,
+    ) -> Result<()> {
+        if self.channels.is_none() {
+            self.channels = track_channels(backend, first_lsn).map(Into::into);
+        }
+        if self.preemphasis.is_none() {
+            self.preemphasis = backend
+                .sector_track(first_lsn)
+                .ok()
+                .map(|track| backend.track_linear_preemphasis(track));
+        }
+        self.expected_sectors =
+            (first_lsn <= last_lsn).then(|| u64::from(last_lsn - first_lsn) + 1);
+        self.channels()?;
+        Ok(())
+    }
+    fn write_sector(&mut self, sector: &[i16]) -> Result<()> {
+        if self.header.is_none() {
+            self.write_header()?;
+        }
+        let endianness = self.aifc.unwrap_or(Endianness::Big);
+        self.output.write(&endianness.encode(sector))?;
+        Ok(())
+    }
+    fn finish(mut self) -> Result<W> {
+        let header = match self.header {
+            Some(header) => header,
+            None => self.write_header()?,
+        };
+        if self.output.is_seekable() {
+            let data_len = self.output.written - header.len;
+            let (form_len, frames, ssnd_len) = self.sizes(header.len, data_len)?;
+            self.output.patch(4, &form_len.to_be_bytes())?;
+            self.output.patch(header.frames, &frames.to_be_bytes())?;
+            self.output
+                .patch(header.len - 12, &ssnd_len.to_be_bytes())?;
+        }
+
+        Ok(self.output.finish()?)
+    }
+    fn channels(&self) -> Result<u16> {
+        let channels = self.channels.unwrap_or(2);
+        if channels == 0 || !SECTOR_WORDS.is_multiple_of(usize::from(channels)) {
+            return Err(Error::InvalidOption(format!(
+                "AIFF supports channel counts that fill a sector with whole samples, not {channels}"
+            )));
+        }
+        Ok(channels)
+    }
+    /// Get the `FORM` size, the number of sample frames and the `SSND` size.
+    fn sizes(&self, header_len: u64, data_len: u64) -> Result<(u32, u32, u32)> {
+        let channels = u64::from(self.channels()?);
+        let convert = |len: u64| u32::try_from(len).unwrap_or(UNKNOWN_LENGTH);
+        Ok((
+            convert(header_len + data_len - 8),
+            convert(data_len / (2 * channels)),
+            convert(data_len + 8),
+        ))
+    }
+    fn write_header(&mut self) -> Result<Header> {
+        let channels = self.channels()?;
+
+        let mut comm = Vec::new();
+        comm.extend(channels.to_be_bytes());
+        comm.extend(UNKNOWN_LENGTH.to_be_bytes());
+        comm.extend(16u16.to_be_bytes());
+        comm.extend(SAMPLE_RATE_EXTENDED);
+        if let Some(endianness) = self.aifc {
+            let (compression, name): (&[u8; 4], &[u8]) = match endianness {
+                Endianness::Big => (b"NONE", b"not compressed"),
+                Endianness::Little => (b"sowt", b""),
+            };
+            comm.extend(compression);
+            comm.push(name.len() as u8);
+            comm.extend(name);
+            if name.len() % 2 == 0 {
+                comm.push(0);
+            }
+        }
+
+        let mut header = Vec::new();
+        header.extend(b"FORM");
+        header.extend(UNKNOWN_LENGTH.to_be_bytes());
+        if self.aifc.is_some() {
+            header.extend(b"AIFC");
+            write_chunk(&mut header, b"FVER", &AIFC_VERSION_1.to_be_bytes());
+        } else {
+            header.extend(b"AIFF");
+        }
+        let frames = header.len() as u64 + 10;
+        write_chunk(&mut header, b"COMM", &comm);
+        if self.preemphasis == Some(true) {
+            let mut channel_status = [0; 24];
+            channel_status[0] = AES_CHANNEL_STATUS_PREEMPHASIS;
+            write_chunk(&mut header, b"AESD", &channel_status);
+        }
+        header.extend(b"SSND");
+        header.extend(UNKNOWN_LENGTH.to_be_bytes());
+        // offset and block size
+        header.extend([0; 8]);
+
+        let len = header.len() as u64;
+        if let Some(sectors) = self.expected_sectors {
+            let (form_len, frames_len, ssnd_len) =
+                self.sizes(len, sectors * SECTOR_BYTES as u64)?;
+            let frames = frames as usize;
+            let ssnd = len as usize - 12;
+            header[4..8].copy_from_slice(&form_len.to_be_bytes());
+            header[frames..frames + 4].copy_from_slice(&frames_len.to_be_bytes());
+            header[ssnd..ssnd + 4].copy_from_slice(&ssnd_len.to_be_bytes());
+        }
+        self.output.write(&header)?;
+
+        let header = Header { len, frames };
+        self.header = Some(header);
+        Ok(header)
+    }
+}
+
+/// Append a chunk including its padding byte.
+fn write_chunk(buf: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    buf.extend(id);
+    buf.extend((data.len() as u32).to_be_bytes());
+    buf.extend(data);
+    if data.len() % 2 == 1 {
+        buf.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{testing::samples, Paranoia, VirtualDisc, VirtualTrack};
+
+    /// Split a file into its chunks, taking the rest of the file for chunks
+    /// of unknown length.
+    fn chunks(aiff: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut chunks = Vec::new();
+        let mut rest = &aiff[12..];
+        while !rest.is_empty() {
+            let len = u32_at(rest, 4);
+            let len = if len == UNKNOWN_LENGTH {
+                rest.len() - 8
+            } else {
+                len as usize
+            };
+            chunks.push((&rest[..4], &rest[8..8 + len]));
+            rest = &rest[(8 + len + len % 2).min(rest.len())..];
+        }
+        chunks
+    }
+
+    fn u32_at(bytes: &[u8], position: usize) -> u32 {
+        u32::from_be_bytes(bytes[position..position + 4].try_into().unwrap())
+    }
+
+    fn write<S: Sink>(mut sink: S, samples: &[i16]) -> S::Output {
+        for sector in samples.chunks(SECTOR_WORDS) {
+            sink.write_sector(sector).unwrap();
+        }
+        sink.finish().unwrap()
+    }
+
+    #[test]
+    fn aifc_big_endian() {
+        let samples = samples(3);
+        let aifc = write(Aifc::new(Cursor::new(Vec::new())), &samples).into_inner();
+
+        assert_eq!(&aifc[..4], b"FORM");
+        assert_eq!(u32_at(&aifc, 4) as usize, aifc.len() - 8);
+        assert_eq!(&aifc[8..12], b"AIFC");
+        let chunks = chunks(&aifc);
+        let [(b"FVER", fver), (b"COMM", comm), (b"SSND", ssnd)] = chunks[..] else {
+            panic!("unexpected chunks");
+        };
+        assert_eq!(fver, AIFC_VERSION_1.to_be_bytes());
+        assert_eq!(comm[..2], [0, 2]);
+        assert_eq!(u32_at(comm, 2) as usize, samples.len() / 2);
+        assert_eq!(comm[6..8], [0, 16]);
+        assert_eq!(comm[8..18], SAMPLE_RATE_EXTENDED);
+        // the name is a Pascal string padded to an even length
+        assert_eq!(&comm[18..], b"NONE\x0enot compressed\x00");
+        assert_eq!(ssnd[..8], [0; 8]);
+        assert_eq!(ssnd[8..], Endianness::Big.encode(&samples));
+    }
+
+    #[test]
+    fn aifc_little_endian() {
+        let samples = samples(1);
+        let aifc = write(
+            Aifc::streaming(Vec::new()).with_endianness(Endianness::Little),
+            &samples,
+        );
+        let chunks = chunks(&aifc);
+        let [(b"FVER", _), (b"COMM", comm), (b"SSND", ssnd)] = chunks[..] else {
+            panic!("unexpected chunks");
+        };
+        assert_eq!(&comm[18..], b"sowt\x00\x00");
+        assert_eq!(ssnd[8..], Endianness::Little.encode(&samples));
+    }
+
+    #[test]
+    fn aiff_streaming_with_unknown_length() {
+        let samples = samples(2);
+        let aiff = write(Aiff::streaming(Vec::new()).with_channels(4), &samples);
+
+        assert_eq!(&aiff[8..12], b"AIFF");
+        assert_eq!(u32_at(&aiff, 4), UNKNOWN_LENGTH);
+        let chunks = chunks(&aiff);
+        let [(b"COMM", comm), (b"SSND", ssnd)] = chunks[..] else {
+            panic!("unexpected chunks");
+        };
+        assert_eq!(comm.len(), 18);
+        assert_eq!(comm[..2], [0, 4]);
+        assert_eq!(u32_at(comm, 2), UNKNOWN_LENGTH);
+        assert_eq!(ssnd[8..], Endianness::Big.encode(&samples));
+    }
+
+    #[test]
+    fn aiff_from_the_table_of_contents() {
+        let samples = samples(5);
+        let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())
+            .with_channels(4)
+            .with_preemphasis(true)])
+        .unwrap();
+        let mut paranoia = Paranoia::new(disc);
+        let aiff = Aiff::streaming(Vec::new())
+            .write_all(paranoia.read_track(1).unwrap())
+            .unwrap();
+
+        // the sizes are known before the data is written
+        assert_eq!(u32_at(&aiff, 4) as usize, aiff.len() - 8);
+        let chunks = chunks(&aiff);
+        let [(b"COMM", comm), (b"AESD", aesd), (b"SSND", ssnd)] = chunks[..] else {
+            panic!("unexpected chunks");
+        };
+        assert_eq!(comm[..2], [0, 4]);
+        assert_eq!(u32_at(comm, 2) as usize, samples.len() / 4);
+        assert_eq!(aesd.len(), 24);
+        assert_eq!(aesd[0], AES_CHANNEL_STATUS_PREEMPHASIS);
+        assert_eq!(ssnd.len(), 8 + 5 * SECTOR_BYTES);
+    }
+
+    #[test]
+    fn unsupported_channel_counts() {
+        for channels in [0, 5, 9, 11] {
+            let mut aiff = Aiff::new(Cursor::new(Vec::new())).with_channels(channels);
+            assert!(matches!(
+                aiff.write_sector(&[0; SECTOR_WORDS]),
+                Err(Error::InvalidOption(_))
+            ));
+            let aifc = Aifc::new(Cursor::new(Vec::new())).with_channels(channels);
+            assert!(matches!(aifc.finish(), Err(Error::InvalidOption(_))));
+
+            let disc =
+                VirtualDisc::new(
+                    [VirtualTrack::new(vec![0; SECTOR_WORDS]).with_channels(channels)],
+                )
+                .unwrap();
+            let mut paranoia = Paranoia::new(disc);
+            let aiff = Aiff::streaming(Vec::new()).write_all(paranoia.read_track(1).unwrap());
+            assert!(matches!(aiff, Err(Error::InvalidOption(_))));
+        }
+    }
+}
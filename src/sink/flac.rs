@@ -0,0 +1,915 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! A FLAC encoder that only uses fixed predictors and Rice coding,
+//! which keeps it simple and fast at the cost of a few percent in size
+//! compared to LPC.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, Write},
+    path::Path,
+};
+
+use md5::{Digest, Md5};
+
+use super::{track_channels, Output, Sink, SAMPLE_RATE};
+use crate::{CdBackend, Error, Result, SECTOR_WORDS};
+
+/// Number of samples per channel in a frame.
+const BLOCK_SIZE: usize = 4096;
+/// Distance between seek points in samples.
+const SEEK_INTERVAL: u64 = 10 * SAMPLE_RATE as u64;
+/// Number of samples in the lead-in of a CD.
+const LEAD_IN_SAMPLES: u64 = 2 * SAMPLE_RATE as u64;
+const CD_LEAD_OUT_TRACK: u8 = 170;
+const LEAD_OUT_TRACK: u8 = 255;
+/// Maximum number of channels that `STREAMINFO` can store.
+const MAX_CHANNELS: u8 = 8;
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_PARTITION_ORDER: u32 = 8;
+const MAX_RICE_PARAMETER: u32 = 14;
+
+const STREAMINFO: u8 = 0;
+const SEEKTABLE: u8 = 3;
+const VORBIS_COMMENT: u8 = 4;
+const CUESHEET: u8 = 5;
+
+/// Writes audio data as a FLAC file.
+///
+/// [`write_all()`](Sink::write_all) embeds a `CUESHEET` block built from the
+/// table of contents, including the media catalog number, ISRCs, pregaps
+/// (index 0) and the pre-emphasis flag, so the same sink can be used for a
+/// single track ([`Paranoia::read_track()`](crate::Paranoia::read_track))
+/// or a whole-disc image ([`Paranoia::read_disc()`](crate::Paranoia::read_disc)).
+///
+/// If the writer is seekable (see [`Flac::new()`]), `STREAMINFO` is
+/// completed by [`finish()`](Sink::finish) and a `SEEKTABLE` with a seek point every
+/// 10 seconds is added. Streams written with [`Flac::streaming()`] have
+/// no seek table, no MD5 signature and unknown frame sizes.
+///
+/// This type is only available with the `flac` feature.
+///
+/// # Example
+///
+/// ```
+/// use cdparanoia::{
+///     sink::{Flac, Sink},
+///     Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS};
+///
+/// let disc = VirtualDisc::new([
+///     VirtualTrack::new(vec![0; 75 * SECTOR_WORDS]).with_isrc("XXA001234567"),
+///     VirtualTrack::new(vec![1; 75 * SECTOR_WORDS]).with_pregap(32),
+/// ])?
+/// .with_mcn("0123456789012");
+/// let mut paranoia = Paranoia::new(disc);
+///
+/// let flac = Flac::new(std::io::Cursor::new(Vec::new()))
+///     .with_comment("TITLE", "Disc image")
+///     .write_all(paranoia.read_disc()?)?
+///     .into_inner();
+///
+/// assert_eq!(&flac[..4], b"fLaC");
+/// # Ok::<(), cdparanoia::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Flac<W: Write> {
+    output: Output<W>,
+    channels: Option<u8>,
+    comments: Vec<String>,
+    cuesheet: Option<Vec<u8>>,
+    expected_samples: Option<u64>,
+    header_written: bool,
+    /// Position of the first frame.
+    first_frame: u64,
+    /// Position of the seek table data and the frames that get a seek point.
+    seektable: Option<(u64, Vec<u64>)>,
+    seekpoints: Vec<[u8; 18]>,
+    /// Interleaved samples that don't fill a whole frame yet.
+    pending: Vec<i16>,
+    frames: u64,
+    samples: u64,
+    frame_sizes: Option<(u32, u32)>,
+    md5: Md5,
+}
+
+impl Flac<BufWriter<File>> {
+    /// Create a FLAC file at the given path.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write + Seek> Flac<W> {
+    /// Write to a seekable writer, e.g. a [`File`].
+    pub fn new(writer: W) -> Self {
+        Self::from_output(Output::seekable(writer))
+    }
+}
+
+impl<W: Write> Flac<W> {
+    /// Write to a writer that isn't seekable, e.g. [`std::io::Stdout`].
+    pub fn streaming(writer: W) -> Self {
+        Self::from_output(Output::streaming(writer))
+    }
+    fn from_output(output: Output<W>) -> Self {
+        Self {
+            output,
+            channels: None,
+            comments: Vec::new(),
+            cuesheet: None,
+            expected_samples: None,
+            header_written: false,
+            first_frame: 0,
+            seektable: None,
+            seekpoints: Vec::new(),
+            pending: Vec::new(),
+            frames: 0,
+            samples: 0,
+            frame_sizes: None,
+            md5: Md5::new(),
+        }
+    }
+    /// Set the number of channels.
+    ///
+    /// By default, [`write_all()`](Sink::write_all) uses the channel count of
+    /// the track that is being read and [`write_sector()`](Sink::write_sector)
+    /// assumes two channels.
+    ///
+    /// FLAC supports 1 to 8 channels, and a sector has to contain whole
+    /// samples of all channels, so 5 channels aren't supported either.
+    pub fn with_channels(mut self, channels: u8) -> Self {
+        self.channels = Some(channels);
+        self
+    }
+    fn channels(&self) -> Result<usize> {
+        let channels = self.channels.unwrap_or(2);
+        if !(1..=MAX_CHANNELS).contains(&channels)
+            || !SECTOR_WORDS.is_multiple_of(usize::from(channels))
+        {
+            return Err(Error::InvalidOption(format!(
+                "FLAC supports 1 to {MAX_CHANNELS} channels that fill a sector with whole samples, not {channels}"
+            )));
+        }
+        Ok(usize::from(channels))
+    }
+    /// Add a Vorbis comment, e.g. `TITLE`, `ARTIST`, `ALBUM`,
+    /// `TRACKNUMBER` or `DATE`.
+    pub fn with_comment(mut self, key: &str, value: &str) -> Self {
+        self.comments.push(format!("{key}={value}"));
+        self
+    }
+}
+
+impl<W: Write> Sink for Flac<W> {
+    type Output = W;
+
+    /// Take the channel count and the `CUESHEET` from the table of contents.
+    ///
+    /// Fails if the channel count isn't supported, see
+    /// [`with_channels()`](Flac::with_channels).
+    fn begin<B: CdBackend + ?Sized>(
+        &mut self,
+        backend: &B,
+        first_lsn: u32,
+        last_lsn: u32,
+    ) -> Result<()> {
+        if self.channels.is_none() {
+            self.channels = track_channels(backend, first_lsn);
+        }
+        let channels = self.channels()?;
+        if first_lsn <= last_lsn {
+            // at most `MAX_CHANNELS`
+            self.cuesheet = Some(cuesheet(backend, first_lsn, last_lsn, channels as u8));
+            self.expected_samples = Some(
+                (u64::from(last_lsn) - u64::from(first_lsn) + 1) * (SECTOR_WORDS / channels) as u64,
+            );
+        }
+        Ok(())
+    }
+    fn write_sector(&mut self, sector: &[i16]) -> Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        let channels = usize::from(self.channels.unwrap_or(2));
+
+        self.pending.extend_from_slice(sector);
+        let block = BLOCK_SIZE * channels;
+        let mut start = 0;
+        while self.pending.len() - start >= block {
+            let samples = self.pending[start..start + block].to_vec();
+            self.write_frame(&samples)?;
+            start += block;
+        }
+        self.pending.drain(..start);
+        Ok(())
+    }
+    /// Encode the remaining samples, complete the metadata if possible,
+    /// flush and return the writer.
+    fn finish(mut self) -> Result<W> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        if !self.pending.is_empty() {
+            let samples = std::mem::take(&mut self.pending);
+            self.write_frame(&samples)?;
+        }
+
+        if self.output.is_seekable() {
+            let md5 = std::mem::take(&mut self.md5).finalize().into();
+            let streaminfo = self.streaminfo(self.samples, self.frame_sizes, md5);
+            self.output.patch(8, &streaminfo)?;
+
+            if let Some((offset, frames)) = &self.seektable {
+                let mut seektable: Vec<u8> = self.seekpoints.concat();
+                for _ in self.seekpoints.len()..frames.len() {
+                    seektable.extend(seekpoint(u64::MAX, 0, 0));
+                }
+                self.output.patch(*offset, &seektable)?;
+            }
+        }
+        Ok(self.output.finish()?)
+    }
+}
+
+impl<W: Write> Flac<W> {
+    fn write_header(&mut self) -> Result<()> {
+        self.channels()?;
+        self.header_written = true;
+
+        let mut blocks = vec![(
+            STREAMINFO,
+            self.streaminfo(self.expected_samples.unwrap_or(0), None, [0; 16]),
+        )];
+        if let (true, Some(expected_samples)) = (self.output.is_seekable(), self.expected_samples) {
+            let frames: Vec<u64> = (0..expected_samples)
+                .step_by(SEEK_INTERVAL as usize)
+                .map(|sample| sample / BLOCK_SIZE as u64)
+                .collect();
+            let placeholders = frames
+                .iter()
+                .flat_map(|_| seekpoint(u64::MAX, 0, 0))
+                .collect();
+            blocks.push((SEEKTABLE, placeholders));
+            self.seektable = Some((0, frames));
+        }
+        blocks.push((VORBIS_COMMENT, vorbis_comment(&self.comments)));
+        if let Some(cuesheet) = self.cuesheet.take() {
+            blocks.push((CUESHEET, cuesheet));
+        }
+
+        let mut header = b"fLaC".to_vec();
+        let count = blocks.len();
+        for (i, (kind, data)) in blocks.into_iter().enumerate() {
+            let last = if i + 1 == count { 0x80 } else { 0 };
+            header.push(kind | last);
+            header.extend(&(data.len() as u32).to_be_bytes()[1..]);
+            if let (SEEKTABLE, Some((offset, _))) = (kind, &mut self.seektable) {
+                *offset = header.len() as u64;
+            }
+            header.extend(data);
+        }
+
+        self.output.write(&header)?;
+        self.first_frame = self.output.written;
+        Ok(())
+    }
+    fn streaminfo(&self, samples: u64, frame_sizes: Option<(u32, u32)>, md5: [u8; 16]) -> Vec<u8> {
+        let channels = u64::from(self.channels.unwrap_or(2));
+        let (min_frame, max_frame) = frame_sizes.unwrap_or((0, 0));
+
+        let mut data = Vec::with_capacity(34);
+        data.extend((BLOCK_SIZE as u16).to_be_bytes());
+        data.extend((BLOCK_SIZE as u16).to_be_bytes());
+        data.extend(&min_frame.to_be_bytes()[1..]);
+        data.extend(&max_frame.to_be_bytes()[1..]);
+        data.extend(
+            (u64::from(SAMPLE_RATE) << 44 | (channels - 1) << 41 | 15 << 36 | samples)
+                .to_be_bytes(),
+        );
+        data.extend(md5);
+        data
+    }
+    fn write_frame(&mut self, samples: &[i16]) -> io::Result<()> {
+        let channels = usize::from(self.channels.unwrap_or(2));
+        let block_size = samples.len() / channels;
+
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.md5.update(&bytes);
+
+        let frame = encode_frame(samples, channels, self.frames);
+        let size = frame.len() as u32;
+        self.frame_sizes = Some(match self.frame_sizes {
+            Some((min, max)) => (min.min(size), max.max(size)),
+            None => (size, size),
+        });
+
+        if let Some((_, frames)) = &self.seektable {
+            if frames.get(self.seekpoints.len()) == Some(&self.frames) {
+                self.seekpoints.push(seekpoint(
+                    self.samples,
+                    self.output.written - self.first_frame,
+                    block_size as u16,
+                ));
+            }
+        }
+
+        self.output.write(&frame)?;
+        self.frames += 1;
+        self.samples += block_size as u64;
+        Ok(())
+    }
+}
+
+fn seekpoint(sample: u64, offset: u64, samples: u16) -> [u8; 18] {
+    let mut point = [0; 18];
+    point[..8].copy_from_slice(&sample.to_be_bytes());
+    point[8..16].copy_from_slice(&offset.to_be_bytes());
+    point[16..].copy_from_slice(&samples.to_be_bytes());
+    point
+}
+
+fn vorbis_comment(comments: &[String]) -> Vec<u8> {
+    let vendor = concat!("cdparanoia-rs ", env!("CARGO_PKG_VERSION"));
+    let mut data = Vec::new();
+    data.extend((vendor.len() as u32).to_le_bytes());
+    data.extend(vendor.as_bytes());
+    data.extend((comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        data.extend((comment.len() as u32).to_le_bytes());
+        data.extend(comment.as_bytes());
+    }
+    data
+}
+
+/// Build a `CUESHEET` block for the sectors `first_lsn..=last_lsn`.
+fn cuesheet<B: CdBackend + ?Sized>(
+    backend: &B,
+    first_lsn: u32,
+    last_lsn: u32,
+    channels: u8,
+) -> Vec<u8> {
+    let is_cd = channels == 2;
+    let sector_samples = (SECTOR_WORDS / usize::from(channels)) as u64;
+    let offset = |lsn: u32| u64::from(lsn - first_lsn) * sector_samples;
+
+    let mut data = Vec::new();
+    let mut mcn = [0; 128];
+    if let Some(disc_mcn) = backend.disc_mcn() {
+        let len = disc_mcn.len().min(mcn.len());
+        mcn[..len].copy_from_slice(&disc_mcn.as_bytes()[..len]);
+    }
+    data.extend(mcn);
+    data.extend(if is_cd { LEAD_IN_SAMPLES } else { 0 }.to_be_bytes());
+    data.push(if is_cd { 0x80 } else { 0 });
+    data.extend([0; 258]);
+
+    let tracks: Vec<(u8, u32)> = (1..=backend.tracks())
+        .filter_map(|track| {
+            let first = backend.track_first_sector(track).ok()?;
+            let last = backend.track_last_sector(track).ok()?;
+            (first <= last_lsn && last >= first_lsn).then_some((track, first.max(first_lsn)))
+        })
+        .collect();
+    data.push(tracks.len() as u8 + 1);
+
+    for (track, start) in tracks {
+        let pregap = backend
+            .track_pregap_sector(track)
+            .filter(|&pregap| pregap >= first_lsn && pregap < start);
+        let track_offset = offset(pregap.unwrap_or(start));
+        data.extend(track_offset.to_be_bytes());
+        data.push(track);
+
+        let mut isrc = [0; 12];
+        if let Some(track_isrc) = backend.track_isrc(track) {
+            let len = track_isrc.len().min(isrc.len());
+            isrc[..len].copy_from_slice(&track_isrc.as_bytes()[..len]);
+        }
+        data.extend(isrc);
+
+        let mut flags = 0;
+        if !backend.track_audio(track) {
+            flags |= 0x80;
+        }
+        if backend.track_linear_preemphasis(track) {
+            flags |= 0x40;
+        }
+        data.push(flags);
+        data.extend([0; 13]);
+
+        let indexes: Vec<(u8, u32)> = pregap
+            .map(|pregap| (0, pregap))
+            .into_iter()
+            .chain([(1, start)])
+            .collect();
+        data.push(indexes.len() as u8);
+        for (number, lsn) in indexes {
+            data.extend((offset(lsn) - track_offset).to_be_bytes());
+            data.push(number);
+            data.extend([0; 3]);
+        }
+    }
+
+    data.extend((offset(last_lsn) + sector_samples).to_be_bytes());
+    data.push(if is_cd {
+        CD_LEAD_OUT_TRACK
+    } else {
+        LEAD_OUT_TRACK
+    });
+    data.extend([0; 12]);
+    data.push(0);
+    data.extend([0; 13]);
+    data.push(0);
+    data
+}
+
+/// Encode a frame from interleaved samples.
+fn encode_frame(samples: &[i16], channels: usize, frame_number: u64) -> Vec<u8> {
+    let block_size = samples.len() / channels;
+    let signals: Vec<Vec<i32>> = (0..channels)
+        .map(|channel| {
+            samples[channel..]
+                .iter()
+                .step_by(channels)
+                .map(|&sample| sample.into())
+                .collect()
+        })
+        .collect();
+
+    let mid_side;
+    let (assignment, subframes) = if channels == 2 {
+        let (left, right) = (&signals[0], &signals[1]);
+        mid_side = (
+            left.iter()
+                .zip(right)
+                .map(|(l, r)| (l + r) >> 1)
+                .collect::<Vec<_>>(),
+            left.iter()
+                .zip(right)
+                .map(|(l, r)| l - r)
+                .collect::<Vec<_>>(),
+        );
+        let left = Subframe::encode(left, 16);
+        let right = Subframe::encode(right, 16);
+        let mid = Subframe::encode(&mid_side.0, 16);
+        let side = Subframe::encode(&mid_side.1, 17);
+
+        let candidates = [
+            (left.bits + right.bits, 0b0001),
+            (left.bits + side.bits, 0b1000),
+            (side.bits + right.bits, 0b1001),
+            (mid.bits + side.bits, 0b1010),
+        ];
+        let (_, assignment) = candidates.into_iter().min().unwrap();
+        let subframes = match assignment {
+            0b0001 => vec![left, right],
+            0b1000 => vec![left, side],
+            0b1001 => vec![side, right],
+            _ => vec![mid, side],
+        };
+        (assignment, subframes)
+    } else {
+        let subframes = signals
+            .iter()
+            .map(|signal| Subframe::encode(signal, 16))
+            .collect();
+        (channels as u64 - 1, subframes)
+    };
+
+    let mut writer = BitWriter::default();
+    writer.write(0xfff8, 16);
+    let block_size_code = if block_size == BLOCK_SIZE {
+        0b1100
+    } else {
+        0b0111
+    };
+    writer.write(block_size_code, 4);
+    // 44.1 kHz
+    writer.write(0b1001, 4);
+    writer.write(assignment, 4);
+    // 16 bits per sample
+    writer.write(0b100, 3);
+    writer.write(0, 1);
+    for byte in utf8_number(frame_number) {
+        writer.write(byte.into(), 8);
+    }
+    if block_size_code == 0b0111 {
+        writer.write(block_size as u64 - 1, 16);
+    }
+    let crc = crc8(&writer.bytes);
+    writer.write(crc.into(), 8);
+
+    for subframe in &subframes {
+        subframe.write(&mut writer);
+    }
+    writer.align();
+    let crc = crc16(&writer.bytes);
+    writer.write(crc.into(), 16);
+    writer.bytes
+}
+
+struct Subframe<'a> {
+    signal: &'a [i32],
+    bits_per_sample: u32,
+    kind: SubframeKind,
+    /// Encoded size in bits.
+    bits: u64,
+}
+
+enum SubframeKind {
+    Constant,
+    Verbatim,
+    Fixed {
+        order: usize,
+        residual: Vec<i32>,
+        partition_order: u32,
+        parameters: Vec<u32>,
+    },
+}
+
+impl<'a> Subframe<'a> {
+    fn encode(signal: &'a [i32], bits_per_sample: u32) -> Self {
+        let n = signal.len() as u64;
+        let bps = u64::from(bits_per_sample);
+        if signal.iter().all(|&sample| sample == signal[0]) {
+            return Self {
+                signal,
+                bits_per_sample,
+                kind: SubframeKind::Constant,
+                bits: 8 + bps,
+            };
+        }
+
+        let mut best = Self {
+            signal,
+            bits_per_sample,
+            kind: SubframeKind::Verbatim,
+            bits: 8 + bps * n,
+        };
+        for order in 0..=MAX_FIXED_ORDER.min(signal.len() - 1) {
+            let residual = fixed_residual(signal, order);
+            let (partition_order, parameters, residual_bits) =
+                rice_parameters(&residual, signal.len(), order);
+            let bits = 8 + order as u64 * bps + 6 + residual_bits;
+            if bits < best.bits {
+                best.bits = bits;
+                best.kind = SubframeKind::Fixed {
+                    order,
+                    residual,
+                    partition_order,
+                    parameters,
+                };
+            }
+        }
+        best
+    }
+    fn write(&self, writer: &mut BitWriter) {
+        let bps = self.bits_per_sample;
+        match &self.kind {
+            SubframeKind::Constant => {
+                writer.write(0b000000 << 1, 8);
+                writer.write_signed(self.signal[0], bps);
+            }
+            SubframeKind::Verbatim => {
+                writer.write(0b000001 << 1, 8);
+                for &sample in self.signal {
+                    writer.write_signed(sample, bps);
+                }
+            }
+            SubframeKind::Fixed {
+                order,
+                residual,
+                partition_order,
+                parameters,
+            } => {
+                writer.write((0b001000 | *order as u64) << 1, 8);
+                for &sample in &self.signal[..*order] {
+                    writer.write_signed(sample, bps);
+                }
+                // Rice coding with 4-bit parameters
+                writer.write(0b00, 2);
+                writer.write((*partition_order).into(), 4);
+
+                let partition_len = self.signal.len() >> partition_order;
+                let mut residual = residual.as_slice();
+                for (i, &parameter) in parameters.iter().enumerate() {
+                    let len = if i == 0 {
+                        partition_len - order
+                    } else {
+                        partition_len
+                    };
+                    let (partition, rest) = residual.split_at(len);
+                    residual = rest;
+
+                    writer.write(parameter.into(), 4);
+                    for &value in partition {
+                        let value = zigzag(value);
+                        writer.write_unary(value >> parameter);
+                        writer.write(value & ((1 << parameter) - 1), parameter);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn fixed_residual(signal: &[i32], order: usize) -> Vec<i32> {
+    const COEFFICIENTS: [&[i64]; MAX_FIXED_ORDER + 1] =
+        [&[], &[1], &[2, -1], &[3, -3, 1], &[4, -6, 4, -1]];
+    (order..signal.len())
+        .map(|i| {
+            let prediction: i64 = COEFFICIENTS[order]
+                .iter()
+                .enumerate()
+                .map(|(j, &coefficient)| coefficient * i64::from(signal[i - j - 1]))
+                .sum();
+            (i64::from(signal[i]) - prediction) as i32
+        })
+        .collect()
+}
+
+/// Choose the partition order and Rice parameters with the smallest
+/// estimated size, returned in bits.
+fn rice_parameters(residual: &[i32], block_size: usize, order: usize) -> (u32, Vec<u32>, u64) {
+    let max_order = (0..=MAX_PARTITION_ORDER)
+        .take_while(|&partition_order| {
+            block_size.is_multiple_of(1 << partition_order) && block_size >> partition_order > order
+        })
+        .last()
+        .unwrap_or(0);
+
+    // sums of the finest partitions, merged pairwise for lower orders
+    let partition_len = block_size >> max_order;
+    let mut sums: Vec<u64> = (0..1 << max_order)
+        .map(|i| {
+            let start = (i * partition_len).saturating_sub(order);
+            let end = (i + 1) * partition_len - order;
+            residual[start..end].iter().map(|&r| zigzag(r)).sum()
+        })
+        .collect();
+
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+    for partition_order in (0..=max_order).rev() {
+        let partition_len = (block_size >> partition_order) as u64;
+        let mut parameters = Vec::with_capacity(sums.len());
+        let mut bits = 0;
+        for (i, &sum) in sums.iter().enumerate() {
+            let n = if i == 0 {
+                partition_len - order as u64
+            } else {
+                partition_len
+            };
+            let (parameter, partition_bits) = (0..=MAX_RICE_PARAMETER)
+                .map(|k| (k, n * u64::from(k + 1) + (sum >> k)))
+                .min_by_key(|&(_, bits)| bits)
+                .unwrap();
+            parameters.push(parameter);
+            bits += 4 + partition_bits;
+        }
+        if best.as_ref().is_none_or(|(_, _, best)| bits < *best) {
+            best = Some((partition_order, parameters, bits));
+        }
+        sums = sums.chunks(2).map(|pair| pair.iter().sum()).collect();
+    }
+    best.unwrap()
+}
+
+fn zigzag(value: i32) -> u64 {
+    ((value << 1) ^ (value >> 31)) as u32 as u64
+}
+
+/// Encode a frame number like a UTF-8 character.
+fn utf8_number(value: u64) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    let mut continuation = Vec::new();
+    let mut value = value;
+    let mut first_bits = 6;
+    while value >= 1 << first_bits {
+        continuation.push(0x80 | (value & 0x3f) as u8);
+        value >>= 6;
+        first_bits -= 1;
+    }
+    let len = continuation.len() + 1;
+    let prefix = !(0xffu8 >> len);
+    let mut bytes = vec![prefix | value as u8];
+    bytes.extend(continuation.into_iter().rev());
+    bytes
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    /// Write the lowest `bits` bits of `value`, `bits` must be at most 32.
+    fn write(&mut self, value: u64, bits: u32) {
+        self.buffer = self.buffer << bits | (value & ((1 << bits) - 1));
+        self.len += bits;
+        while self.len >= 8 {
+            self.len -= 8;
+            self.bytes.push((self.buffer >> self.len) as u8);
+        }
+    }
+    fn write_signed(&mut self, value: i32, bits: u32) {
+        self.write(value as u32 as u64, bits);
+    }
+    /// Write `value` zeros followed by a one.
+    fn write_unary(&mut self, mut value: u64) {
+        while value >= 32 {
+            self.write(0, 32);
+            value -= 32;
+        }
+        self.write(1, value as u32 + 1);
+    }
+    /// Pad with zeros to a whole byte.
+    fn align(&mut self) {
+        if self.len > 0 {
+            self.write(0, 8 - self.len);
+        }
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                crc << 1 ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |mut crc, &byte| {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{testing::samples, Paranoia, VirtualDisc, VirtualTrack};
+
+    /// A signal that needs every kind of subframe: noise, ramps and silence.
+    fn signal(len: usize) -> Vec<i16> {
+        let noise = samples(len.div_ceil(SECTOR_WORDS));
+        (0..len)
+            .map(|i| match i / 10000 % 3 {
+                0 => noise[i],
+                1 => (i % 2000 * 16) as i16,
+                _ => 0,
+            })
+            .collect()
+    }
+
+    fn md5(samples: &[i16]) -> [u8; 16] {
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        Md5::digest(bytes).into()
+    }
+
+    /// Decode a FLAC file and return its `STREAMINFO` and samples.
+    fn decode(flac: &[u8]) -> (claxon::metadata::StreamInfo, Vec<i16>) {
+        let mut reader = claxon::FlacReader::new(flac).unwrap();
+        let samples = reader
+            .samples()
+            .map(|sample| sample.unwrap().try_into().unwrap())
+            .collect();
+        (reader.streaminfo(), samples)
+    }
+
+    #[test]
+    fn stereo_disc_round_trip() {
+        let tracks = [signal(100 * SECTOR_WORDS), signal(37 * SECTOR_WORDS)];
+        let disc = VirtualDisc::new(tracks.clone().map(VirtualTrack::new)).unwrap();
+        let mut paranoia = Paranoia::new(disc);
+
+        let flac = Flac::new(Cursor::new(Vec::new()))
+            .write_all(paranoia.read_disc().unwrap())
+            .unwrap()
+            .into_inner();
+        let (streaminfo, samples) = decode(&flac);
+
+        let expected = tracks.concat();
+        assert_eq!(streaminfo.channels, 2);
+        assert_eq!(streaminfo.bits_per_sample, 16);
+        assert_eq!(streaminfo.sample_rate, SAMPLE_RATE);
+        assert_eq!(streaminfo.samples, Some(expected.len() as u64 / 2));
+        assert!(streaminfo.min_frame_size.is_some());
+        assert_eq!(streaminfo.md5sum, md5(&expected));
+        assert!(samples == expected);
+    }
+
+    #[test]
+    fn stereo_streaming_round_trip() {
+        let track = signal(100 * SECTOR_WORDS);
+        let disc = VirtualDisc::new([VirtualTrack::new(track.clone())]).unwrap();
+        let mut paranoia = Paranoia::new(disc);
+
+        let flac = Flac::streaming(Vec::new())
+            .write_all(paranoia.read_track(1).unwrap())
+            .unwrap();
+        let (streaminfo, samples) = decode(&flac);
+
+        // the length is known from the table of contents, the MD5 isn't
+        assert_eq!(streaminfo.samples, Some(track.len() as u64 / 2));
+        assert_eq!(streaminfo.min_frame_size, None);
+        assert_eq!(streaminfo.md5sum, [0; 16]);
+        assert!(samples == track);
+    }
+
+    #[test]
+    fn mono_round_trip() {
+        let signal = signal(50 * SECTOR_WORDS);
+        for seekable in [true, false] {
+            let writer = Cursor::new(Vec::new());
+            let flac = if seekable {
+                Flac::new(writer)
+            } else {
+                Flac::streaming(writer)
+            };
+            let mut flac = flac.with_channels(1);
+            for sector in signal.chunks(SECTOR_WORDS) {
+                flac.write_sector(sector).unwrap();
+            }
+            let (streaminfo, samples) = decode(flac.finish().unwrap().get_ref());
+
+            assert_eq!(streaminfo.channels, 1);
+            if seekable {
+                assert_eq!(streaminfo.samples, Some(signal.len() as u64));
+                assert_eq!(streaminfo.md5sum, md5(&signal));
+            } else {
+                assert_eq!(streaminfo.samples, None);
+            }
+            assert!(samples == signal);
+        }
+    }
+
+    #[test]
+    fn multichannel_round_trip() {
+        for channels in [3, 4, 6, 7, 8] {
+            let signal = signal(20 * SECTOR_WORDS);
+            let mut flac = Flac::new(Cursor::new(Vec::new())).with_channels(channels);
+            for sector in signal.chunks(SECTOR_WORDS) {
+                flac.write_sector(sector).unwrap();
+            }
+            let (streaminfo, samples) = decode(flac.finish().unwrap().get_ref());
+
+            assert_eq!(streaminfo.channels, u32::from(channels));
+            assert_eq!(streaminfo.md5sum, md5(&signal));
+            assert!(samples == signal, "{channels} channels");
+        }
+    }
+
+    #[test]
+    fn unsupported_channel_counts() {
+        for channels in [0, 5, 9, 12] {
+            let mut flac = Flac::new(Cursor::new(Vec::new())).with_channels(channels);
+            assert!(matches!(
+                flac.write_sector(&[0; SECTOR_WORDS]),
+                Err(Error::InvalidOption(_))
+            ));
+            let flac = Flac::streaming(Vec::new()).with_channels(channels);
+            assert!(matches!(flac.finish(), Err(Error::InvalidOption(_))));
+
+            let disc =
+                VirtualDisc::new(
+                    [VirtualTrack::new(vec![0; SECTOR_WORDS]).with_channels(channels)],
+                )
+                .unwrap();
+            let mut paranoia = Paranoia::new(disc);
+            let flac = Flac::streaming(Vec::new()).write_all(paranoia.read_track(1).unwrap());
+            assert!(matches!(flac, Err(Error::InvalidOption(_))));
+        }
+    }
+
+    #[test]
+    fn begin_with_the_whole_address_space() {
+        let disc = VirtualDisc::new([VirtualTrack::new(vec![0; SECTOR_WORDS])]).unwrap();
+        let mut flac = Flac::streaming(Vec::new());
+        flac.begin(&disc, 0, u32::MAX).unwrap();
+        assert_eq!(
+            flac.expected_samples,
+            Some((1 << 32) * (SECTOR_WORDS / 2) as u64)
+        );
+    }
+}
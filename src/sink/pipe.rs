@@ -0,0 +1,308 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    io::{self, Read},
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+    thread::JoinHandle,
+};
+
+use super::{track_channels, Sink, Wav};
+use crate::{CdBackend, Error, Result};
+
+/// Streams audio data as WAV into the standard input of an external
+/// encoder, e.g. `opusenc - out.opus`.
+///
+/// The command is spawned by [`begin()`](Sink::begin) or when the first sector
+/// is written. Before that, `{track}` in the program name and arguments is
+/// replaced with the two-digit number of the track that is being read, and
+/// `{name}` with values from [`with_placeholder()`](Pipe::with_placeholder).
+///
+/// The standard error of the encoder is collected and returned together with
+/// its exit status by [`finish()`](Sink::finish). If the encoder exits
+/// before all data has been written, the next write fails with
+/// [`Error::Encoder`], so [`write_all()`](Sink::write_all) stops reading the
+/// disc. Dropping a `Pipe` without finishing it kills the encoder.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(unix)]
+/// # {
+/// use cdparanoia::{
+///     sink::{Pipe, Sink},
+///     Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+/// };
+///
+/// let disc = VirtualDisc::new([VirtualTrack::new(vec![1; 10 * SECTOR_WORDS])])?;
+/// let mut paranoia = Paranoia::new(disc);
+///
+/// // `opusenc --title {title} - track{track}.opus` works the same way
+/// let output = Pipe::new(["sh", "-c", "echo $0 >&2; wc -c >&2", "track{track}"])
+///     .write_all(paranoia.read_track(1)?)?;
+///
+/// assert!(output.status.success());
+/// assert_eq!(output.stderr.split_whitespace().collect::<Vec<_>>(), ["track01", "23564"]);
+/// # }
+/// # Ok::<(), cdparanoia::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Pipe {
+    command: Vec<String>,
+    placeholders: Vec<(String, String)>,
+    stdout: Option<Stdio>,
+    channels: Option<u8>,
+    track: Option<u8>,
+    encoder: Option<Encoder>,
+}
+
+/// The result of running an external encoder.
+#[derive(Debug, Clone)]
+pub struct EncoderOutput {
+    pub status: ExitStatus,
+    /// Everything the encoder wrote to its standard error.
+    pub stderr: String,
+}
+
+impl Pipe {
+    /// Run the given program with arguments.
+    pub fn new(command: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            command: command.into_iter().map(Into::into).collect(),
+            placeholders: Vec::new(),
+            stdout: None,
+            channels: None,
+            track: None,
+            encoder: None,
+        }
+    }
+    /// Replace `{name}` in the command with `value`, e.g. for `{title}`
+    /// or `{artist}`.
+    pub fn with_placeholder(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.placeholders.push((name.into(), value.into()));
+        self
+    }
+    /// Set the standard output of the encoder. By default, it is inherited.
+    pub fn with_stdout(mut self, stdout: impl Into<Stdio>) -> Self {
+        self.stdout = Some(stdout.into());
+        self
+    }
+    /// Set the number of channels.
+    ///
+    /// By default, [`write_all()`](Sink::write_all) uses the channel count of
+    /// the track that is being read and [`write_sector()`](Sink::write_sector)
+    /// assumes two channels.
+    pub fn with_channels(mut self, channels: u8) -> Self {
+        self.channels = Some(channels);
+        self
+    }
+    fn expand(&self, arg: &str) -> String {
+        let mut arg = arg.to_owned();
+        if let Some(track) = self.track {
+            arg = arg.replace("{track}", &format!("{track:02}"));
+        }
+        for (name, value) in &self.placeholders {
+            arg = arg.replace(&format!("{{{name}}}"), value);
+        }
+        arg
+    }
+    fn spawn(&mut self) -> Result<&mut Encoder> {
+        let mut command = self.command.iter().map(|arg| self.expand(arg));
+        let program = command.next().unwrap_or_default();
+        let mut child = Command::new(program)
+            .args(command)
+            .stdin(Stdio::piped())
+            .stdout(self.stdout.take().unwrap_or_else(Stdio::inherit))
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr = std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            stderr.read_to_end(&mut buf).map(|_| buf)
+        });
+        let mut wav = Wav::streaming(child.stdin.take().expect("stdin is piped"));
+        if let Some(channels) = self.channels {
+            wav = wav.with_channels(channels);
+        }
+
+        Ok(self.encoder.insert(Encoder {
+            child,
+            wav: Some(wav),
+            stderr: Some(stderr),
+        }))
+    }
+}
+
+impl Sink for Pipe {
+    type Output = EncoderOutput;
+
+    fn begin<B: CdBackend + ?Sized>(
+        &mut self,
+        backend: &B,
+        first_lsn: u32,
+        last_lsn: u32,
+    ) -> Result<()> {
+        if self.channels.is_none() {
+            self.channels = track_channels(backend, first_lsn);
+        }
+        self.track = backend.sector_track(first_lsn).ok();
+
+        let encoder = match &mut self.encoder {
+            Some(encoder) => encoder,
+            None => self.spawn()?,
+        };
+        let wav = encoder
+            .wav
+            .as_mut()
+            .expect("wav is only taken when finishing");
+        wav.begin(backend, first_lsn, last_lsn)
+    }
+    fn write_sector(&mut self, sector: &[i16]) -> Result<()> {
+        let encoder = match &mut self.encoder {
+            Some(encoder) => encoder,
+            None => self.spawn()?,
+        };
+        let wav = encoder
+            .wav
+            .as_mut()
+            .expect("wav is only taken when finishing");
+        match wav.write_sector(sector) {
+            Err(Error::Io(_)) => {
+                // Most likely a broken pipe, so report why the encoder exited.
+                let output = self.encoder.take().expect("encoder was spawned").wait()?;
+                Err(Error::Encoder {
+                    status: output.status,
+                    stderr: output.stderr,
+                })
+            }
+            result => result,
+        }
+    }
+    /// Close the standard input of the encoder and wait for it to exit.
+    fn finish(mut self) -> Result<EncoderOutput> {
+        if self.encoder.is_none() {
+            self.spawn()?;
+        }
+        let mut encoder = self.encoder.take().expect("encoder was spawned");
+        if let Some(wav) = encoder.wav.take() {
+            // The encoder may already be done, its exit status tells more.
+            let _ = wav.finish();
+        }
+        encoder.wait()
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        if let Some(mut encoder) = self.encoder.take() {
+            let _ = encoder.child.kill();
+            let _ = encoder.wait();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Encoder {
+    child: Child,
+    wav: Option<Wav<ChildStdin>>,
+    stderr: Option<JoinHandle<io::Result<Vec<u8>>>>,
+}
+
+impl Encoder {
+    fn wait(&mut self) -> Result<EncoderOutput> {
+        drop(self.wav.take());
+        let status = self.child.wait()?;
+        let stderr = match self.stderr.take().map(JoinHandle::join) {
+            Some(Ok(stderr)) => stderr?,
+            _ => Vec::new(),
+        };
+        Ok(EncoderOutput {
+            status,
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{Paranoia, VirtualDisc, VirtualTrack, SECTOR_BYTES, SECTOR_WORDS};
+
+    fn paranoia(sectors: usize) -> Paranoia<VirtualDisc> {
+        let disc = VirtualDisc::new([
+            VirtualTrack::new(vec![0; SECTOR_WORDS]),
+            VirtualTrack::new(vec![1; sectors * SECTOR_WORDS]).with_channels(4),
+        ])
+        .unwrap();
+        Paranoia::new(disc)
+    }
+
+    #[test]
+    fn encoder_gets_a_wav_stream() {
+        let output = Pipe::new([
+            "sh",
+            "-c",
+            "head -c 4 >&2; echo \" $0 {title} $(head -c 20 | tail -c 2 | od -An -tu1)\" >&2; wc -c >&2",
+            "{track}",
+        ])
+        .with_placeholder("title", "Title")
+        .write_all(paranoia(3).read_track(2).unwrap())
+        .unwrap();
+
+        assert!(output.status.success());
+        let stderr: Vec<&str> = output.stderr.split_whitespace().collect();
+        // the channel count follows the format tag, which is at byte 20
+        assert_eq!(
+            stderr,
+            [
+                "RIFF",
+                "02",
+                "Title",
+                "4",
+                "0",
+                &(3 * SECTOR_BYTES + 68 - 24).to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn encoder_dies_mid_track() {
+        let result = Pipe::new([
+            "sh",
+            "-c",
+            "head -c 1000 >/dev/null; echo giving up >&2; exit 3",
+        ])
+        .write_all(paranoia(200).read_track(2).unwrap());
+
+        match result {
+            Err(Error::Encoder { status, stderr }) => {
+                assert_eq!(status.code(), Some(3));
+                assert_eq!(stderr, "giving up\n");
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+
+    #[test]
+    fn failed_encoder_is_reported_by_finish() {
+        let mut pipe = Pipe::new(["sh", "-c", "cat >/dev/null; exit 1"]);
+        pipe.write_sector(&[0; SECTOR_WORDS]).unwrap();
+        let output = pipe.finish().unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(output.stderr, "");
+
+        // the header is written even without audio data
+        let output = Pipe::new(["sh", "-c", "wc -c >&2"]).finish().unwrap();
+        assert_eq!(output.stderr.trim(), "44");
+    }
+
+    #[test]
+    fn missing_encoder() {
+        let mut pipe = Pipe::new(["/nonexistent/encoder"]);
+        assert!(matches!(
+            pipe.write_sector(&[0; SECTOR_WORDS]),
+            Err(Error::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::{Endianness, Output, Sink};
+use crate::Result;
+
+/// Writes headerless 16-bit PCM samples.
+///
+/// The samples are little-endian by default, see
+/// [`with_endianness()`](Raw::with_endianness).
+#[derive(Debug)]
+pub struct Raw<W: Write> {
+    output: Output<W>,
+    endianness: Endianness,
+}
+
+impl Raw<BufWriter<File>> {
+    /// Create a raw file at the given path.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Raw<W> {
+    /// Write to any writer, it doesn't need to be seekable.
+    pub fn new(writer: W) -> Self {
+        Self {
+            output: Output::streaming(writer),
+            endianness: Endianness::default(),
+        }
+    }
+    /// Set the byte order of the samples.
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+}
+
+impl<W: Write> Sink for Raw<W> {
+    type Output = W;
+
+    fn write_sector(&mut self, sector: &[i16]) -> Result<()> {
+        self.output.write(&self.endianness.encode(sector))?;
+        Ok(())
+    }
+    fn finish(self) -> Result<W> {
+        Ok(self.output.finish()?)
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::Path,
+};
+
+use super::{track_channels, Endianness, Output, Sink, SAMPLE_RATE, UNKNOWN_LENGTH};
+use crate::{CdBackend, Result, SECTOR_BYTES};
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+/// `KSDATAFORMAT_SUBTYPE_PCM`
+const SUBTYPE_PCM: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+/// Front left, front right, back left and back right.
+const QUADRO_CHANNEL_MASK: u32 = 0x33;
+
+/// Writes audio data as a RIFF/WAVE file.
+///
+/// The header is written before the first sector. If the writer is seekable
+/// (see [`Wav::new()`]), the chunk sizes are filled in by
+/// [`finish()`](Sink::finish). Otherwise (see [`Wav::streaming()`]),
+/// they are taken from the length passed to [`begin()`](Sink::begin) or set
+/// to `0xffffffff`, which most programs interpret as "read until the end of
+/// the stream".
+///
+/// Tracks with four channels are written as `WAVE_FORMAT_EXTENSIBLE`
+/// with a quadraphonic channel mask.
+#[derive(Debug)]
+pub struct Wav<W: Write> {
+    output: Output<W>,
+    channels: Option<u16>,
+    info: Vec<([u8; 4], String)>,
+    expected_sectors: Option<u64>,
+    /// Length of the header, once it has been written.
+    header_len: Option<u64>,
+}
+
+impl Wav<BufWriter<File>> {
+    /// Create a WAV file at the given path.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write + Seek> Wav<W> {
+    /// Write to a seekable writer, e.g. a [`File`].
+    pub fn new(writer: W) -> Self {
+        Self::from_output(Output::seekable(writer))
+    }
+}
+
+impl<W: Write> Wav<W> {
+    /// Write to a writer that isn't seekable, e.g. [`std::io::Stdout`].
+    pub fn streaming(writer: W) -> Self {
+        Self::from_output(Output::streaming(writer))
+    }
+    fn from_output(output: Output<W>) -> Self {
+        Self {
+            output,
+            channels: None,
+            info: Vec::new(),
+            expected_sectors: None,
+            header_len: None,
+        }
+    }
+    /// Set the number of channels.
+    ///
+    /// By default, [`write_all()`](Sink::write_all) uses the channel count of
+    /// the track that is being read and [`write_sector()`](Sink::write_sector)
+    /// assumes two channels.
+    pub fn with_channels(mut self, channels: u8) -> Self {
+        self.channels = Some(channels.into());
+        self
+    }
+    /// Add an entry to the `LIST/INFO` chunk, e.g. `INAM` (title),
+    /// `IART` (artist), `IPRD` (album), `ITRK` (track number),
+    /// `ICRD` (date), `IGNR` (genre) or `ICMT` (comment).
+    pub fn with_info(mut self, id: [u8; 4], value: impl Into<String>) -> Self {
+        self.info.push((id, value.into()));
+        self
+    }
+    fn write_header(&mut self) -> Result<()> {
+        let channels = self.channels.unwrap_or(2);
+        let block_align = 2 * channels;
+
+        let mut fmt = Vec::with_capacity(40);
+        let format = if channels > 2 {
+            WAVE_FORMAT_EXTENSIBLE
+        } else {
+            WAVE_FORMAT_PCM
+        };
+        fmt.extend(format.to_le_bytes());
+        fmt.extend(channels.to_le_bytes());
+        fmt.extend(SAMPLE_RATE.to_le_bytes());
+        fmt.extend((SAMPLE_RATE * u32::from(block_align)).to_le_bytes());
+        fmt.extend(block_align.to_le_bytes());
+        fmt.extend(16u16.to_le_bytes());
+        if format == WAVE_FORMAT_EXTENSIBLE {
+            fmt.extend(22u16.to_le_bytes());
+            fmt.extend(16u16.to_le_bytes());
+            fmt.extend(QUADRO_CHANNEL_MASK.to_le_bytes());
+            fmt.extend(SUBTYPE_PCM);
+        }
+
+        let mut header = Vec::new();
+        header.extend(b"RIFF");
+        header.extend(UNKNOWN_LENGTH.to_le_bytes());
+        header.extend(b"WAVE");
+        write_chunk(&mut header, b"fmt ", &fmt);
+        if !self.info.is_empty() {
+            let mut list = b"INFO".to_vec();
+            for (id, value) in &self.info {
+                let mut value = value.as_bytes().to_vec();
+                value.push(0);
+                write_chunk(&mut list, id, &value);
+            }
+            write_chunk(&mut header, b"LIST", &list);
+        }
+        header.extend(b"data");
+        header.extend(UNKNOWN_LENGTH.to_le_bytes());
+
+        if let Some(sectors) = self.expected_sectors {
+            let (riff_len, data_len) = sizes(header.len() as u64, sectors * SECTOR_BYTES as u64);
+            let data = header.len() - 4;
+            header[4..8].copy_from_slice(&riff_len.to_le_bytes());
+            header[data..].copy_from_slice(&data_len.to_le_bytes());
+        }
+
+        self.output.write(&header)?;
+        self.header_len = Some(header.len() as u64);
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for Wav<W> {
+    type Output = W;
+
+    fn begin<B: CdBackend + ?Sized>(
+        &mut self,
+        backend: &B,
+        first_lsn: u32,
+        last_lsn: u32,
+    ) -> Result<()> {
+        if self.channels.is_none() {
+            self.channels = track_channels(backend, first_lsn).map(Into::into);
+        }
+        self.expected_sectors =
+            (first_lsn <= last_lsn).then(|| u64::from(last_lsn - first_lsn) + 1);
+        Ok(())
+    }
+    fn write_sector(&mut self, sector: &[i16]) -> Result<()> {
+        if self.header_len.is_none() {
+            self.write_header()?;
+        }
+        self.output.write(&Endianness::Little.encode(sector))?;
+        Ok(())
+    }
+    /// Fill in the chunk sizes if possible, flush and return the writer.
+    fn finish(mut self) -> Result<W> {
+        if self.header_len.is_none() {
+            self.write_header()?;
+        }
+        let header_len = self.header_len.unwrap();
+
+        let (riff_len, data_len) = sizes(header_len, self.output.written - header_len);
+        self.output.patch(4, &riff_len.to_le_bytes())?;
+        self.output.patch(header_len - 4, &data_len.to_le_bytes())?;
+
+        Ok(self.output.finish()?)
+    }
+}
+
+/// Get the `RIFF` and `data` chunk sizes.
+fn sizes(header_len: u64, data_len: u64) -> (u32, u32) {
+    let convert = |len: u64| u32::try_from(len).unwrap_or(UNKNOWN_LENGTH);
+    (convert(header_len + data_len - 8), convert(data_len))
+}
+
+/// Append a chunk including its padding byte.
+fn write_chunk(buf: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    buf.extend(id);
+    buf.extend((data.len() as u32).to_le_bytes());
+    buf.extend(data);
+    if data.len() % 2 == 1 {
+        buf.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS};
+
+    /// Split a file into its chunks, taking the rest of the file for chunks
+    /// of unknown length.
+    fn chunks(wav: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut chunks = Vec::new();
+        let mut rest = &wav[12..];
+        while !rest.is_empty() {
+            let len = u32::from_le_bytes(rest[4..8].try_into().unwrap());
+            let len = if len == UNKNOWN_LENGTH {
+                rest.len() - 8
+            } else {
+                len as usize
+            };
+            chunks.push((&rest[..4], &rest[8..8 + len]));
+            rest = &rest[(8 + len + len % 2).min(rest.len())..];
+        }
+        chunks
+    }
+
+    fn u16_at(bytes: &[u8], position: usize) -> u16 {
+        u16::from_le_bytes(bytes[position..position + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], position: usize) -> u32 {
+        u32::from_le_bytes(bytes[position..position + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn four_channels() {
+        let samples: Vec<i16> = (0..3 * SECTOR_WORDS as i16).collect();
+        let disc = VirtualDisc::new([VirtualTrack::new(samples.clone()).with_channels(4)]).unwrap();
+        let mut paranoia = Paranoia::new(disc);
+        let wav = Wav::new(Cursor::new(Vec::new()))
+            .write_all(paranoia.read_track(1).unwrap())
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32_at(&wav, 4) as usize, wav.len() - 8);
+        assert_eq!(&wav[8..12], b"WAVE");
+        let chunks = chunks(&wav);
+        let [(b"fmt ", fmt), (b"data", data)] = chunks[..] else {
+            panic!("unexpected chunks");
+        };
+        assert_eq!(fmt.len(), 40);
+        assert_eq!(u16_at(fmt, 0), WAVE_FORMAT_EXTENSIBLE);
+        assert_eq!(u16_at(fmt, 2), 4);
+        assert_eq!(u32_at(fmt, 4), SAMPLE_RATE);
+        assert_eq!(u32_at(fmt, 8), SAMPLE_RATE * 8);
+        assert_eq!(u16_at(fmt, 12), 8);
+        assert_eq!(u16_at(fmt, 14), 16);
+        assert_eq!(u16_at(fmt, 16), 22);
+        assert_eq!(u16_at(fmt, 18), 16);
+        assert_eq!(u32_at(fmt, 20), QUADRO_CHANNEL_MASK);
+        assert_eq!(fmt[24..], SUBTYPE_PCM);
+        assert_eq!(data, Endianness::Little.encode(&samples));
+    }
+
+    #[test]
+    fn streaming_with_unknown_length() {
+        let mut wav = Wav::streaming(Vec::new()).with_info(*b"INAM", "odd");
+        wav.write_sector(&[1; SECTOR_WORDS]).unwrap();
+        wav.write_sector(&[2; SECTOR_WORDS]).unwrap();
+        let wav = wav.finish().unwrap();
+
+        assert_eq!(u32_at(&wav, 4), UNKNOWN_LENGTH);
+        let chunks = chunks(&wav);
+        let [(b"fmt ", fmt), (b"LIST", list), (b"data", data)] = chunks[..] else {
+            panic!("unexpected chunks");
+        };
+        assert_eq!(u16_at(fmt, 0), WAVE_FORMAT_PCM);
+        assert_eq!(u16_at(fmt, 2), 2);
+        assert_eq!(fmt.len(), 16);
+        // the value is terminated and padded to an even length
+        assert_eq!(list, b"INFOINAM\x04\x00\x00\x00odd\x00");
+        assert_eq!(u32_at(&wav, wav.len() - data.len() - 4), UNKNOWN_LENGTH);
+        assert_eq!(data.len(), 2 * SECTOR_BYTES);
+    }
+
+    #[test]
+    fn streaming_with_known_length() {
+        let disc = VirtualDisc::new([VirtualTrack::new(vec![3; 5 * SECTOR_WORDS])]).unwrap();
+        let mut paranoia = Paranoia::new(disc);
+        let wav = Wav::streaming(Vec::new())
+            .write_all(paranoia.read_track(1).unwrap())
+            .unwrap();
+
+        assert_eq!(u32_at(&wav, 4) as usize, wav.len() - 8);
+        assert_eq!(wav.len(), 44 + 5 * SECTOR_BYTES);
+        assert_eq!(u32_at(&wav, 40) as usize, 5 * SECTOR_BYTES);
+    }
+
+    #[test]
+    fn seekable_sizes_are_filled_in() {
+        let mut wav = Wav::new(Cursor::new(Vec::new())).with_channels(1);
+        wav.write_sector(&[1; SECTOR_WORDS]).unwrap();
+        let wav = wav.finish().unwrap().into_inner();
+
+        assert_eq!(u32_at(&wav, 4) as usize, wav.len() - 8);
+        assert_eq!(u16_at(&wav, 22), 1);
+        assert_eq!(u32_at(&wav, 28), SAMPLE_RATE * 2);
+        assert_eq!(u32_at(&wav, 40) as usize, SECTOR_BYTES);
+
+        // a file without any audio data is still complete
+        let wav = Wav::new(Cursor::new(Vec::new()))
+            .finish()
+            .unwrap()
+            .into_inner();
+        assert_eq!(wav.len(), 44);
+        assert_eq!(u32_at(&wav, 4), 36);
+        assert_eq!(u32_at(&wav, 40), 0);
+    }
+}
@@ -0,0 +1,458 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Parsing of cdparanoia span arguments.
+//!
+//! A span selects a range of sectors using tracks and times relative to the
+//! start of a track, e.g. `1[0:30.12]-3[1:00]`. Each position consists of a
+//! track number, a time in brackets or both. Times have the format
+//! `[hh:mm:ss.ff]`, where `ff` are sectors (1/75 s) and fields that are left
+//! out count as zero, so `[20]`, `[:20]` and `[20.]` are twenty seconds,
+//! `[10:]` is ten minutes and `[.30]` is thirty sectors.
+//!
+//! - `1` is track 1.
+//! - `1[20.35]` starts 20 seconds and 35 sectors into track 1 and ends at the
+//!   end of track 1.
+//! - `1[20.35]-` continues to the end of the disc.
+//! - `-2` starts at the beginning of the disc and ends with track 2.
+//! - `-2[30.35]` ends 30 seconds and 35 sectors into track 2 (inclusive).
+//! - `2-4` are tracks 2 to 4.
+//! - `2[1:00]-[2:00]` ends in the same track it starts in.
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{span::Span, Paranoia, VirtualDisc, VirtualTrack, SECTOR_WORDS};
+//!
+//! let disc = VirtualDisc::new([
+//!     VirtualTrack::new(vec![0; 750 * SECTOR_WORDS]),
+//!     VirtualTrack::new(vec![0; 750 * SECTOR_WORDS]),
+//! ])?;
+//!
+//! let span: Span = "1[0:05.10]-2[2]".parse()?;
+//! assert_eq!(span.resolve(&disc)?, 385..=900);
+//!
+//! let mut paranoia = Paranoia::new(disc);
+//! let reader = paranoia.read_span("2-")?;
+//! assert_eq!((reader.current_lsn(), reader.last_lsn()), (750, 1499));
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::{ops::RangeInclusive, str::FromStr};
+
+use crate::{CdBackend, Result};
+
+/// Number of sectors per second of audio.
+const SECTORS_PER_SECOND: u32 = 75;
+
+/// A parsed span that can be resolved against the table of contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// The first position, or `None` for the start of the disc.
+    pub start: Option<Position>,
+    pub end: SpanEnd,
+}
+
+/// Where a [`Span`] ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpanEnd {
+    /// At the end of the track the span starts in, e.g. `1` or `1[20]`.
+    Track,
+    /// At the end of the disc, e.g. `1-`.
+    Disc,
+    /// At the given position, e.g. `1-3` or `1-3[1:00]`.
+    Position(Position),
+}
+
+/// A track, a time relative to the start of a track or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    /// The track number, or `None` to use the start of the disc
+    /// (for the start of a span) or the track the span starts in
+    /// (for the end of a span).
+    pub track: Option<u8>,
+    /// The time in sectors, or `None` for a whole track.
+    pub offset: Option<u32>,
+}
+
+/// An error from parsing or resolving a [`Span`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SpanError {
+    #[error("the span is empty")]
+    Empty,
+    #[error("unexpected {found:?} at position {position} of the span, expected {expected}")]
+    Unexpected {
+        position: usize,
+        found: char,
+        expected: &'static str,
+    },
+    #[error("unexpected end of the span, expected {expected}")]
+    UnexpectedEnd { expected: &'static str },
+    #[error("the number at position {position} of the span is too large")]
+    NumberTooLarge { position: usize },
+    #[error("track {0} doesn't exist")]
+    InvalidTrack(u8),
+    #[error("track {0} is not an audio track")]
+    NotAudio(u8),
+    #[error("sector {0} is outside of the audio tracks of the disc")]
+    OutOfRange(u64),
+    #[error("the span ends at sector {end} before it starts at sector {start}")]
+    EndsBeforeStart { start: u32, end: u32 },
+}
+
+impl FromStr for Span {
+    type Err = SpanError;
+
+    fn from_str(span: &str) -> std::result::Result<Self, SpanError> {
+        let mut parser = Parser {
+            chars: span.char_indices().peekable(),
+        };
+        parser.skip_whitespace();
+        if parser.peek().is_none() {
+            return Err(SpanError::Empty);
+        }
+
+        let start = parser.position()?;
+        parser.skip_whitespace();
+        let end = if parser.eat('-') {
+            parser.skip_whitespace();
+            match parser.position()? {
+                Some(position) => SpanEnd::Position(position),
+                None => SpanEnd::Disc,
+            }
+        } else if start.is_some() {
+            SpanEnd::Track
+        } else {
+            return Err(parser.unexpected("a track, a time or '-'"));
+        };
+        parser.skip_whitespace();
+        if parser.peek().is_some() {
+            return Err(parser.unexpected("the end of the span"));
+        }
+
+        Ok(Span { start, end })
+    }
+}
+
+impl Span {
+    /// Get the inclusive range of logical sector numbers selected by the span.
+    pub fn resolve<B: CdBackend + ?Sized>(&self, backend: &B) -> Result<RangeInclusive<u32>> {
+        let disc_first = backend.disc_first_sector()?;
+        let disc_last = backend.disc_last_sector()?;
+        let first_track = backend.sector_track(disc_first)?;
+
+        let start_track = match self.start.and_then(|start| start.track) {
+            Some(track) => checked_track(backend, track)?,
+            None => first_track,
+        };
+        let track_first = backend.track_first_sector(start_track)?;
+        let start = match self.start {
+            None
+            | Some(Position {
+                track: None,
+                offset: None,
+            }) => u64::from(disc_first),
+            Some(Position { offset, .. }) => {
+                u64::from(track_first) + u64::from(offset.unwrap_or_default())
+            }
+        };
+
+        let end = match self.end {
+            SpanEnd::Track => u64::from(backend.track_last_sector(start_track)?),
+            SpanEnd::Disc => u64::from(disc_last),
+            SpanEnd::Position(Position { track, offset }) => {
+                let track = match track {
+                    Some(track) => checked_track(backend, track)?,
+                    None => start_track,
+                };
+                match offset {
+                    Some(offset) => {
+                        u64::from(backend.track_first_sector(track)?) + u64::from(offset)
+                    }
+                    None => u64::from(backend.track_last_sector(track)?),
+                }
+            }
+        };
+
+        for lsn in [start, end] {
+            if lsn < u64::from(disc_first) || lsn > u64::from(disc_last) {
+                return Err(SpanError::OutOfRange(lsn).into());
+            }
+        }
+        let (start, end) = (start as u32, end as u32);
+        if end < start {
+            return Err(SpanError::EndsBeforeStart { start, end }.into());
+        }
+        Ok(start..=end)
+    }
+}
+
+fn checked_track<B: CdBackend + ?Sized>(backend: &B, track: u8) -> Result<u8> {
+    if track == 0 || track > backend.tracks() {
+        return Err(SpanError::InvalidTrack(track).into());
+    }
+    if !backend.track_audio(track) {
+        return Err(SpanError::NotAudio(track).into());
+    }
+    Ok(track)
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+    fn eat(&mut self, expected: char) -> bool {
+        self.chars.next_if(|&(_, c)| c == expected).is_some()
+    }
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+    fn unexpected(&mut self, expected: &'static str) -> SpanError {
+        match self.chars.peek() {
+            Some(&(position, found)) => SpanError::Unexpected {
+                position,
+                found,
+                expected,
+            },
+            None => SpanError::UnexpectedEnd { expected },
+        }
+    }
+    /// Parse an optional number.
+    fn number(&mut self) -> std::result::Result<Option<u32>, SpanError> {
+        let Some(&(position, _)) = self.chars.peek() else {
+            return Ok(None);
+        };
+        let mut number: Option<u32> = None;
+        while let Some((_, digit)) = self.chars.next_if(|(_, c)| c.is_ascii_digit()) {
+            number = number
+                .unwrap_or_default()
+                .checked_mul(10)
+                .and_then(|number| number.checked_add(digit.to_digit(10).unwrap()))
+                .map(Some)
+                .ok_or(SpanError::NumberTooLarge { position })?;
+        }
+        Ok(number)
+    }
+    /// Parse an optional position, e.g. `1`, `1[2:00]` or `[2:00]`.
+    fn position(&mut self) -> std::result::Result<Option<Position>, SpanError> {
+        let start = self.chars.peek().map(|&(position, _)| position);
+        let track = match self.number()? {
+            Some(track) => Some(u8::try_from(track).map_err(|_| SpanError::NumberTooLarge {
+                position: start.unwrap_or_default(),
+            })?),
+            None => None,
+        };
+        if track.is_some() && self.eat(':') && self.peek() != Some('[') {
+            return Err(self.unexpected("'['"));
+        }
+        let offset = if self.eat('[') {
+            let offset = self.time()?;
+            if !self.eat(']') {
+                return Err(self.unexpected("']'"));
+            }
+            Some(offset)
+        } else {
+            None
+        };
+        Ok((track.is_some() || offset.is_some()).then_some(Position { track, offset }))
+    }
+    /// Parse the contents of `[hh:mm:ss.ff]` as a number of sectors.
+    fn time(&mut self) -> std::result::Result<u32, SpanError> {
+        let start = self.chars.peek().map(|&(position, _)| position);
+        let mut fields = vec![self.number()?.unwrap_or_default()];
+        while fields.len() < 3 && self.eat(':') {
+            fields.push(self.number()?.unwrap_or_default());
+        }
+        let sectors = if self.eat('.') {
+            self.number()?.unwrap_or_default()
+        } else {
+            0
+        };
+
+        let seconds = fields.iter().try_fold(0u32, |total, &field| {
+            total.checked_mul(60)?.checked_add(field)
+        });
+        seconds
+            .and_then(|seconds| seconds.checked_mul(SECTORS_PER_SECOND))
+            .and_then(|total| total.checked_add(sectors))
+            .ok_or(SpanError::NumberTooLarge {
+                position: start.unwrap_or_default(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, VirtualDisc, VirtualTrack, SECTOR_WORDS};
+
+    /// Two audio tracks of 10 seconds at LSN 150 and 900, followed by a
+    /// data track.
+    fn disc() -> VirtualDisc {
+        VirtualDisc::new([
+            VirtualTrack::new(vec![0; 750 * SECTOR_WORDS]),
+            VirtualTrack::new(vec![0; 750 * SECTOR_WORDS]),
+            VirtualTrack::new(vec![0; 100 * SECTOR_WORDS]).with_audio(false),
+        ])
+        .unwrap()
+        .with_first_sector(150)
+    }
+
+    fn resolve(span: &str) -> std::result::Result<RangeInclusive<u32>, SpanError> {
+        match span.parse::<Span>()?.resolve(&disc()) {
+            Ok(range) => Ok(range),
+            Err(Error::Span(err)) => Err(err),
+            Err(err) => panic!("unexpected error {err}"),
+        }
+    }
+
+    fn position(track: Option<u8>, offset: Option<u32>) -> Position {
+        Position { track, offset }
+    }
+
+    #[test]
+    fn tracks() {
+        assert_eq!(resolve("1"), Ok(150..=899));
+        assert_eq!(resolve(" 2 "), Ok(900..=1649));
+        assert_eq!(resolve("1-2"), Ok(150..=1649));
+        assert_eq!(resolve("2-2"), Ok(900..=1649));
+    }
+
+    #[test]
+    fn open_ended() {
+        assert_eq!(resolve("1-"), Ok(150..=1649));
+        assert_eq!(resolve("2-"), Ok(900..=1649));
+        assert_eq!(resolve("1[5]-"), Ok(525..=1649));
+        // `cdparanoia -- -2`
+        assert_eq!(resolve("-2"), Ok(150..=1649));
+        assert_eq!(resolve("-1"), Ok(150..=899));
+        assert_eq!(resolve("-2[1.5]"), Ok(150..=980));
+        assert_eq!(resolve("-[1]"), Ok(150..=225));
+        assert_eq!(
+            "-".parse(),
+            Ok(Span {
+                start: None,
+                end: SpanEnd::Disc,
+            })
+        );
+        assert_eq!(resolve("-"), Ok(150..=1649));
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(
+            "1[1:02:03.04]".parse(),
+            Ok(Span {
+                start: Some(position(Some(1), Some(3723 * 75 + 4))),
+                end: SpanEnd::Track,
+            })
+        );
+        for (time, sectors) in [
+            ("[20]", 1500),
+            ("[:20]", 1500),
+            ("[20.]", 1500),
+            ("[10:]", 45000),
+            ("[.30]", 30),
+            ("[1:00.74]", 4574),
+            ("[]", 0),
+        ] {
+            let span: Span = time.parse().unwrap();
+            assert_eq!(span.start, Some(position(None, Some(sectors))), "{time}");
+        }
+
+        // frames are sectors relative to the start of the track
+        assert_eq!(resolve("1[.5]"), Ok(155..=899));
+        assert_eq!(resolve("2[2.74]"), Ok(1124..=1649));
+        assert_eq!(resolve("1[1.5]-2[.10]"), Ok(230..=910));
+        assert_eq!(resolve("2[1]-[2]"), Ok(975..=1050));
+        assert_eq!(resolve("1:[1]"), Ok(225..=899));
+        // a time may reach into the next track
+        assert_eq!(resolve("1[9.74]-[12]"), Ok(899..=1050));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!("".parse::<Span>(), Err(SpanError::Empty));
+        assert_eq!("  ".parse::<Span>(), Err(SpanError::Empty));
+        for (span, position, found, expected) in [
+            ("x", 0, 'x', "a track, a time or '-'"),
+            ("1x", 1, 'x', "the end of the span"),
+            ("1-2-3", 3, '-', "the end of the span"),
+            ("1[2:3:4:5]", 7, ':', "']'"),
+            ("1[2,3]", 3, ',', "']'"),
+            ("1:2", 2, '2', "'['"),
+            ("--2", 1, '-', "the end of the span"),
+        ] {
+            assert_eq!(
+                span.parse::<Span>(),
+                Err(SpanError::Unexpected {
+                    position,
+                    found,
+                    expected,
+                }),
+                "{span}"
+            );
+        }
+        for (span, expected) in [("1[2", "']'"), ("1:", "'['")] {
+            assert_eq!(
+                span.parse::<Span>(),
+                Err(SpanError::UnexpectedEnd { expected }),
+                "{span}"
+            );
+        }
+    }
+
+    #[test]
+    fn numbers_too_large() {
+        for (span, position) in [
+            ("256", 0),
+            ("1-300", 2),
+            ("1[4294967296]", 2),
+            ("1[.4294967296]", 3),
+            ("1[57266231]", 2),
+            ("1[1193047:]", 2),
+            ("1[57266230.46]", 2),
+        ] {
+            assert_eq!(
+                span.parse::<Span>(),
+                Err(SpanError::NumberTooLarge { position }),
+                "{span}"
+            );
+        }
+        assert!("255".parse::<Span>().is_ok());
+        assert!("1[57266230.45]".parse::<Span>().is_ok());
+    }
+
+    #[test]
+    fn resolve_errors() {
+        assert_eq!(resolve("0"), Err(SpanError::InvalidTrack(0)));
+        assert_eq!(resolve("4"), Err(SpanError::InvalidTrack(4)));
+        assert_eq!(resolve("1-4"), Err(SpanError::InvalidTrack(4)));
+        assert_eq!(resolve("3"), Err(SpanError::NotAudio(3)));
+        assert_eq!(resolve("1-3"), Err(SpanError::NotAudio(3)));
+        assert_eq!(resolve("2[10]"), Err(SpanError::OutOfRange(1650)));
+        assert_eq!(resolve("1-2[10]"), Err(SpanError::OutOfRange(1650)));
+        assert_eq!(
+            resolve("1[57266230.45]"),
+            Err(SpanError::OutOfRange(150 + u64::from(u32::MAX)))
+        );
+        assert_eq!(
+            resolve("2-1"),
+            Err(SpanError::EndsBeforeStart {
+                start: 900,
+                end: 899,
+            })
+        );
+        assert_eq!(
+            resolve("1[2]-[1]"),
+            Err(SpanError::EndsBeforeStart {
+                start: 300,
+                end: 225,
+            })
+        );
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Fixtures that are shared by the unit tests.
+
+use crate::{CdBackend, Result, VirtualDisc, SECTOR_WORDS};
+
+/// Get the samples of `sectors` sectors of noise, so data that is read at a
+/// wrong position never matches by accident.
+pub(crate) fn samples(sectors: usize) -> Vec<i16> {
+    (0..sectors * SECTOR_WORDS)
+        .map(|i| (i * 7919 % 65521) as i16)
+        .collect()
+}
+
+/// Counts the sectors that have been read from another backend.
+///
+/// With a limit, it panics once more sectors are read, like a rip that is
+/// killed without getting the chance to clean up.
+#[derive(Debug, Clone)]
+pub(crate) struct CountingDisc<B = VirtualDisc> {
+    inner: B,
+    /// The number of sectors that have been read.
+    pub(crate) sectors: usize,
+    limit: usize,
+}
+
+impl<B: CdBackend> CountingDisc<B> {
+    pub(crate) fn new(inner: B) -> Self {
+        Self {
+            inner,
+            sectors: 0,
+            limit: usize::MAX,
+        }
+    }
+    pub(crate) fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+impl<B: CdBackend> CdBackend for CountingDisc<B> {
+    fn tracks(&self) -> u8 {
+        self.inner.tracks()
+    }
+    fn track_first_sector(&self, track: u8) -> Result<u32> {
+        self.inner.track_first_sector(track)
+    }
+    fn track_last_sector(&self, track: u8) -> Result<u32> {
+        self.inner.track_last_sector(track)
+    }
+    fn track_channels(&self, track: u8) -> Option<u8> {
+        self.inner.track_channels(track)
+    }
+    fn track_audio(&self, track: u8) -> bool {
+        self.inner.track_audio(track)
+    }
+    fn track_copy_permitted(&self, track: u8) -> bool {
+        self.inner.track_copy_permitted(track)
+    }
+    fn track_linear_preemphasis(&self, track: u8) -> bool {
+        self.inner.track_linear_preemphasis(track)
+    }
+    fn disc_mcn(&self) -> Option<String> {
+        self.inner.disc_mcn()
+    }
+    fn track_isrc(&self, track: u8) -> Option<String> {
+        self.inner.track_isrc(track)
+    }
+    fn track_pregap_sector(&self, track: u8) -> Option<u32> {
+        self.inner.track_pregap_sector(track)
+    }
+    fn read_raw(&mut self, first_lsn: u32, buf: &mut [i16]) -> Result<usize> {
+        let sectors = self.inner.read_raw(first_lsn, buf)?;
+        self.sectors += sectors;
+        assert!(self.sectors <= self.limit, "killed");
+        Ok(sectors)
+    }
+}
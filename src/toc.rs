@@ -0,0 +1,286 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! The table of contents of a disc, as printed by `cdparanoia -Q`.
+//!
+//! [`Toc`] collects everything a [`CdBackend`] knows about the layout of a
+//! disc. Its [`Display`] implementation prints the classic table of
+//! cdparanoia and [`Toc::to_json()`] returns the same data as JSON.
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{toc::Toc, VirtualDisc, VirtualTrack, SECTOR_WORDS};
+//!
+//! let disc = VirtualDisc::new([
+//!     VirtualTrack::new(vec![0; 16503 * SECTOR_WORDS]),
+//!     VirtualTrack::new(vec![0; 75 * SECTOR_WORDS]).with_preemphasis(true),
+//! ])?;
+//! let toc = Toc::read(&disc)?;
+//!
+//! assert_eq!(
+//!     toc.to_string(),
+//!     "\
+//! Table of contents (audio tracks only):
+//! track        length               begin        copy pre ch
+//! ===========================================================
+//!   1.    16503 [03:40.03]        0 [00:00.00]    no   no  2
+//!   2.       75 [00:01.00]    16503 [03:40.03]    no  yes  2
+//! TOTAL   16578 [03:41.03]    (audio only)
+//! "
+//! );
+//! assert!(toc.to_json().contains(r#""length": "03:40.03""#));
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::fmt::{self, Display};
+
+use serde_json::{json, Value};
+
+use crate::{CdBackend, ParanoiaError, Result};
+
+/// Number of sectors per second of audio.
+const SECTORS_PER_SECOND: u32 = 75;
+
+/// The table of contents of a disc.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Toc {
+    /// The media catalog number (UPC/EAN), if available.
+    pub mcn: Option<String>,
+    /// All tracks, including data tracks.
+    pub tracks: Vec<TocEntry>,
+}
+
+/// A track in the [`Toc`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TocEntry {
+    pub number: u8,
+    pub first_sector: u32,
+    pub last_sector: u32,
+    pub audio: bool,
+    pub copy_permitted: bool,
+    pub preemphasis: bool,
+    pub channels: Option<u8>,
+    pub isrc: Option<String>,
+    /// The first sector of the pregap (index 0), if known.
+    pub pregap_sector: Option<u32>,
+}
+
+impl Toc {
+    /// Read the table of contents from a [`Drive`](crate::Drive) or any
+    /// other backend.
+    ///
+    /// Fails with [`ParanoiaError::IllegalToc`] if a track ends before it
+    /// starts.
+    pub fn read<B: CdBackend + ?Sized>(backend: &B) -> Result<Self> {
+        let tracks = (1..=backend.tracks())
+            .map(|number| {
+                let first_sector = backend.track_first_sector(number)?;
+                let last_sector = backend.track_last_sector(number)?;
+                if last_sector < first_sector {
+                    return Err(ParanoiaError::IllegalToc.into());
+                }
+                Ok(TocEntry {
+                    number,
+                    first_sector,
+                    last_sector,
+                    audio: backend.track_audio(number),
+                    copy_permitted: backend.track_copy_permitted(number),
+                    preemphasis: backend.track_linear_preemphasis(number),
+                    channels: backend.track_channels(number),
+                    isrc: backend.track_isrc(number),
+                    pregap_sector: backend.track_pregap_sector(number),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            mcn: backend.disc_mcn(),
+            tracks,
+        })
+    }
+    /// Iterate over the audio tracks.
+    pub fn audio_tracks(&self) -> impl Iterator<Item = &TocEntry> {
+        self.tracks.iter().filter(|track| track.audio)
+    }
+    /// Get the total number of sectors in audio tracks.
+    pub fn audio_sectors(&self) -> u32 {
+        self.audio_tracks()
+            .map(TocEntry::sectors)
+            .fold(0, u32::saturating_add)
+    }
+    /// Get the table of contents as a JSON document.
+    ///
+    /// Times are formatted as `mm:ss.ff` like in the table, where `ff` are
+    /// sectors. Missing values are `null`.
+    pub fn to_json(&self) -> String {
+        format!("{:#}\n", self.to_value())
+    }
+    pub(crate) fn to_value(&self) -> Value {
+        let tracks: Vec<Value> = self
+            .tracks
+            .iter()
+            .map(|track| {
+                json!({
+                    "number": track.number,
+                    "audio": track.audio,
+                    "first_sector": track.first_sector,
+                    "last_sector": track.last_sector,
+                    "sectors": track.sectors(),
+                    "begin": msf(track.first_sector),
+                    "length": msf(track.sectors()),
+                    "copy_permitted": track.copy_permitted,
+                    "preemphasis": track.preemphasis,
+                    "channels": track.channels,
+                    "isrc": track.isrc,
+                    "pregap_sector": track.pregap_sector,
+                })
+            })
+            .collect();
+
+        json!({
+            "mcn": self.mcn,
+            "tracks": tracks,
+            "lead_out_sector": self
+                .tracks
+                .last()
+                .and_then(|track| track.last_sector.checked_add(1)),
+            "audio_sectors": self.audio_sectors(),
+            "audio_length": msf(self.audio_sectors()),
+        })
+    }
+}
+
+impl Display for Toc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Table of contents (audio tracks only):")?;
+        writeln!(
+            f,
+            "track        length               begin        copy pre ch"
+        )?;
+        writeln!(
+            f,
+            "==========================================================="
+        )?;
+        for track in self.audio_tracks() {
+            writeln!(
+                f,
+                "{:3}.  {:7} [{}]  {:7} [{}]  {} {} {}",
+                track.number,
+                track.sectors(),
+                msf(track.sectors()),
+                track.first_sector,
+                msf(track.first_sector),
+                if track.copy_permitted { "  OK" } else { "  no" },
+                if track.preemphasis { " yes" } else { "  no" },
+                if track.channels == Some(4) {
+                    " 4"
+                } else {
+                    " 2"
+                },
+            )?;
+        }
+        writeln!(
+            f,
+            "TOTAL {:7} [{}]    (audio only)",
+            self.audio_sectors(),
+            msf(self.audio_sectors())
+        )
+    }
+}
+
+impl TocEntry {
+    /// Get the number of sectors in the track.
+    ///
+    /// Returns 0 if the last sector is before the first one and saturates
+    /// at [`u32::MAX`].
+    pub fn sectors(&self) -> u32 {
+        self.last_sector
+            .checked_sub(self.first_sector)
+            .map_or(0, |sectors| sectors.saturating_add(1))
+    }
+}
+
+/// Format a number of sectors as `mm:ss.ff`.
+pub(crate) fn msf(sectors: u32) -> String {
+    let seconds = sectors / SECTORS_PER_SECOND;
+    format!(
+        "{:02}:{:02}.{:02}",
+        seconds / 60,
+        seconds % 60,
+        sectors % SECTORS_PER_SECOND
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn entry(first_sector: u32, last_sector: u32) -> TocEntry {
+        TocEntry {
+            number: 1,
+            first_sector,
+            last_sector,
+            audio: true,
+            copy_permitted: false,
+            preemphasis: false,
+            channels: Some(2),
+            isrc: None,
+            pregap_sector: None,
+        }
+    }
+
+    /// A drive that reports the last sector of each track before its first.
+    struct InvertedToc;
+
+    impl CdBackend for InvertedToc {
+        fn tracks(&self) -> u8 {
+            1
+        }
+        fn track_first_sector(&self, _track: u8) -> Result<u32> {
+            Ok(100)
+        }
+        fn track_last_sector(&self, _track: u8) -> Result<u32> {
+            Ok(50)
+        }
+        fn track_channels(&self, _track: u8) -> Option<u8> {
+            Some(2)
+        }
+        fn track_audio(&self, _track: u8) -> bool {
+            true
+        }
+        fn track_copy_permitted(&self, _track: u8) -> bool {
+            false
+        }
+        fn track_linear_preemphasis(&self, _track: u8) -> bool {
+            false
+        }
+        fn read_raw(&mut self, _first_lsn: u32, _buf: &mut [i16]) -> Result<usize> {
+            Err(ParanoiaError::UnaddressableSector.into())
+        }
+    }
+
+    #[test]
+    fn sectors_dont_overflow() {
+        assert_eq!(entry(150, 150).sectors(), 1);
+        assert_eq!(entry(0, u32::MAX).sectors(), u32::MAX);
+        assert_eq!(entry(100, 50).sectors(), 0);
+
+        let toc = Toc {
+            mcn: None,
+            tracks: vec![entry(0, u32::MAX - 1), entry(u32::MAX, u32::MAX)],
+        };
+        assert_eq!(toc.audio_sectors(), u32::MAX);
+        assert!(toc.to_json().contains(r#""lead_out_sector": null"#));
+        assert!(toc.to_string().contains("TOTAL 4294967295"));
+    }
+
+    #[test]
+    fn read_rejects_tracks_that_end_before_they_start() {
+        assert!(matches!(
+            Toc::read(&InvertedToc),
+            Err(Error::Paranoia(ParanoiaError::IllegalToc))
+        ));
+    }
+}
@@ -0,0 +1,325 @@
+// Copyright (c) 2023 d-k-bo
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Consensus of several rips of a damaged disc by majority vote.
+//!
+//! Rips of the same sectors, made in several passes or on several drives,
+//! often fail in different places. [`vote()`] aligns [`Source`]s by their
+//! first sector and read offset and takes the version that more than half
+//! of them agree on, either per sector or per sample (see [`Granularity`]).
+//! Sectors without a majority are reported in [`Consensus::disputed`].
+//!
+//! # Example
+//!
+//! ```
+//! use cdparanoia::{
+//!     fault::{Fault, FaultyDisc},
+//!     vote::{self, Granularity, Source},
+//!     Paranoia, ParanoiaMode, VirtualDisc, VirtualTrack, SECTOR_WORDS,
+//! };
+//!
+//! let samples: Vec<i16> = (0..20 * SECTOR_WORDS as i32)
+//!     .map(|i| (i * 7919 % 65521) as i16)
+//!     .collect();
+//! let disc = VirtualDisc::new([VirtualTrack::new(samples.clone())])?;
+//!
+//! // three rips that are damaged in different places
+//! let mut sources = Vec::new();
+//! for damaged in [2..=4, 8..=9, 6..=6] {
+//!     let disc = FaultyDisc::new(disc.clone(), 42).with_fault(Fault::Unstable {
+//!         sectors: damaged,
+//!         failures: 1,
+//!     });
+//!     let mut paranoia = Paranoia::new(disc);
+//!     paranoia.set_mode(ParanoiaMode::DISABLE);
+//!     sources.push(Source::read(paranoia.read_track(1)?)?);
+//! }
+//!
+//! let consensus = vote::vote(&sources, 0, 19, Granularity::Sector);
+//! assert!(consensus.disputed.is_empty());
+//! assert_eq!(consensus.samples, samples);
+//! # Ok::<(), cdparanoia::Error>(())
+//! ```
+
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use crate::{CdBackend, DiscReader, Result, SECTOR_WORDS};
+
+/// A rip that takes part in a [`vote()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    first_lsn: u32,
+    offset: i32,
+    samples: Vec<i16>,
+}
+
+/// What a [`vote()`] decides on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Granularity {
+    /// Whole sectors, so every sector of the consensus comes from a single
+    /// rip.
+    #[default]
+    Sector,
+    /// Single samples, which can combine rips that are damaged in the same
+    /// sector.
+    Sample,
+}
+
+/// The result of a [`vote()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Consensus {
+    pub first_lsn: u32,
+    /// The audio data of all sectors.
+    ///
+    /// Where there is no majority, the version of most sources is used
+    /// (the first one on a tie), or silence if no source has the data.
+    pub samples: Vec<i16>,
+    /// Sectors that contain data without a majority.
+    pub disputed: Vec<RangeInclusive<u32>>,
+    /// The number of samples without a majority.
+    pub disputed_samples: usize,
+}
+
+impl Source {
+    /// Create a source from interleaved samples starting at `first_lsn`.
+    pub fn new(first_lsn: u32, samples: Vec<i16>) -> Self {
+        Self {
+            first_lsn,
+            offset: 0,
+            samples,
+        }
+    }
+    /// Read all remaining sectors of a [`DiscReader`].
+    pub fn read<B: CdBackend>(reader: DiscReader<'_, B>) -> Result<Self> {
+        let first_lsn = reader.current_lsn();
+        let mut samples = Vec::new();
+        for sector in reader {
+            samples.extend(sector?);
+        }
+        Ok(Self::new(first_lsn, samples))
+    }
+    /// Read a raw rip with little-endian samples, like the output of
+    /// [`Raw`](crate::sink::Raw), that starts at `first_lsn`.
+    pub fn read_raw(first_lsn: u32, mut reader: impl Read) -> Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let samples = bytes
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        Ok(Self::new(first_lsn, samples))
+    }
+    /// Open a raw rip, see [`read_raw()`](Self::read_raw).
+    pub fn open_raw(first_lsn: u32, path: impl AsRef<Path>) -> Result<Self> {
+        Self::read_raw(first_lsn, BufReader::new(File::open(path)?))
+    }
+    /// Set the read offset correction of the drive the rip was made with,
+    /// in stereo samples like in EAC, if it hasn't been applied yet.
+    ///
+    /// With a correction of `+6`, sample `n` of the disc is sample `n + 6`
+    /// of the rip.
+    pub fn with_offset(mut self, offset: i32) -> Self {
+        self.offset = offset;
+        self
+    }
+    /// Get the sample at an interleaved position of the disc.
+    fn sample(&self, position: i64) -> Option<i16> {
+        let index =
+            position - i64::from(self.first_lsn) * SECTOR_WORDS as i64 + 2 * i64::from(self.offset);
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.samples.get(index).copied())
+    }
+    /// Get a whole sector of the disc, if the rip contains all of it.
+    fn sector(&self, lsn: u32) -> Option<Vec<i16>> {
+        let start = i64::from(lsn) * SECTOR_WORDS as i64;
+        (start..start + SECTOR_WORDS as i64)
+            .map(|position| self.sample(position))
+            .collect()
+    }
+}
+
+/// Build the consensus of the sectors `first_lsn..=last_lsn` of several rips.
+///
+/// A version wins if more than half of all sources agree on it.
+pub fn vote(
+    sources: &[Source],
+    first_lsn: u32,
+    last_lsn: u32,
+    granularity: Granularity,
+) -> Consensus {
+    let mut samples = Vec::new();
+    let mut disputed: Vec<RangeInclusive<u32>> = Vec::new();
+    let mut disputed_samples = 0;
+
+    for lsn in first_lsn..=last_lsn {
+        let decided = match granularity {
+            Granularity::Sector => {
+                let sectors = sources.iter().map(|source| source.sector(lsn));
+                let (sector, majority) = majority(sectors, sources.len());
+                if !majority {
+                    disputed_samples += SECTOR_WORDS;
+                }
+                samples.extend(sector.unwrap_or_else(|| vec![0; SECTOR_WORDS]));
+                majority
+            }
+            Granularity::Sample => {
+                let start = i64::from(lsn) * SECTOR_WORDS as i64;
+                let mut decided = true;
+                for position in start..start + SECTOR_WORDS as i64 {
+                    let values = sources.iter().map(|source| source.sample(position));
+                    let (sample, majority) = majority(values, sources.len());
+                    if !majority {
+                        disputed_samples += 1;
+                        decided = false;
+                    }
+                    samples.push(sample.unwrap_or(0));
+                }
+                decided
+            }
+        };
+        if !decided {
+            match disputed.last_mut() {
+                Some(range) if *range.end() + 1 == lsn => *range = *range.start()..=lsn,
+                _ => disputed.push(lsn..=lsn),
+            }
+        }
+    }
+
+    Consensus {
+        first_lsn,
+        samples,
+        disputed,
+        disputed_samples,
+    }
+}
+
+/// Find the most common value, and whether it is a majority of `sources`.
+///
+/// Missing values don't count as votes.
+fn majority<T: PartialEq>(
+    values: impl IntoIterator<Item = Option<T>>,
+    sources: usize,
+) -> (Option<T>, bool) {
+    let mut votes: Vec<(T, usize)> = Vec::new();
+    for value in values.into_iter().flatten() {
+        match votes.iter_mut().find(|(candidate, _)| *candidate == value) {
+            Some((_, count)) => *count += 1,
+            None => votes.push((value, 1)),
+        }
+    }
+
+    // max_by_key returns the last maximum, so ties go to the first source
+    // by searching from the back
+    let best = votes.into_iter().rev().max_by_key(|(_, count)| *count);
+    match best {
+        Some((value, count)) => (Some(value), 2 * count > sources),
+        None => (None, false),
+    }
+}
+
+impl Consensus {
+    /// Check if all sectors had a majority.
+    pub fn is_decided(&self) -> bool {
+        self.disputed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::samples;
+
+    fn damaged(samples: &[i16], positions: &[usize]) -> Vec<i16> {
+        let mut samples = samples.to_vec();
+        for &position in positions {
+            samples[position] = !samples[position];
+        }
+        samples
+    }
+
+    #[test]
+    fn samples_combine_sources_damaged_in_the_same_sector() {
+        let samples = samples(3);
+        let sources = [
+            Source::new(0, damaged(&samples, &[10])),
+            Source::new(0, damaged(&samples, &[20])),
+            Source::new(0, damaged(&samples, &[30])),
+        ];
+
+        let consensus = vote(&sources, 0, 2, Granularity::Sector);
+        assert_eq!(consensus.disputed, [0..=0]);
+        assert_eq!(consensus.disputed_samples, SECTOR_WORDS);
+        // the first source wins the tie
+        assert_eq!(consensus.samples[10], !samples[10]);
+
+        let consensus = vote(&sources, 0, 2, Granularity::Sample);
+        assert!(consensus.is_decided());
+        assert_eq!(consensus.disputed_samples, 0);
+        assert_eq!(consensus.samples, samples);
+    }
+
+    #[test]
+    fn missing_data_is_not_a_vote() {
+        let samples = samples(4);
+        let sources = [
+            Source::new(0, samples.clone()),
+            Source::new(0, samples[..2 * SECTOR_WORDS].to_vec()),
+            Source::new(1, samples[SECTOR_WORDS..3 * SECTOR_WORDS].to_vec()),
+        ];
+
+        let consensus = vote(&sources, 0, 4, Granularity::Sector);
+        // sector 3 is only in one rip, sector 4 in none
+        assert_eq!(consensus.disputed, [3..=4]);
+        assert_eq!(consensus.disputed_samples, 2 * SECTOR_WORDS);
+        assert_eq!(consensus.samples[..4 * SECTOR_WORDS], samples);
+        assert!(consensus.samples[4 * SECTOR_WORDS..]
+            .iter()
+            .all(|&sample| sample == 0));
+    }
+
+    #[test]
+    fn sources_are_aligned_by_offset() {
+        let samples = samples(3);
+        // a drive with a read offset correction of +6 stereo samples
+        // returns the disc 6 stereo samples late
+        let mut late = vec![0; 12];
+        late.extend(&samples[..samples.len() - 12]);
+        let late = Source::new(0, late).with_offset(6);
+
+        // alone, the shifted rip has everything but the end of the disc
+        let consensus = vote(std::slice::from_ref(&late), 0, 2, Granularity::Sample);
+        assert_eq!(
+            consensus.samples[..samples.len() - 12],
+            samples[..samples.len() - 12]
+        );
+        assert_eq!(consensus.disputed, [2..=2]);
+        assert_eq!(consensus.disputed_samples, 12);
+
+        // the other rips are damaged in different places, so every sample
+        // needs the shifted rip for a majority
+        let damaged_positions: Vec<usize> = (0..samples.len() - 12).step_by(2).collect();
+        let other_positions: Vec<usize> = (1..samples.len() - 12).step_by(2).collect();
+        let sources = [
+            Source::new(0, damaged(&samples, &damaged_positions)),
+            late,
+            Source::read_raw(
+                0,
+                &damaged(&samples, &other_positions)
+                    .iter()
+                    .flat_map(|sample| sample.to_le_bytes())
+                    .collect::<Vec<_>>()[..],
+            )
+            .unwrap(),
+        ];
+
+        let consensus = vote(&sources, 0, 2, Granularity::Sample);
+        assert!(consensus.is_decided());
+        assert_eq!(consensus.samples, samples);
+    }
+}